@@ -0,0 +1,10 @@
+//! Disassembles arbitrary bytes and assembles the disassembly back.
+
+#![no_main]
+
+#[path = "../../tests/fuzzing/mod.rs"]
+mod fuzzing;
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| fuzzing::decode_arbitrary_program(data));
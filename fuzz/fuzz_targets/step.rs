@@ -0,0 +1,10 @@
+//! Runs arbitrary bytes as a program on a platform picked by the first byte.
+
+#![no_main]
+
+#[path = "../../tests/fuzzing/mod.rs"]
+mod fuzzing;
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| fuzzing::run_arbitrary_program(data));
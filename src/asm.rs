@@ -0,0 +1,570 @@
+//! Assembles Chip-8 programs written with the same mnemonics the disassembler shows, like
+//! `LD V3, 0x1F` and `DRW V0, V1, 5`, into the bytes of a program (ROM) that loads at 0x200.
+//!
+//! Besides instructions, the source can have:
+//!
+//! - Comments, from `;` to the end of the line.
+//! - Labels like `loop:`, either on a line of their own or before an instruction. They stand for
+//!   the address of whatever comes after them.
+//! - Constants like `SPEED EQU 4`.
+//! - `DB` and `DW` directives followed by bytes or words separated by commas.
+//! - `INCLUDE "sprites.asm"` to assemble another file in place. The path is relative to the file
+//!   doing the including.
+//!
+//! Anywhere a number goes, it can be written in decimal, in hex with `0x` or in binary with `0b`,
+//! or be a label or constant. Numbers, labels and constants can be added and subtracted, like
+//! `sprites + 5`. Mnemonics and registers don't care about case but labels and constants do.
+
+use crate::{chip8::PROGRAM_START, error::AssemblerError};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// How deep includes can nest before the assembler assumes a file ends up including itself.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Assembles a program from source. Included files are looked up relative to the working
+/// directory.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssemblerError> {
+    let mut lines = Vec::new();
+    read_lines(source, None, 0, &mut lines)?;
+
+    Assembler::new(&lines)?.assemble(&lines)
+}
+
+/// Assembles a program from a source file. Included files are looked up relative to it.
+pub fn assemble_file(path: &Path) -> Result<Vec<u8>, AssemblerError> {
+    let source = fs::read_to_string(path).map_err(|error| AssemblerError {
+        path: Some(path.to_path_buf()),
+        line: 0,
+        message: error.to_string(),
+    })?;
+
+    let mut lines = Vec::new();
+    read_lines(&source, Some(path), 0, &mut lines)?;
+
+    Assembler::new(&lines)?.assemble(&lines)
+}
+
+/// A line of source that does something, along with where it came from for error messages.
+struct SourceLine {
+    path: Option<PathBuf>,
+    number: usize,
+    label: Option<String>,
+    statement: Option<Statement>,
+}
+
+impl SourceLine {
+    fn error(&self, message: impl Into<String>) -> AssemblerError {
+        AssemblerError {
+            path: self.path.clone(),
+            line: self.number,
+            message: message.into(),
+        }
+    }
+}
+
+enum Statement {
+    Constant {
+        name: String,
+        value: String,
+    },
+    Instruction {
+        mnemonic: String,
+        operands: Vec<String>,
+    },
+}
+
+impl Statement {
+    /// Returns how many bytes the statement assembles to. This has to be known before any labels
+    /// can be given addresses, so it only looks at the shape of the statement.
+    fn size(&self) -> usize {
+        match self {
+            Self::Constant { .. } => 0,
+            Self::Instruction { mnemonic, operands } => match mnemonic.as_str() {
+                "DB" => operands.len(),
+                "DW" => operands.len() * 2,
+                _ if operands.len() == 2 && parse_operand(&operands[1]).is_long() => 4,
+                _ => 2,
+            },
+        }
+    }
+}
+
+/// Splits source into lines, reading any included files in place.
+fn read_lines(
+    source: &str,
+    path: Option<&Path>,
+    depth: usize,
+    lines: &mut Vec<SourceLine>,
+) -> Result<(), AssemblerError> {
+    for (index, text) in source.lines().enumerate() {
+        let mut line = SourceLine {
+            path: path.map(Path::to_path_buf),
+            number: index + 1,
+            label: None,
+            statement: None,
+        };
+
+        let mut text = text.split_once(';').map_or(text, |(code, _)| code).trim();
+
+        if let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            if !is_name(label) {
+                return Err(line.error(format!("'{label}' isn't a valid label")));
+            }
+            line.label = Some(label.to_string());
+            text = rest.trim();
+        }
+
+        let (mnemonic, operands) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let operands = operands.trim();
+
+        if mnemonic.eq_ignore_ascii_case("INCLUDE") {
+            let (include_path, include_source) = read_include(operands, &line, path, depth)?;
+            if line.label.is_some() {
+                lines.push(line);
+            }
+            read_lines(&include_source, Some(&include_path), depth + 1, lines)?;
+            continue;
+        }
+
+        if let Some((name, value)) = split_constant(text) {
+            if line.label.is_some() {
+                return Err(line.error("a constant can't have a label"));
+            }
+            if !is_name(name) {
+                return Err(line.error(format!("'{name}' isn't a valid constant name")));
+            }
+            line.statement = Some(Statement::Constant {
+                name: name.to_string(),
+                value: value.to_string(),
+            });
+        } else if !mnemonic.is_empty() {
+            let operands = if operands.is_empty() {
+                Vec::new()
+            } else {
+                operands
+                    .split(',')
+                    .map(|operand| operand.trim().to_string())
+                    .collect()
+            };
+
+            line.statement = Some(Statement::Instruction {
+                mnemonic: mnemonic.to_ascii_uppercase(),
+                operands,
+            });
+        }
+
+        if line.label.is_some() || line.statement.is_some() {
+            lines.push(line);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the file an INCLUDE line points to, returning its path and source.
+fn read_include(
+    operand: &str,
+    line: &SourceLine,
+    including_path: Option<&Path>,
+    depth: usize,
+) -> Result<(PathBuf, String), AssemblerError> {
+    let Some(include_path) = operand
+        .strip_prefix('"')
+        .and_then(|operand| operand.strip_suffix('"'))
+    else {
+        return Err(line.error("INCLUDE needs a path in double quotes"));
+    };
+    if depth >= MAX_INCLUDE_DEPTH {
+        return Err(line.error("includes are nested too deep, does a file include itself?"));
+    }
+
+    let include_path = match including_path.and_then(Path::parent) {
+        Some(folder) => folder.join(include_path),
+        None => PathBuf::from(include_path),
+    };
+    let source = fs::read_to_string(&include_path).map_err(|error| {
+        line.error(format!("can't include {}: {error}", include_path.display()))
+    })?;
+
+    Ok((include_path, source))
+}
+
+/// Splits `NAME EQU value` into the name and the value.
+fn split_constant(text: &str) -> Option<(&str, &str)> {
+    let (name, rest) = text.split_once(char::is_whitespace)?;
+    let (keyword, value) = rest.trim().split_once(char::is_whitespace)?;
+
+    keyword
+        .eq_ignore_ascii_case("EQU")
+        .then_some((name, value.trim()))
+}
+
+/// Labels and constants start with a letter or underscore and only have letters, digits and
+/// underscores after that.
+fn is_name(name: &str) -> bool {
+    let mut characters = name.chars();
+
+    characters
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && characters.all(|character| character.is_ascii_alphanumeric() || character == '_')
+}
+
+#[derive(Clone, Copy)]
+enum Symbol<'a> {
+    Label(u16),
+    Constant(&'a str),
+}
+
+/// Everything an operand can be. Anything that isn't a register or one of the special operands
+/// is a value to work out once every label has an address.
+enum Operand<'a> {
+    V(u16),
+    I,
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    HiresFont,
+    Bcd,
+    Rpl,
+    Long(&'a str),
+    Value(&'a str),
+}
+
+impl Operand<'_> {
+    fn is_long(&self) -> bool {
+        matches!(self, Self::Long(_))
+    }
+}
+
+fn parse_operand(operand: &str) -> Operand<'_> {
+    let upper = operand.to_ascii_uppercase();
+
+    match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::DelayTimer,
+        "ST" => Operand::SoundTimer,
+        "K" => Operand::Key,
+        "F" => Operand::Font,
+        "HF" => Operand::HiresFont,
+        "B" => Operand::Bcd,
+        "R" => Operand::Rpl,
+        _ => {
+            if let Some(register) = upper
+                .strip_prefix('V')
+                .filter(|register| register.len() == 1)
+                .and_then(|register| u16::from_str_radix(register, 16).ok())
+            {
+                Operand::V(register)
+            } else if upper.starts_with("LONG ") {
+                Operand::Long(operand[5..].trim())
+            } else {
+                Operand::Value(operand)
+            }
+        }
+    }
+}
+
+struct Assembler<'a> {
+    symbols: HashMap<&'a str, Symbol<'a>>,
+}
+
+impl<'a> Assembler<'a> {
+    /// Goes through the source once to give every label an address and collect the constants.
+    fn new(lines: &'a [SourceLine]) -> Result<Self, AssemblerError> {
+        let mut symbols = HashMap::new();
+        let mut address = PROGRAM_START;
+
+        for line in lines {
+            let mut define = |name: &'a str, symbol| {
+                if symbols.insert(name, symbol).is_some() {
+                    Err(line.error(format!("'{name}' is defined more than once")))
+                } else {
+                    Ok(())
+                }
+            };
+
+            if let Some(label) = &line.label {
+                define(label, Symbol::Label(address as u16))?;
+            }
+
+            match &line.statement {
+                Some(Statement::Constant { name, value }) => {
+                    define(name, Symbol::Constant(value))?;
+                }
+                Some(statement) => address += statement.size(),
+                None => (),
+            }
+
+            if address > u16::MAX as usize + 1 {
+                return Err(line.error("the program doesn't fit in memory"));
+            }
+        }
+
+        Ok(Self { symbols })
+    }
+
+    /// Goes through the source again, now that every label has an address, to assemble it.
+    fn assemble(&self, lines: &[SourceLine]) -> Result<Vec<u8>, AssemblerError> {
+        let mut program = Vec::new();
+
+        for line in lines {
+            match &line.statement {
+                Some(Statement::Instruction { mnemonic, operands }) => {
+                    let bytes = self
+                        .assemble_instruction(mnemonic, operands)
+                        .map_err(|message| line.error(message))?;
+                    program.extend(bytes);
+                }
+                Some(Statement::Constant { value, .. }) => {
+                    // Catch constants that don't work out even if nothing uses them.
+                    self.evaluate(value, 0)
+                        .map_err(|message| line.error(message))?;
+                }
+                None => (),
+            }
+        }
+
+        Ok(program)
+    }
+
+    fn assemble_instruction(&self, mnemonic: &str, operands: &[String]) -> Result<Vec<u8>, String> {
+        match mnemonic {
+            "DB" => {
+                return operands
+                    .iter()
+                    .map(|operand| self.value(operand, 0xff).map(|byte| byte as u8))
+                    .collect();
+            }
+            "DW" => {
+                let mut bytes = Vec::new();
+                for operand in operands {
+                    bytes.extend(self.value(operand, 0xffff)?.to_be_bytes());
+                }
+                return Ok(bytes);
+            }
+            _ => (),
+        }
+
+        let operands: Vec<Operand> = operands
+            .iter()
+            .map(|operand| parse_operand(operand))
+            .collect();
+        let nibble = |value| self.value(value, 0xf);
+        let byte = |value| self.value(value, 0xff);
+        let address = |value| self.value(value, 0xfff);
+        let xy = |x: &u16, y: &u16| (x << 8) | (y << 4);
+
+        use Operand::*;
+        let opcode = match (mnemonic, operands.as_slice()) {
+            ("SYS", [Value(nnn)]) => address(nnn)?,
+            ("SCD", [Value(n)]) => 0x00c0 | nibble(n)?,
+            ("SCU", [Value(n)]) => 0x00d0 | nibble(n)?,
+            ("CLS", []) => 0x00e0,
+            ("RET", []) => 0x00ee,
+            ("SCR", []) => 0x00fb,
+            ("SCL", []) => 0x00fc,
+            ("EXIT", []) => 0x00fd,
+            ("LOW", []) => 0x00fe,
+            ("HIGH", []) => 0x00ff,
+            ("JP", [Value(nnn)]) => 0x1000 | address(nnn)?,
+            ("CALL", [Value(nnn)]) => 0x2000 | address(nnn)?,
+            ("SE", [V(x), Value(nn)]) => 0x3000 | (x << 8) | byte(nn)?,
+            ("SNE", [V(x), Value(nn)]) => 0x4000 | (x << 8) | byte(nn)?,
+            ("SE", [V(x), V(y)]) => 0x5000 | xy(x, y),
+            ("SAVE", [V(x), V(y)]) => 0x5002 | xy(x, y),
+            ("LOAD", [V(x), V(y)]) => 0x5003 | xy(x, y),
+            ("LD", [V(x), Value(nn)]) => 0x6000 | (x << 8) | byte(nn)?,
+            ("ADD", [V(x), Value(nn)]) => 0x7000 | (x << 8) | byte(nn)?,
+            ("LD", [V(x), V(y)]) => 0x8000 | xy(x, y),
+            ("OR", [V(x), V(y)]) => 0x8001 | xy(x, y),
+            ("AND", [V(x), V(y)]) => 0x8002 | xy(x, y),
+            ("XOR", [V(x), V(y)]) => 0x8003 | xy(x, y),
+            ("ADD", [V(x), V(y)]) => 0x8004 | xy(x, y),
+            ("SUB", [V(x), V(y)]) => 0x8005 | xy(x, y),
+            ("SHR", [V(x), V(y)]) => 0x8006 | xy(x, y),
+            ("SUBN", [V(x), V(y)]) => 0x8007 | xy(x, y),
+            ("SHL", [V(x), V(y)]) => 0x800e | xy(x, y),
+            ("SNE", [V(x), V(y)]) => 0x9000 | xy(x, y),
+            ("LD", [I, Value(nnn)]) => 0xa000 | address(nnn)?,
+            ("JP", [V(0), Value(nnn)]) => 0xb000 | address(nnn)?,
+            ("RND", [V(x), Value(nn)]) => 0xc000 | (x << 8) | byte(nn)?,
+            ("DRW", [V(x), V(y), Value(n)]) => 0xd000 | xy(x, y) | nibble(n)?,
+            ("SKP", [V(x)]) => 0xe09e | (x << 8),
+            ("SKNP", [V(x)]) => 0xe0a1 | (x << 8),
+            ("LD", [I, Long(nnnn)]) => {
+                let mut bytes = vec![0xf0, 0x00];
+                bytes.extend(self.value(nnnn, 0xffff)?.to_be_bytes());
+                return Ok(bytes);
+            }
+            ("PLANE", [Value(n)]) => 0xf001 | (nibble(n)? << 8),
+            ("AUDIO", []) => 0xf002,
+            ("LD", [V(x), DelayTimer]) => 0xf007 | (x << 8),
+            ("LD", [V(x), Key]) => 0xf00a | (x << 8),
+            ("LD", [DelayTimer, V(x)]) => 0xf015 | (x << 8),
+            ("LD", [SoundTimer, V(x)]) => 0xf018 | (x << 8),
+            ("ADD", [I, V(x)]) => 0xf01e | (x << 8),
+            ("LD", [Font, V(x)]) => 0xf029 | (x << 8),
+            ("LD", [HiresFont, V(x)]) => 0xf030 | (x << 8),
+            ("LD", [Bcd, V(x)]) => 0xf033 | (x << 8),
+            ("PITCH", [V(x)]) => 0xf03a | (x << 8),
+            ("LD", [IndirectI, V(x)]) => 0xf055 | (x << 8),
+            ("LD", [V(x), IndirectI]) => 0xf065 | (x << 8),
+            ("LD", [Rpl, V(x)]) => 0xf075 | (x << 8),
+            ("LD", [V(x), Rpl]) => 0xf085 | (x << 8),
+            _ => {
+                return Err(format!(
+                    "'{mnemonic}' with {} operands isn't an instruction",
+                    operands.len()
+                ));
+            }
+        };
+
+        Ok(opcode.to_be_bytes().to_vec())
+    }
+
+    /// Works out a value and checks that it fits in the operand it's for.
+    fn value(&self, expression: &str, max: u16) -> Result<u16, String> {
+        let value = self.evaluate(expression, 0)?;
+
+        u16::try_from(value)
+            .ok()
+            .filter(|value| *value <= max)
+            .ok_or_else(|| format!("{expression} is {value}, which isn't between 0 and {max:#x}"))
+    }
+
+    /// Adds up numbers, labels and constants separated by `+` and `-`. `depth` counts how many
+    /// constants deep the evaluation is, so a constant defined in terms of itself is an error
+    /// instead of a stack overflow.
+    fn evaluate(&self, expression: &str, depth: usize) -> Result<i64, String> {
+        if depth > self.symbols.len() {
+            return Err(format!("'{expression}' is defined in terms of itself"));
+        }
+
+        let expression = expression.trim();
+        let (mut sign, rest) = match expression.strip_prefix('-') {
+            Some(rest) => (-1, rest),
+            None => (1, expression),
+        };
+        let mut total: i64 = 0;
+        let mut term = String::new();
+
+        // A trailing + ends the last term so it's added up like the others.
+        for character in rest.chars().chain(['+']) {
+            if character == '+' || character == '-' {
+                let finished_term = std::mem::take(&mut term);
+                if finished_term.trim().is_empty() {
+                    return Err(format!("'{expression}' is missing a number"));
+                }
+
+                total += sign * self.term(finished_term.trim(), depth)?;
+                sign = if character == '-' { -1 } else { 1 };
+            } else {
+                term.push(character);
+            }
+        }
+
+        Ok(total)
+    }
+
+    fn term(&self, term: &str, depth: usize) -> Result<i64, String> {
+        let lower = term.to_ascii_lowercase();
+        let number = if let Some(hex) = lower.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16).ok()
+        } else if let Some(binary) = lower.strip_prefix("0b") {
+            i64::from_str_radix(binary, 2).ok()
+        } else if lower.starts_with(|character: char| character.is_ascii_digit()) {
+            lower.parse().ok()
+        } else {
+            match self.symbols.get(term) {
+                Some(Symbol::Label(address)) => return Ok(*address as i64),
+                Some(Symbol::Constant(value)) => return self.evaluate(value, depth + 1),
+                None => return Err(format!("'{term}' isn't a label or constant")),
+            }
+        };
+
+        number.ok_or_else(|| format!("'{term}' isn't a number"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn labels_can_be_used_before_they_are_defined() {
+        let source = "\
+start:  CALL draw       ; 0x200
+        JP start
+draw:   LD I, sprite    ; 0x204
+        DRW V0, V1, 2
+        RET
+sprite: DB 0b11000000, 0xc0";
+
+        assert_eq!(
+            assemble(source).unwrap(),
+            [
+                0x22, 0x04, 0x12, 0x00, 0xa2, 0x0a, 0xd0, 0x12, 0x00, 0xee, 0xc0, 0xc0
+            ]
+        );
+    }
+
+    #[test]
+    fn constants_and_arithmetic() {
+        let source = "\
+HEIGHT EQU WIDTH - 32
+WIDTH EQU 64
+        LD V0, HEIGHT + 1
+        LD I, LONG table - 2
+table:  DW 0x1234, WIDTH";
+
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x60, 0x21, 0xf0, 0x00, 0x02, 0x04, 0x12, 0x34, 0x00, 0x40]
+        );
+    }
+
+    #[test]
+    fn includes_are_relative_to_the_including_file() {
+        let folder = env::temp_dir().join(format!("chip8-asm-test-{}", std::process::id()));
+        fs::create_dir_all(folder.join("lib")).unwrap();
+        fs::write(
+            folder.join("main.asm"),
+            "CALL clear\nJP 0x200\nINCLUDE \"lib/clear.asm\"",
+        )
+        .unwrap();
+        fs::write(folder.join("lib/clear.asm"), "clear: CLS\nRET").unwrap();
+
+        let program = assemble_file(&folder.join("main.asm"));
+        fs::remove_dir_all(&folder).unwrap();
+
+        assert_eq!(
+            program.unwrap(),
+            [0x22, 0x04, 0x12, 0x00, 0x00, 0xe0, 0x00, 0xee]
+        );
+    }
+
+    #[test]
+    fn errors_report_the_line() {
+        let error = assemble("CLS\n\nJP nowhere").unwrap_err();
+        assert_eq!(error.line, 3);
+        assert_eq!(
+            error.to_string(),
+            "line 3: 'nowhere' isn't a label or constant"
+        );
+
+        assert_eq!(assemble("LD V0, 256").unwrap_err().line, 1);
+        assert_eq!(assemble("DRW V0, 5").unwrap_err().line, 1);
+        assert_eq!(assemble("a: CLS\na: CLS").unwrap_err().line, 2);
+        assert_eq!(assemble("A EQU B\nB EQU A").unwrap_err().line, 1);
+    }
+}
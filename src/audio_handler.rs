@@ -10,6 +10,10 @@ pub struct Buzzer {
     phase_increment: f32, // Essentially what tone (in Hz) the generated waveform will play at
     phase: f32,
     volume: f32, // The max intensity (amplitude) the generated wave will reach
+    sample_rate: f32,
+    pattern: Option<[u8; 16]>, // XO-CHIP audio pattern played instead of the tone when loaded
+    pattern_increment: f32,    // How many pattern bits to advance per sample
+    pattern_position: f32,     // Which of the 128 pattern bits is being played
 }
 
 impl Buzzer {
@@ -33,16 +37,42 @@ impl Buzzer {
                     phase_increment: settings.tone / (spec.freq as f32),
                     phase: 0.0,
                     volume: settings.volume / 20.0,
+                    sample_rate: spec.freq as f32,
+                    pattern: None,
+                    pattern_increment: 0.0,
+                    pattern_position: 0.0,
                 }
             })
             .map_err(anyhow::Error::msg)
     }
+
+    /// Sets the XO-CHIP audio pattern to play and how many bits of it to play per second. With no
+    /// pattern, the buzzer goes back to playing its regular tone.
+    pub fn set_pattern(&mut self, pattern: Option<[u8; 16]>, playback_rate: f32) {
+        self.pattern = pattern;
+        self.pattern_increment = playback_rate / self.sample_rate;
+    }
 }
 
 impl AudioCallback for Buzzer {
     type Channel = f32;
 
     fn callback(&mut self, out: &mut [f32]) {
+        // Play the XO-CHIP audio pattern as a 1-bit waveform, looping back to the start after the
+        // last of the 128 bits.
+        if let Some(pattern) = self.pattern {
+            for x in out.iter_mut() {
+                let bit = self.pattern_position as usize;
+                *x = if (pattern[bit / 8] >> (7 - bit % 8)) & 1 == 1 {
+                    self.volume
+                } else {
+                    -self.volume
+                };
+                self.pattern_position = (self.pattern_position + self.pattern_increment) % 128.0;
+            }
+            return;
+        }
+
         // Generate a square wave for that "cheap motherboard speaker" kind of sound
         for x in out.iter_mut() {
             *x = if self.phase <= 0.5 {
@@ -0,0 +1,361 @@
+//! Runs a Chip-8 program with no window, audio or input and reports the final state of the VM.
+//! Made for checking that programs behave in CI and for running batches of programs.
+//!
+//! The registers and the frame buffer (as text) are printed to stdout as JSON. The frame buffer
+//! can also be saved as a text file or a PNG image.
+//!
+//! `chip8-headless disasm <rom>` prints a disassembly listing of a program instead of running it,
+//! `chip8-headless asm <source>` assembles a program and `chip8-headless diff <rom> <reference>`
+//! runs a program in lockstep with a reference trace from another interpreter (see the difftest
+//! module docs for its format).
+
+use chip_8_interpreter::{
+    Chip8Settings, Platform, Tracer, VirtualMachine, asm, difftest, disasm, rom,
+};
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+use std::{
+    fs,
+    io::{BufRead, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+/// The same colors a freshly created settings.toml uses for each frame buffer pixel value.
+const PALETTE: [[u8; 3]; 4] = [[0, 0, 0], [255, 255, 255], [85, 85, 85], [170, 170, 170]];
+
+/// Number of frames to run for when neither a cycle nor a frame count is given (one second).
+const DEFAULT_FRAMES: u64 = 60;
+
+#[derive(Parser)]
+#[command(
+    name = "chip8-headless",
+    about = "Runs a Chip-8 program without a window and reports the final state of the VM as JSON.",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    run: Arguments,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Prints a disassembly listing of a program with the address of each instruction. Bytes that
+    /// can't be reached from 0x200 are listed as data.
+    Disasm {
+        /// Path to the program (ROM) to disassemble.
+        rom: PathBuf,
+        /// Print source that `asm` assembles back into the same program instead of a listing.
+        #[arg(long)]
+        source: bool,
+    },
+    /// Assembles a program from source written with the disassembler's mnemonics.
+    Asm {
+        /// Path to the source to assemble.
+        source: PathBuf,
+        /// Where to save the program. Defaults to the source's path with a .ch8 extension.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Runs a program in lockstep with a reference trace (JSON Lines) from another interpreter and
+    /// reports the first instruction after which they disagree.
+    Diff {
+        /// Path to the program (ROM) to run.
+        rom: PathBuf,
+        /// Path to the reference trace.
+        reference: PathBuf,
+        #[command(flatten)]
+        machine: Machine,
+    },
+}
+
+#[derive(Args)]
+struct Arguments {
+    /// Path to the program (ROM) to run.
+    #[arg(required = true)]
+    rom: Option<PathBuf>,
+    #[command(flatten)]
+    machine: Machine,
+    /// Stop after executing this many instructions.
+    #[arg(long, conflicts_with = "frames")]
+    cycles: Option<u64>,
+    /// Stop after this many 60 Hz frames. Defaults to 60 (one second).
+    #[arg(long)]
+    frames: Option<u64>,
+    /// Also save the final frame buffer as text to this file.
+    #[arg(long)]
+    text: Option<PathBuf>,
+    /// Also save the final frame buffer as a PNG image to this file.
+    #[arg(long)]
+    png: Option<PathBuf>,
+    /// Write a line to this file for every executed instruction, see the trace module docs.
+    #[arg(long)]
+    trace: Option<PathBuf>,
+    /// Only trace from this cycle on.
+    #[arg(long, requires = "trace", default_value_t = 0)]
+    trace_from: u64,
+    /// Stop tracing before this cycle.
+    #[arg(long, requires = "trace")]
+    trace_to: Option<u64>,
+}
+
+/// How the VM behaves, shared by running and diffing.
+#[derive(Args)]
+struct Machine {
+    /// Platform the program was written for. Sets all the quirks at once.
+    #[arg(long, default_value_t = Platform::CosmacVip)]
+    platform: Platform,
+    /// 8XY6/8XYE shift Vx in place instead of shifting Vy into Vx.
+    #[arg(long)]
+    shift_quirk: Option<bool>,
+    /// 8XY1/8XY2/8XY3 reset VF to 0.
+    #[arg(long)]
+    or_and_xor_quirk: Option<bool>,
+    /// FX55/FX65 leave I just past the last register stored or loaded.
+    #[arg(long)]
+    mem_quirk: Option<bool>,
+    /// FX55/FX65 leave I on the last register instead of just past it (CHIP-48).
+    #[arg(long)]
+    mem_increment_by_x_quirk: Option<bool>,
+    /// DXYN wraps the starting coordinates of sprites that start off screen.
+    #[arg(long)]
+    sprite_wrapping_quirk: Option<bool>,
+    /// BNNN jumps to XNN + Vx instead of NNN + V0.
+    #[arg(long)]
+    jump_offset_quirk: Option<bool>,
+    /// DXYN draws and then waits for the display to refresh.
+    #[arg(long)]
+    display_wait_quirk: Option<bool>,
+    /// DXYN waits for the display in high resolution too (off for SUPER-CHIP 1.1).
+    #[arg(long)]
+    hires_display_wait_quirk: Option<bool>,
+    /// DXYN cuts sprites off at the edges of the screen instead of wrapping them around.
+    #[arg(long)]
+    clipping_quirk: Option<bool>,
+    /// Seed for the random numbers programs get from CXNN.
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Multiplied by how long each operation takes. 0 runs as fast as possible, which never ends
+    /// a frame, so it needs --cycles. So do speeds below 0.04, where some operations take no time.
+    #[arg(long, default_value_t = 1.0)]
+    speed: f32,
+}
+
+impl Machine {
+    fn settings(&self) -> Chip8Settings {
+        Chip8Settings {
+            platform: self.platform,
+            shift_quirk: self.shift_quirk,
+            or_and_xor_quirk: self.or_and_xor_quirk,
+            mem_quirk: self.mem_quirk,
+            mem_increment_by_x_quirk: self.mem_increment_by_x_quirk,
+            sprite_wrapping_quirk: self.sprite_wrapping_quirk,
+            jump_offset_quirk: self.jump_offset_quirk,
+            display_wait_quirk: self.display_wait_quirk,
+            hires_display_wait_quirk: self.hires_display_wait_quirk,
+            clipping_quirk: self.clipping_quirk,
+            execution_speed_multiple: self.speed,
+            rng_seed: self.seed,
+            ..Chip8Settings::default()
+        }
+    }
+}
+
+/// The final state of the VM, printed as JSON.
+#[derive(Serialize)]
+struct Report {
+    cycles: u64,
+    frames: u64,
+    pc: u16,
+    i: u16,
+    v: [u8; 16],
+    stack: Vec<u16>,
+    delay_timer: u8,
+    sound_timer: u8,
+    exited: bool,
+    fault: Option<String>,
+    resolution: (usize, usize),
+    framebuffer: Vec<String>,
+}
+
+fn main() -> anyhow::Result<ExitCode> {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Some(Command::Disasm { rom, source }) => {
+            let program = rom::read_program(&rom)?;
+            if source {
+                println!("{}", disasm::source(&program));
+            } else {
+                println!("{}", disasm::listing(&program));
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Asm { source, output }) => {
+            let program = asm::assemble_file(&source)?;
+            let output = output.unwrap_or_else(|| source.with_extension("ch8"));
+            fs::write(&output, &program)?;
+            println!(
+                "Assembled {} bytes into {}.",
+                program.len(),
+                output.display()
+            );
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Diff {
+            rom,
+            reference,
+            machine,
+        }) => diff(&rom, &reference, &machine),
+        None => run(cli.run),
+    }
+}
+
+/// Runs the program against the reference trace and prints the first divergence.
+fn diff(rom: &Path, reference: &Path, machine: &Machine) -> anyhow::Result<ExitCode> {
+    let mut states = Vec::new();
+    for (number, line) in BufReader::new(fs::File::open(reference)?)
+        .lines()
+        .enumerate()
+    {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let state = serde_json::from_str(&line).map_err(|error| {
+            anyhow::Error::msg(format!("{}:{}: {error}", reference.display(), number + 1))
+        })?;
+        states.push(state);
+    }
+
+    let cycles = states.len();
+    let mut vm = VirtualMachine::initialize(&machine.settings(), rom)?;
+    match difftest::run_lockstep(&mut vm, states) {
+        Some(divergence) => {
+            println!("{divergence}");
+            Ok(ExitCode::FAILURE)
+        }
+        None => {
+            println!("No divergence in {cycles} cycles.");
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+/// Runs the program and prints the report.
+fn run(arguments: Arguments) -> anyhow::Result<ExitCode> {
+    // clap requires the ROM unless a subcommand is given.
+    let rom = arguments
+        .rom
+        .clone()
+        .expect("the ROM is a required argument");
+
+    let settings = arguments.machine.settings();
+    if !settings.every_operation_takes_time() && arguments.cycles.is_none() {
+        return Err(anyhow::Error::msg(format!(
+            "--speed {} makes some operations take no time, so a frame might never finish and \
+             --cycles is required.",
+            arguments.machine.speed
+        )));
+    }
+
+    let mut vm = VirtualMachine::initialize(&settings, &rom)?;
+    if let Some(trace_path) = &arguments.trace {
+        let trace_to = arguments.trace_to.unwrap_or(u64::MAX);
+        vm.set_tracer(Some(
+            Tracer::create(trace_path)?.with_cycles(arguments.trace_from..trace_to),
+        ));
+    }
+
+    // Run until whichever limit was asked for is reached, or until the program can't go on.
+    let mut cycles: u64 = 0;
+    let mut frames: u64 = 0;
+    let frame_limit = arguments.frames.unwrap_or(DEFAULT_FRAMES);
+
+    loop {
+        let limit_reached = match arguments.cycles {
+            Some(cycle_limit) => cycles >= cycle_limit,
+            None => frames >= frame_limit,
+        };
+        if limit_reached || vm.exited() {
+            break;
+        }
+
+        match vm.step() {
+            Ok(Some(_)) => cycles += 1,
+            // Out of frame time budget, so move on to the next frame just like the interpreter.
+            Ok(None) => {
+                vm.tick_timers();
+                frames += 1;
+            }
+            Err(_) => break,
+        }
+    }
+
+    if let Some(tracer) = vm.tracer_mut() {
+        tracer.flush()?;
+        if let Some(error) = tracer.error() {
+            return Err(anyhow::Error::msg(format!(
+                "writing the trace failed: {error}"
+            )));
+        }
+    }
+
+    // Save the frame buffer wherever it was asked for.
+    let framebuffer_text = vm.framebuffer_text();
+
+    if let Some(text_path) = &arguments.text {
+        fs::write(text_path, format!("{framebuffer_text}\n"))?;
+    }
+
+    if let Some(png_path) = &arguments.png {
+        write_png(&vm, png_path)?;
+    }
+
+    let report = Report {
+        cycles,
+        frames,
+        pc: vm.program_counter(),
+        i: vm.index_register(),
+        v: vm.registers(),
+        stack: vm.stack().to_vec(),
+        delay_timer: vm.delay_timer(),
+        sound_timer: vm.sound_timer,
+        exited: vm.exited(),
+        fault: vm.fault().map(|fault| fault.to_string()),
+        resolution: vm.resolution(),
+        framebuffer: framebuffer_text.lines().map(String::from).collect(),
+    };
+
+    println!("{}", serde_json::to_string_pretty(&report)?);
+
+    // A program that faulted didn't behave, so let CI know.
+    if vm.fault().is_some() {
+        Ok(ExitCode::FAILURE)
+    } else {
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+/// Saves the frame buffer as an RGB PNG image with one image pixel per Chip-8 pixel.
+fn write_png(vm: &VirtualMachine, png_path: &Path) -> anyhow::Result<()> {
+    let (width, height) = vm.resolution();
+    let image_data: Vec<u8> = vm
+        .framebuffer()
+        .iter()
+        .flat_map(|pixel| PALETTE[(pixel & 0b11) as usize])
+        .collect();
+
+    let file = BufWriter::new(fs::File::create(png_path)?);
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image_data)?;
+
+    Ok(())
+}
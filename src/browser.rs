@@ -0,0 +1,595 @@
+use chip_8_interpreter::{
+    Chip8Settings, Platform, ProgramDatabase,
+    rom::{
+        detect_platform, extension_platform, is_archive, is_program, list_archive, read_program,
+        rom_hash,
+    },
+};
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// How many recently played programs are remembered.
+const RECENT_PROGRAMS: usize = 10;
+
+/// Lines the browser uses for things other than the list: the title, the filter, the two rules,
+/// the key help and the message.
+const NON_LIST_LINES: u16 = 6;
+
+const HELP: &str = "Up/Down select  Enter run  Tab tweak and run  F2 favourite  Left back  Esc quit  Type to filter";
+
+/// What the user picked in the browser.
+pub(crate) struct Choice {
+    pub path: PathBuf,
+    /// Whether the user wants to tweak the program's settings before it runs.
+    pub tweak: bool,
+}
+
+/// What the browser is showing the contents of.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum View {
+    Folder,
+    Favourites,
+    Recent,
+}
+
+/// A line in the browser's list.
+enum Entry {
+    /// Goes back to the folder above, or out of the favourites or recent list.
+    Back,
+    /// A folder or a zip archive, which is opened like a folder.
+    Folder(PathBuf),
+    Favourites,
+    Recent,
+    Program(PathBuf),
+}
+
+/// What's shown next to a program in the list.
+struct ProgramInfo {
+    size: u64,
+    /// The database's title and authors, if it knows the program.
+    description: Option<String>,
+    /// What the database says the program runs on, or what its extension or the instructions it
+    /// uses say. None if the program can't be read.
+    platform: Option<Platform>,
+}
+
+/// The recently played and favourite programs, saved to a library.toml file next to the
+/// programs folder. Paths that aren't UTF-8 can't be saved, so they're only kept until the
+/// interpreter exits.
+#[derive(Default, Deserialize, Serialize)]
+struct Library {
+    #[serde(default)]
+    recent: Vec<PathBuf>,
+    #[serde(default)]
+    favourites: Vec<PathBuf>,
+}
+
+impl Library {
+    fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|library| toml::from_str(&library).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let saveable = |paths: &[PathBuf]| -> Vec<PathBuf> {
+            paths
+                .iter()
+                .filter(|path| path.to_str().is_some())
+                .cloned()
+                .collect()
+        };
+        let library = Self {
+            recent: saveable(&self.recent),
+            favourites: saveable(&self.favourites),
+        };
+
+        fs::write(path, toml::to_string(&library)?)?;
+
+        Ok(())
+    }
+
+    fn played(&mut self, program: &Path) {
+        self.recent.retain(|recent| recent != program);
+        self.recent.insert(0, program.to_path_buf());
+        self.recent.truncate(RECENT_PROGRAMS);
+    }
+
+    fn toggle_favourite(&mut self, program: &Path) {
+        if self.favourites.iter().any(|favourite| favourite == program) {
+            self.favourites.retain(|favourite| favourite != program);
+        } else {
+            self.favourites.push(program.to_path_buf());
+        }
+    }
+}
+
+/// Puts the terminal in raw mode on its own screen while it's alive, and puts it back afterwards
+/// no matter how the browser stopped.
+struct BrowserScreen;
+
+impl BrowserScreen {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, cursor::Hide)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for BrowserScreen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), LeaveAlternateScreen, cursor::Show);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// A terminal browser for the programs in the programs folder and its subfolders, with a filter
+/// that searches every folder below the current one as it's typed in. Programs the database knows
+/// are shown by title and author, and every program has its size and platform next to it.
+pub(crate) struct Browser<'a> {
+    root: PathBuf,
+    folder: PathBuf,
+    view: View,
+    filter: String,
+    entries: Vec<Entry>,
+    selected: usize,
+    /// The first entry shown, for lists longer than the terminal.
+    scroll: usize,
+    programs: HashMap<PathBuf, ProgramInfo>,
+    database: &'a ProgramDatabase,
+    library: Library,
+    library_path: PathBuf,
+    message: String,
+}
+
+impl<'a> Browser<'a> {
+    /// Creates a browser for the programs folder from the settings, creating the folder if it
+    /// doesn't exist yet.
+    pub fn new(settings: &Chip8Settings, database: &'a ProgramDatabase) -> anyhow::Result<Self> {
+        let root = PathBuf::from(&settings.program_folder_path);
+        if !fs::exists(&root)? {
+            fs::create_dir_all(&root)?;
+        }
+
+        let library_path = root.with_file_name("library.toml");
+
+        Ok(Self {
+            folder: root.clone(),
+            root,
+            view: View::Folder,
+            filter: String::new(),
+            entries: Vec::new(),
+            selected: 0,
+            scroll: 0,
+            programs: HashMap::new(),
+            database,
+            library: Library::load(&library_path),
+            library_path,
+            message: String::new(),
+        })
+    }
+
+    /// Shows a message under the list the next time the browser is shown, like why the last
+    /// program couldn't run.
+    pub fn set_message(&mut self, message: String) {
+        self.message = message;
+    }
+
+    /// Shows the browser until a program is picked and adds it to the recently played programs.
+    /// Returns None if the user quit.
+    pub fn ask(&mut self) -> anyhow::Result<Option<Choice>> {
+        let choice = {
+            let _screen = BrowserScreen::enter()?;
+            self.refresh();
+            self.run()?
+        };
+
+        if let Some(choice) = &choice {
+            self.library.played(&choice.path);
+            self.save_library();
+        }
+
+        Ok(choice)
+    }
+
+    fn run(&mut self) -> anyhow::Result<Option<Choice>> {
+        loop {
+            self.draw()?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind == KeyEventKind::Release {
+                continue;
+            }
+            self.message.clear();
+
+            if let Some(result) = self.handle_key(key) {
+                return Ok(result);
+            }
+        }
+    }
+
+    /// Does whatever a key does. Returns Some once the browser is done, with the program that
+    /// was picked or None if the user quit.
+    fn handle_key(&mut self, key: KeyEvent) -> Option<Option<Choice>> {
+        let page = terminal::size().map_or(10, |(_, rows)| rows.saturating_sub(NON_LIST_LINES));
+        let last = self.entries.len().saturating_sub(1);
+
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Some(None);
+            }
+            KeyCode::Esc if self.filter.is_empty() => return Some(None),
+            KeyCode::Esc => {
+                self.filter.clear();
+                self.refresh();
+            }
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => self.selected = (self.selected + 1).min(last),
+            KeyCode::PageUp => self.selected = self.selected.saturating_sub(page as usize),
+            KeyCode::PageDown => self.selected = (self.selected + page as usize).min(last),
+            KeyCode::Home => self.selected = 0,
+            KeyCode::End => self.selected = last,
+            KeyCode::Enter | KeyCode::Right => return self.open(false),
+            KeyCode::Tab => return self.open(true),
+            KeyCode::Left => self.back(),
+            KeyCode::Backspace if self.filter.is_empty() => self.back(),
+            KeyCode::Backspace => {
+                self.filter.pop();
+                self.refresh();
+            }
+            KeyCode::F(2) => {
+                if let Some(Entry::Program(program)) = self.entries.get(self.selected) {
+                    let program = program.clone();
+                    self.library.toggle_favourite(&program);
+                    self.save_library();
+                    if self.view == View::Favourites {
+                        let selected = self.selected;
+                        self.refresh();
+                        self.selected = selected.min(self.entries.len().saturating_sub(1));
+                    }
+                }
+            }
+            KeyCode::Char(character) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.filter.push(character);
+                self.refresh();
+            }
+            _ => (),
+        }
+
+        None
+    }
+
+    /// Opens the selected folder or list, or picks the selected program.
+    fn open(&mut self, tweak: bool) -> Option<Option<Choice>> {
+        match self.entries.get(self.selected)? {
+            Entry::Back => self.back(),
+            Entry::Folder(folder) => {
+                self.folder = folder.clone();
+                self.view = View::Folder;
+                self.filter.clear();
+                self.refresh();
+            }
+            Entry::Favourites => self.show_list(View::Favourites),
+            Entry::Recent => self.show_list(View::Recent),
+            Entry::Program(program) => {
+                return Some(Some(Choice {
+                    path: program.clone(),
+                    tweak,
+                }));
+            }
+        }
+
+        None
+    }
+
+    fn show_list(&mut self, view: View) {
+        self.view = view;
+        self.filter.clear();
+        self.refresh();
+    }
+
+    /// Goes back out of the favourites or recent list, or up to the folder above with the folder
+    /// that was left selected.
+    fn back(&mut self) {
+        let left = self.folder.clone();
+
+        if self.view != View::Folder {
+            self.view = View::Folder;
+        } else if self.folder != self.root
+            && let Some(parent) = self.folder.parent()
+        {
+            self.folder = parent.to_path_buf();
+        } else {
+            return;
+        }
+
+        self.filter.clear();
+        self.refresh();
+
+        if let Some(index) = self
+            .entries
+            .iter()
+            .position(|entry| matches!(entry, Entry::Folder(folder) if *folder == left))
+        {
+            self.selected = index;
+        }
+    }
+
+    fn save_library(&mut self) {
+        if let Err(error) = self.library.save(&self.library_path) {
+            self.message = format!("Couldn't save {}: {error}", self.library_path.display());
+        }
+    }
+
+    /// Lists what's in the current folder or list again, keeping only what matches the filter, and
+    /// selects the first entry.
+    fn refresh(&mut self) {
+        let mut entries = Vec::new();
+        let at_root = self.view == View::Folder && self.folder == self.root;
+        if !at_root {
+            entries.push(Entry::Back);
+        }
+
+        let programs = match self.view {
+            View::Favourites => self.library.favourites.clone(),
+            View::Recent => self.library.recent.clone(),
+            View::Folder if self.filter.is_empty() => {
+                if at_root && !self.library.favourites.is_empty() {
+                    entries.push(Entry::Favourites);
+                }
+                if at_root && !self.library.recent.is_empty() {
+                    entries.push(Entry::Recent);
+                }
+
+                let (folders, programs) = list_folder(&self.folder);
+                entries.extend(folders.into_iter().map(Entry::Folder));
+                programs
+            }
+            // Filtering searches every folder below this one.
+            View::Folder => {
+                let mut programs = Vec::new();
+                find_programs(&self.folder, &mut programs);
+                programs
+            }
+        };
+
+        let filter = self.filter.to_lowercase();
+        let mut programs: Vec<(String, PathBuf)> = programs
+            .into_iter()
+            .filter(|program| program_exists(program))
+            .map(|program| (self.program_name(&program), program))
+            .filter(|(name, _)| name.to_lowercase().contains(&filter))
+            .collect();
+
+        // Lists are kept in the order they were played or added in.
+        if self.view == View::Folder {
+            programs.sort_by_key(|(name, _)| name.to_lowercase());
+        }
+
+        entries.extend(
+            programs
+                .into_iter()
+                .map(|(_, program)| Entry::Program(program)),
+        );
+
+        self.entries = entries;
+        self.selected = 0;
+    }
+
+    /// The name a program is listed by: its title and authors if the database knows it, and its
+    /// path from the current folder otherwise. The path is there either way when it's in another
+    /// folder, which happens when filtering or in the favourites and recent lists.
+    fn program_name(&mut self, program: &Path) -> String {
+        let path = program
+            .strip_prefix(&self.folder)
+            .unwrap_or(program)
+            .to_string_lossy()
+            .into_owned();
+
+        let in_folder = program.parent() == Some(self.folder.as_path());
+
+        match &self.info(program).description {
+            Some(description) if in_folder => description.clone(),
+            Some(description) => format!("{description} ({path})"),
+            None => path,
+        }
+    }
+
+    /// Reads a program to find out what to show next to it. Remembered so every program is only
+    /// read once.
+    fn info(&mut self, program: &Path) -> &ProgramInfo {
+        self.programs
+            .entry(program.to_path_buf())
+            .or_insert_with(|| {
+                let data = read_program(program);
+                // Programs in archives have no file of their own, so their size is how big they
+                // are unpacked.
+                let size = fs::metadata(program).map_or_else(
+                    |_| data.as_ref().map_or(0, |data| data.len() as u64),
+                    |metadata| metadata.len(),
+                );
+                let Ok(data) = data else {
+                    return ProgramInfo {
+                        size,
+                        description: None,
+                        platform: None,
+                    };
+                };
+
+                let entry = self.database.get(&rom_hash(&data));
+                ProgramInfo {
+                    size,
+                    description: entry.map(|entry| entry.description()),
+                    platform: entry
+                        .and_then(|entry| entry.platform)
+                        .or_else(|| extension_platform(program))
+                        .or_else(|| Some(detect_platform(&data))),
+                }
+            })
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        let (columns, rows) = terminal::size()?;
+        let columns = columns as usize;
+        let list_rows = rows.saturating_sub(NON_LIST_LINES).max(1) as usize;
+
+        // Scroll just enough to keep the selection on screen.
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + list_rows {
+            self.scroll = self.selected + 1 - list_rows;
+        }
+
+        let location = match self.view {
+            View::Folder => self.folder.to_string_lossy().into_owned(),
+            View::Favourites => String::from("Favourites"),
+            View::Recent => String::from("Recently played"),
+        };
+        let rule = "-".repeat(columns.min(80));
+
+        let mut stdout = io::stdout();
+        queue!(
+            stdout,
+            terminal::Clear(ClearType::All),
+            cursor::MoveTo(0, 0),
+            Print(fit(&format!("Chip-8 programs in {location}"), columns)),
+            cursor::MoveToNextLine(1),
+            Print(fit(&format!("Filter: {}_", self.filter), columns)),
+            cursor::MoveToNextLine(1),
+            Print(&rule),
+        )?;
+
+        let lines: Vec<(bool, String)> = (self.scroll..self.entries.len())
+            .take(list_rows)
+            .map(|index| (index == self.selected, self.entry_line(index, columns)))
+            .collect();
+        for (selected, line) in lines {
+            queue!(stdout, cursor::MoveToNextLine(1))?;
+            if selected {
+                queue!(stdout, SetAttribute(Attribute::Reverse))?;
+            }
+            queue!(stdout, Print(line), SetAttribute(Attribute::Reset))?;
+        }
+
+        queue!(
+            stdout,
+            cursor::MoveTo(0, rows.saturating_sub(3)),
+            Print(&rule),
+            cursor::MoveToNextLine(1),
+            Print(fit(HELP, columns)),
+            cursor::MoveToNextLine(1),
+            Print(fit(&self.message, columns)),
+        )?;
+
+        stdout.flush()
+    }
+
+    fn entry_line(&mut self, index: usize, columns: usize) -> String {
+        let (name, details) = match &self.entries[index] {
+            Entry::Back => (String::from(".."), String::new()),
+            Entry::Folder(folder) => {
+                let name = folder.file_name().unwrap_or_default().to_string_lossy();
+                (format!("{name}/"), String::new())
+            }
+            Entry::Favourites => (
+                String::from("* Favourites"),
+                format!("{} programs", self.library.favourites.len()),
+            ),
+            Entry::Recent => (
+                String::from("Recently played"),
+                format!("{} programs", self.library.recent.len()),
+            ),
+            Entry::Program(program) => {
+                let program = program.clone();
+                let favourite = if self.library.favourites.contains(&program) {
+                    "* "
+                } else {
+                    "  "
+                };
+                let name = format!("{favourite}{}", self.program_name(&program));
+                let info = self.info(&program);
+                let platform = info.platform.map_or("unreadable", Platform::name);
+                (
+                    name,
+                    format!("{:>9}  {platform:<12}", format_size(info.size)),
+                )
+            }
+        };
+
+        let name_width = columns.saturating_sub(details.len() + 3);
+        format!(" {:<name_width$} {details}", fit(&name, name_width))
+    }
+}
+
+/// Returns the folders (and archives) and programs directly in a folder, or every program in an
+/// archive. Anything that can't be read is left out rather than stopping the browser.
+fn list_folder(folder: &Path) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    if is_archive(folder) && folder.is_file() {
+        return (Vec::new(), list_archive(folder).unwrap_or_default());
+    }
+
+    let mut folders = Vec::new();
+    let mut programs = Vec::new();
+
+    for path in fs::read_dir(folder)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|dir_entry| dir_entry.path())
+    {
+        if path.is_dir() || is_archive(&path) {
+            folders.push(path);
+        } else if is_program(&path) {
+            programs.push(path);
+        }
+    }
+
+    folders.sort();
+    (folders, programs)
+}
+
+/// Adds every program in a folder and all the folders below it.
+fn find_programs(folder: &Path, programs: &mut Vec<PathBuf>) {
+    let (folders, folder_programs) = list_folder(folder);
+    programs.extend(folder_programs);
+    for folder in folders {
+        find_programs(&folder, programs);
+    }
+}
+
+/// Whether a program is still there, in its folder or its archive.
+fn program_exists(program: &Path) -> bool {
+    program.exists()
+        || program
+            .ancestors()
+            .skip(1)
+            .any(|ancestor| is_archive(ancestor) && ancestor.is_file())
+}
+
+fn format_size(bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{bytes} B")
+    } else {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    }
+}
+
+/// Cuts text off at a number of characters so it doesn't wrap onto the next line.
+fn fit(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
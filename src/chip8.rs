@@ -1,13 +1,51 @@
-use crate::{configuration::Chip8Settings, input_handler::KEYUP_RELEASE_DURATION};
-use rand::random;
-use std::{
-    fs,
-    path::Path,
-    time::{Duration, Instant},
+use crate::{
+    Chip8Error, disasm,
+    quirks::{Platform, Quirks},
+    rom,
+    trace::{TraceEntry, Tracer},
 };
+use rand::{Rng, SeedableRng, random};
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, time::Duration};
+
+mod state;
+mod watchpoints;
+
+pub use state::{SAVE_STATE_VERSION, SaveState};
+pub use watchpoints::{MemoryAccess, Watchpoint, WatchpointHit};
 
 /// At 60 FPS/Hz, the frame time budget is 16.67 milliseconds.
-const MAX_FRAME_TIME: Duration = Duration::from_nanos(16_666_667);
+pub const MAX_FRAME_TIME: Duration = Duration::from_nanos(16_666_667);
+
+/// How long a key stays in the keypad shadow after being released. Used by FX0A to detect a key
+/// being pressed and then released.
+pub const KEYUP_RELEASE_DURATION: Duration = Duration::from_millis(30);
+
+/// Address the program (ROM) is loaded at and where execution begins.
+pub(crate) const PROGRAM_START: usize = 0x200;
+
+/// XO-CHIP's 16-bit address space, the most memory any platform has. See `Platform::memory_size()`.
+pub(crate) const MEMORY_SIZE: usize = 0x10000;
+
+/// How many µsec the quickest operations take at an execution speed multiple of 1.
+const FASTEST_OPERATION_MICROS: f32 = 27.0;
+
+/// The stack can hold at most 16 return addresses (subroutines nested 16 deep).
+const STACK_DEPTH: usize = 16;
+
+/// Screen resolution of the original Chip-8 and SUPER-CHIP's low resolution mode.
+const LORES_RESOLUTION: (usize, usize) = (64, 32);
+
+/// Screen resolution of SUPER-CHIP's high resolution mode.
+const HIRES_RESOLUTION: (usize, usize) = (128, 64);
+
+/// Characters used for each frame buffer pixel value by `framebuffer_text()`.
+const TEXT_PIXELS: [char; 4] = ['.', '#', '+', '@'];
+
+/// What every operation function returns. Ok(None) means the operation didn't fit in the current
+/// frame time budget and wasn't executed.
+type OperationResult = Result<Option<Duration>, Chip8Error>;
 
 /// Each character in the font is a sprite, which are is composed of 5 rows of 8 pixels. Each
 /// sprite row can be represented by a single byte and then loaded row-by-row into memory. Each of
@@ -32,10 +70,110 @@ const FONT_DATA: [u8; 80] = [
     0xf0, 0x80, 0xf0, 0x80, 0x80, // F
 ];
 
+/// SUPER-CHIP's big font. Same idea as the regular font, but each character is 8 pixels wide and
+/// 10 rows tall so it's readable on the high resolution screen. The original only had the digits
+/// 0-9, but A-F are included here too as plenty of programs expect them.
+#[rustfmt::skip]
+const BIG_FONT_DATA: [u8; 160] = [
+    0x3c, 0x7e, 0xe7, 0xc3, 0xc3, 0xc3, 0xc3, 0xe7, 0x7e, 0x3c, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3c, // 1
+    0x3e, 0x7f, 0xc3, 0x06, 0x0c, 0x18, 0x30, 0x60, 0xff, 0xff, // 2
+    0x3c, 0x7e, 0xc3, 0x03, 0x0e, 0x0e, 0x03, 0xc3, 0x7e, 0x3c, // 3
+    0x06, 0x0e, 0x1e, 0x36, 0x66, 0xc6, 0xff, 0xff, 0x06, 0x06, // 4
+    0xff, 0xff, 0xc0, 0xc0, 0xfc, 0xfe, 0x03, 0xc3, 0x7e, 0x3c, // 5
+    0x3e, 0x7c, 0xe0, 0xc0, 0xfc, 0xfe, 0xc3, 0xc3, 0x7e, 0x3c, // 6
+    0xff, 0xff, 0x03, 0x06, 0x0c, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3c, 0x7e, 0xc3, 0xc3, 0x7e, 0x7e, 0xc3, 0xc3, 0x7e, 0x3c, // 8
+    0x3c, 0x7e, 0xc3, 0xc3, 0x7f, 0x3f, 0x03, 0x03, 0x3e, 0x7c, // 9
+    0x18, 0x3c, 0x66, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xc3, // A
+    0xfc, 0xfe, 0xc3, 0xc3, 0xfe, 0xfe, 0xc3, 0xc3, 0xfe, 0xfc, // B
+    0x3c, 0x7e, 0xc3, 0xc0, 0xc0, 0xc0, 0xc0, 0xc3, 0x7e, 0x3c, // C
+    0xfc, 0xfe, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xfe, 0xfc, // D
+    0xff, 0xff, 0xc0, 0xc0, 0xfc, 0xfc, 0xc0, 0xc0, 0xff, 0xff, // E
+    0xff, 0xff, 0xc0, 0xc0, 0xfc, 0xfc, 0xc0, 0xc0, 0xc0, 0xc0, // F
+];
+
+/// Contains all the settings related to the operation of Chip-8 VM. The platform picks all the
+/// quirks at once and any quirk that is set individually overrides what the platform picked.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Chip8Settings {
+    #[serde(default)]
+    pub platform: Platform,
+    pub shift_quirk: Option<bool>,
+    pub or_and_xor_quirk: Option<bool>,
+    pub mem_quirk: Option<bool>,
+    pub mem_increment_by_x_quirk: Option<bool>,
+    pub sprite_wrapping_quirk: Option<bool>,
+    pub jump_offset_quirk: Option<bool>,
+    pub display_wait_quirk: Option<bool>,
+    pub hires_display_wait_quirk: Option<bool>,
+    pub clipping_quirk: Option<bool>,
+    pub execution_speed_multiple: f32,
+    pub font_memory_starting_location: u16,
+    pub program_folder_path: String,
+    pub rng_seed: Option<u64>,
+}
+
+impl Default for Chip8Settings {
+    /// The same defaults that get written to a freshly created settings.toml.
+    fn default() -> Self {
+        Self {
+            platform: Platform::CosmacVip,
+            shift_quirk: None,
+            or_and_xor_quirk: None,
+            mem_quirk: None,
+            mem_increment_by_x_quirk: None,
+            sprite_wrapping_quirk: None,
+            jump_offset_quirk: None,
+            display_wait_quirk: None,
+            hires_display_wait_quirk: None,
+            clipping_quirk: None,
+            execution_speed_multiple: 1.0,
+            font_memory_starting_location: 0x050,
+            program_folder_path: String::from("programs"),
+            rng_seed: None,
+        }
+    }
+}
+
+impl Chip8Settings {
+    /// Returns the quirks of the selected platform with any individually set quirks applied on top.
+    pub fn quirks(&self) -> Quirks {
+        let platform = self.platform.quirks();
+
+        Quirks {
+            shift: self.shift_quirk.unwrap_or(platform.shift),
+            or_and_xor: self.or_and_xor_quirk.unwrap_or(platform.or_and_xor),
+            mem: self.mem_quirk.unwrap_or(platform.mem),
+            mem_increment_by_x: self
+                .mem_increment_by_x_quirk
+                .unwrap_or(platform.mem_increment_by_x),
+            sprite_wrapping: self
+                .sprite_wrapping_quirk
+                .unwrap_or(platform.sprite_wrapping),
+            jump_offset: self.jump_offset_quirk.unwrap_or(platform.jump_offset),
+            display_wait: self.display_wait_quirk.unwrap_or(platform.display_wait),
+            hires_display_wait: self
+                .hires_display_wait_quirk
+                .unwrap_or(platform.hires_display_wait),
+            clipping: self.clipping_quirk.unwrap_or(platform.clipping),
+        }
+    }
+
+    /// Whether every operation takes some time at the execution speed multiple. Operation times
+    /// are rounded down to whole µsec, so at very fast speeds some take none at all and a program
+    /// looping on them never finishes a frame.
+    pub fn every_operation_takes_time(&self) -> bool {
+        FASTEST_OPERATION_MICROS * self.execution_speed_multiple >= 1.0
+    }
+}
+
 /// Representation of Chip-8 virtual machine.
 pub struct VirtualMachine {
-    /// VM working memory. Total address range: 0x000 to 0xfff.
-    mem: [u8; 4096],
+    /// VM working memory, as much as the platform has. The original Chip-8 only had 0x000 to
+    /// 0xfff, but XO-CHIP programs can use the whole 16-bit address space. Anything past the end
+    /// faults.
+    mem: Vec<u8>,
     /// General purpose registers V0 to VF. VF is used to set flags by operations and shouldn't be
     /// used by a program directly to store anything except flags, but this isn't a hard rule.
     v: [u8; 16],
@@ -47,100 +185,198 @@ pub struct VirtualMachine {
     stack: Vec<u16>,
     /// Program counter. Points to the next opcode in memory.
     pc: u16,
+    /// The opcode currently being executed. Kept around so errors can report what caused them.
+    opcode: u16,
+    /// Set when an operation fails. The VM stays halted in this faulted state until it's reset.
+    fault: Option<Chip8Error>,
     /// When set by a program to an arbitrary value (0-255), counts down to 0 at a rate of -60 per
     /// second. For general use by the a program.
     delay_timer: u8,
     /// When set by a program to an arbitrary value (0-255), counts down to 0 at a rate of -60 per
     /// second. Sounds buzzer while not 0. Used by programs to generate sound affects.
     pub sound_timer: u8,
+    /// Set by F002. XO-CHIP's 128 bit (16 byte) audio pattern that is played one bit at a time
+    /// while the sound timer is running. None until a program loads one, which means the buzzer
+    /// should just play its regular tone.
+    audio_pattern: Option<[u8; 16]>,
+    /// Set by FX3A. XO-CHIP's audio pitch register, which controls how fast the audio pattern is
+    /// played back. 64 is the default and plays the pattern at 4000 bits per second.
+    pitch: u8,
     /// Keypad input register. Each bool represents the status of a different key.
     pub keypad: [bool; 16],
     /// A copy of the keypad input register to check for a change in state from pressed to released.
-    pub keypad_shadow: [bool; 16],
-    /// Frame buffer that totals 2048 pixels (64 x 32 resolution). Used to store state of each pixel
-    /// so it can be rendered to the screen. There's a more efficient way of representing this
-    /// (a 256 byte array), but it would require bit level encoding and decoding. This is a lot
-    /// easier to work with and worth the 8x bigger memory footprint. The performance delta between
-    /// the two methods is literally imperceptible to the user during gameplay.
-    pub fb: [bool; 2048],
+    keypad_shadow: [bool; 16],
+    /// How much longer each released key will remain in the keypad shadow.
+    keypad_shadow_timers: [Duration; 16],
+    /// Frame buffer that totals 2048 pixels (64 x 32 resolution) or 8192 pixels in SUPER-CHIP's
+    /// high resolution mode (128 x 64 resolution). Used to store state of each pixel so it can be
+    /// rendered to the screen. Each pixel is a byte where bit 0 is the pixel in the first bitplane
+    /// and bit 1 is the pixel in XO-CHIP's second bitplane, so 0 is off and 1 to 3 pick one of the
+    /// four palette colors. There's a more efficient way of representing this, but it would require
+    /// bit level encoding and decoding. The performance delta between the two methods is literally
+    /// imperceptible to the user during gameplay.
+    pub fb: Vec<u8>,
+    /// Set by FN01. Bitmask of the XO-CHIP bitplanes that drawing, clearing and scrolling affect.
+    /// Only the first plane is selected by default so programs for older platforms never touch the
+    /// second one.
+    planes: u8,
+    /// Set by 00FF and cleared by 00FE. Whether the SUPER-CHIP high resolution mode is active.
+    hires: bool,
+    /// Set by 00FD. The program has exited and there is nothing left to execute until a reset.
+    exited: bool,
+    /// SUPER-CHIP RPL user flags saved and loaded by FX75 and FX85. These survive a reset (and can
+    /// be saved by the frontend) just like they survived between programs on the HP-48.
+    rpl_flags: [u8; 16],
     /// Indicates the Chip-8 VM frame is done and it should be rendered to the virtual screen.
     pub draw_flag: bool,
     ///Starting locations for each character in the built-in font (0-F).
     font_locations: [u16; 16],
+    ///Starting locations for each character in the built-in big font (0-F).
+    big_font_locations: [u16; 16],
+    /// Random number generator used by CXNN. Owned by the VM and seeded from `rng_seed` so a run
+    /// can be reproduced exactly by using the same seed.
+    rng: Pcg32,
+    /// The seed the random number generator started from. Either the one from the settings or a
+    /// random one if the settings didn't have one.
+    rng_seed: u64,
     /// Represents total time elapsed since the beginning of the current frame.
     frame_time: Duration,
+    /// Set by DXYN when the display wait quirk is on. Execution is suspended until the next frame
+    /// starts, just like the COSMAC VIP waiting for the vertical blank interrupt.
+    waiting_for_vblank: bool,
+    /// Memory ranges to report accesses to, for debugging.
+    watchpoints: Vec<Watchpoint>,
+    /// Accesses that matched a watchpoint since they were last taken with `take_watchpoint_hits()`.
+    watchpoint_hits: Vec<WatchpointHit>,
+    /// Instructions executed since the last reset. Trace lines are numbered with it.
+    cycles: u64,
+    /// Records every executed instruction when tracing is on.
+    tracer: Option<Tracer>,
     /// Settings for the Chip-8 VM as specified in settings.toml.
     settings: Chip8Settings,
+    /// The quirks resolved from the settings. Looked up on almost every operation so they're only
+    /// resolved once.
+    quirks: Quirks,
 }
 
 impl VirtualMachine {
     /// Creates and returns a new instance of the Chip-8 virtual machine. Loads the built-in font
-    /// into memory and opens a program file (ROM) and load it into memory at location 0x200.
+    /// into memory and opens a program file (ROM) and load it into memory at location 0x200. Octo
+    /// source (.8o) is compiled first.
     pub fn initialize(settings: &Chip8Settings, program_path: &Path) -> anyhow::Result<Self> {
+        let program_data: Vec<u8> = rom::read_program(program_path)?;
+
+        let mut vm = Self::new(settings);
+        vm.load_rom(&program_data)?;
+
+        Ok(vm)
+    }
+
+    /// Creates and returns a new instance of the Chip-8 virtual machine with the built-in font
+    /// loaded into memory, but no program. Use `load_rom()` to give it something to run.
+    pub fn new(settings: &Chip8Settings) -> Self {
         //-----------------------------------------------------------
         // Initialize memory and load built-in font
         //-----------------------------------------------------------
         // This starting address default is 0x050 and is arbitrary but it's popular convention. The
         // font data can exist anywhere between 0x000 and 0x1ff (inclusive) so long as it fits in
-        // that range.
-        let mut mem: [u8; 4096] = [0; 4096];
+        // that range. The big font is loaded directly after the regular font.
+        let mut mem: Vec<u8> = vec![0; settings.platform.memory_size()];
         let mut font_locations: [u16; 16] = [0; 16];
-        let mut font_offset: u16 = settings.font_memory_starting_location;
-        let mut char: usize = 0;
+        let mut big_font_locations: [u16; 16] = [0; 16];
+        let font_start: u16 = settings.font_memory_starting_location;
+        let big_font_start: u16 = font_start + FONT_DATA.len() as u16;
 
         for (iteration, byte) in FONT_DATA.iter().enumerate() {
+            let font_offset = font_start + iteration as u16;
+
             // Every 5 bytes is a complete sprite, so store that starting location for later use in
             // an operation.
             if iteration % 5 == 0 {
-                font_locations[char] = font_offset;
-                char += 1;
+                font_locations[iteration / 5] = font_offset;
             }
             // Load each font byte into a continuous region of memory.
             mem[font_offset as usize] = *byte;
-            font_offset += 0x001;
         }
 
-        //-----------------------------------------------------------
-        // Load the program into memory
-        //-----------------------------------------------------------
-        let mut program_offset: usize = 0x200;
-        let program_data: Vec<u8> = fs::read(program_path)?;
+        for (iteration, byte) in BIG_FONT_DATA.iter().enumerate() {
+            let font_offset = big_font_start + iteration as u16;
 
-        for bytes in program_data.iter() {
-            mem[program_offset] = *bytes;
-            program_offset += 0x01;
+            // Every 10 bytes is a complete big sprite.
+            if iteration % 10 == 0 {
+                big_font_locations[iteration / 10] = font_offset;
+            }
+            mem[font_offset as usize] = *byte;
         }
 
         //-----------------------------------------------------------
         // Initialize the rest of the VirtualMachine and construct it
         //-----------------------------------------------------------
-        Ok(Self {
+        let rng_seed = settings.rng_seed.unwrap_or_else(random::<u64>);
+
+        Self {
             mem,
             v: [0; 16],
             i: 0,
-            pc: 0x200,
-            stack: Vec::with_capacity(16), // Will never be bigger than a size of 16.
+            pc: PROGRAM_START as u16,
+            opcode: 0,
+            fault: None,
+            stack: Vec::with_capacity(STACK_DEPTH), // Will never be bigger than a size of 16.
             delay_timer: 0,
             sound_timer: 0,
+            audio_pattern: None,
+            pitch: 64,
             keypad: [false; 16],
             keypad_shadow: [false; 16],
-            fb: [false; 2048],
+            keypad_shadow_timers: [Duration::ZERO; 16],
+            fb: vec![0; LORES_RESOLUTION.0 * LORES_RESOLUTION.1],
+            planes: 0b01,
+            hires: false,
+            exited: false,
+            rpl_flags: [0; 16],
             draw_flag: false,
             font_locations,
+            big_font_locations,
+            rng: Pcg32::seed_from_u64(rng_seed),
+            rng_seed,
             frame_time: Duration::ZERO,
+            waiting_for_vblank: false,
+            watchpoints: Vec::new(),
+            watchpoint_hits: Vec::new(),
+            cycles: 0,
+            tracer: None,
             // The lifetime annotations to borrow this are not not worth the squeeze. The performance
             // hit is so little, it's fine to just clone it into an owned type.
             settings: settings.clone(),
-        })
+            quirks: settings.quirks(),
+        }
+    }
+
+    /// Copies a program (ROM) into memory starting at location 0x200 and resets the VM so it's
+    /// ready to run it. Anything left over from a previously loaded program is cleared first.
+    pub fn load_rom(&mut self, program_data: &[u8]) -> Result<(), Chip8Error> {
+        let program_space = self.mem.len() - PROGRAM_START;
+        if program_data.len() > program_space {
+            return Err(Chip8Error::RomTooLarge {
+                size: program_data.len(),
+                max: program_space,
+            });
+        }
+
+        self.mem[PROGRAM_START..].fill(0);
+        self.mem[PROGRAM_START..PROGRAM_START + program_data.len()].copy_from_slice(program_data);
+        self.reset();
+
+        Ok(())
     }
 
     /// Resets the Chip-8 VM. Trying to avoid allocating additional real machine memory whenever
     /// possible. This will not allow the user to select a new program.
     pub fn reset(&mut self) {
-        // Clear frame buffer
-        for pixel in self.fb.iter_mut() {
-            *pixel = false;
-        }
+        // Clear frame buffer and go back to low resolution mode with only the first plane selected
+        self.set_resolution(false);
+        self.planes = 0b01;
+        self.exited = false;
 
         // Clear the stack. Capacity of the stack stays the same so no new memory takes place.
         // Equivalent to calling self.stack.pop() in a loop until the vec is empty.
@@ -163,78 +399,406 @@ impl VirtualMachine {
             *key = false;
         }
 
-        // Reset timers
+        // Reset timers and audio
         self.delay_timer = 0;
         self.sound_timer = 0;
+        self.audio_pattern = None;
+        self.pitch = 64;
+        self.frame_time = Duration::ZERO;
+        self.waiting_for_vblank = false;
+
+        // Start the random numbers over so a reset program plays out exactly the same way
+        self.rng = Pcg32::seed_from_u64(self.rng_seed);
+
+        // Set program counter to program start address and recover from any fault
+        self.pc = PROGRAM_START as u16;
+        self.opcode = 0;
+        self.fault = None;
+        self.watchpoint_hits.clear();
+        self.cycles = 0;
+    }
 
-        // Set program counter to program start address
-        self.pc = 0x200;
+    /// Returns the error that halted the VM, if any. A faulted VM won't execute anything else
+    /// until it's reset.
+    pub fn fault(&self) -> Option<Chip8Error> {
+        self.fault
     }
 
-    /// Simulates one operation cycle (not clock cycle) of the Chip-8 VM.
-    pub fn simulate_operation_cycle(
-        &mut self,
-        mol_start_time: &Instant,
-        keypad_shadow_timers: &mut [Instant; 16],
-    ) {
-        let opcode = self.fetch_opcode();
+    /// Returns the frame buffer as rows of pixels, starting from the top left of the screen. Use
+    /// `resolution()` to know how wide each row is. Each pixel is a bitmask of the bitplanes that
+    /// are on at that location (0 to 3).
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.fb
+    }
+
+    /// Returns the frame buffer as text, one line per row of pixels. Pixels that are off are '.',
+    /// pixels in the first bitplane are '#', pixels in the second bitplane are '+' and pixels in
+    /// both bitplanes are '@'.
+    pub fn framebuffer_text(&self) -> String {
+        let (width, _) = self.resolution();
+
+        self.fb
+            .chunks(width)
+            .map(|row| {
+                row.iter()
+                    .map(|pixel| TEXT_PIXELS[(pixel & 0b11) as usize])
+                    .collect::<String>()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Returns the general purpose registers V0 to VF.
+    pub fn registers(&self) -> [u8; 16] {
+        self.v
+    }
+
+    /// Returns the value of the index register (I).
+    pub fn index_register(&self) -> u16 {
+        self.i
+    }
+
+    /// Returns the program counter, which points at the next opcode to be executed.
+    pub fn program_counter(&self) -> u16 {
+        self.pc
+    }
+
+    /// Returns the return addresses on the stack, with the most recent call last.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    /// Returns the current value of the delay timer.
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    /// Returns the VM's whole memory.
+    pub fn memory(&self) -> &[u8] {
+        &self.mem
+    }
+
+    /// Returns the VM's whole memory for tools that need to change it from outside the program,
+    /// like test harnesses selecting a test through a memory location.
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.mem
+    }
+
+    /// Returns the current screen resolution as (width, height). This is 64 x 32 unless a
+    /// SUPER-CHIP program switched to the 128 x 64 high resolution mode.
+    pub fn resolution(&self) -> (usize, usize) {
+        if self.hires {
+            HIRES_RESOLUTION
+        } else {
+            LORES_RESOLUTION
+        }
+    }
+
+    /// Returns true once a SUPER-CHIP program has exited with 00FD. Nothing else will be executed
+    /// until the VM is reset.
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Returns the XO-CHIP audio pattern loaded by the program, if it has loaded one.
+    pub fn audio_pattern(&self) -> Option<[u8; 16]> {
+        self.audio_pattern
+    }
+
+    /// Returns how many bits of the XO-CHIP audio pattern should be played per second. This is
+    /// 4000 * 2^((pitch - 64) / 48) Hz.
+    pub fn audio_playback_rate(&self) -> f32 {
+        4000.0 * 2.0_f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    /// Returns the seed the random number generator started from.
+    pub fn rng_seed(&self) -> u64 {
+        self.rng_seed
+    }
+
+    /// Restarts the random number generator from a new seed. Running the same program with the
+    /// same seed and inputs always produces the same random numbers.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng_seed = seed;
+        self.rng = Pcg32::seed_from_u64(seed);
+    }
+
+    /// Returns the SUPER-CHIP RPL user flags so the frontend can save them between sessions.
+    pub fn rpl_flags(&self) -> [u8; 16] {
+        self.rpl_flags
+    }
+
+    /// Restores SUPER-CHIP RPL user flags previously returned by `rpl_flags()`.
+    pub fn set_rpl_flags(&mut self, rpl_flags: [u8; 16]) {
+        self.rpl_flags = rpl_flags;
+    }
+
+    /// Saves the RPL user flags to a file, so they're still there the next time the program runs
+    /// like they would be on an HP-48.
+    pub fn write_rpl_flags_file(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, self.rpl_flags)?;
+
+        Ok(())
+    }
+
+    /// Loads RPL user flags saved by `write_rpl_flags_file()`. Without a file the flags are left
+    /// as they are, since the program has never saved any.
+    pub fn read_rpl_flags_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        if !fs::exists(path)? {
+            return Ok(());
+        }
+
+        self.rpl_flags = fs::read(path)?.try_into().map_err(|_| {
+            anyhow::Error::msg(format!("{} isn't 16 RPL user flags", path.display()))
+        })?;
+
+        Ok(())
+    }
+
+    /// Starts reporting accesses to a range of memory. Check for them with `take_watchpoint_hits()`.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Stops watching the watchpoint at `index` in `watchpoints()`, returning it if there was one.
+    pub fn remove_watchpoint(&mut self, index: usize) -> Option<Watchpoint> {
+        (index < self.watchpoints.len()).then(|| self.watchpoints.remove(index))
+    }
+
+    /// Returns every watchpoint in the order they were added.
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Returns the memory accesses that matched a watchpoint since the last call, oldest first.
+    pub fn take_watchpoint_hits(&mut self) -> Vec<WatchpointHit> {
+        std::mem::take(&mut self.watchpoint_hits)
+    }
+
+    /// Returns how many instructions have been executed since the VM was last reset.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Starts writing a trace line for every executed instruction, or stops tracing with None.
+    /// Returns the tracer that was there before, if any.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) -> Option<Tracer> {
+        std::mem::replace(&mut self.tracer, tracer)
+    }
+
+    /// Returns the tracer that's recording the program, if there is one.
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.tracer.as_mut()
+    }
+
+    /// Switches between low and high resolution modes. The frame buffer is resized to match and
+    /// cleared either way.
+    fn set_resolution(&mut self, hires: bool) {
+        self.hires = hires;
+        let (width, height) = self.resolution();
+
+        self.fb.clear();
+        self.fb.resize(width * height, 0);
+    }
+
+    /// Moves the selected bitplanes across the screen by (dx, dy) pixels. Pixels scrolled in from
+    /// the edges are off and pixels scrolled past the edges are lost.
+    fn scroll_planes(&mut self, dx: isize, dy: isize) {
+        let (width, height) = self.resolution();
+        let unscrolled = self.fb.clone();
+
+        for y in 0..height {
+            for x in 0..width {
+                let source_x = x as isize - dx;
+                let source_y = y as isize - dy;
+                let scrolled_pixel = if (0..width as isize).contains(&source_x)
+                    && (0..height as isize).contains(&source_y)
+                {
+                    unscrolled[source_y as usize * width + source_x as usize]
+                } else {
+                    0
+                };
+
+                let pixel = &mut self.fb[y * width + x];
+                *pixel = (*pixel & !self.planes) | (scrolled_pixel & self.planes);
+            }
+        }
+    }
+
+    /// Skips over the next instruction. XO-CHIP's F000 NNNN is twice as long as every other
+    /// instruction, so it has to be skipped as a whole or the VM would end up executing its
+    /// address as an opcode.
+    fn skip_next_instruction(&mut self) {
+        let next = self.pc as usize + 2;
+        let next_is_long_load =
+            self.fetch_mem(next).ok() == Some(0xf0) && self.fetch_mem(next + 1).ok() == Some(0x00);
+
+        self.pc = self.pc.wrapping_add(if next_is_long_load { 4 } else { 2 });
+    }
+
+    /// Sets a keypad key (0x0 to 0xf) as pressed or released. Releasing a key that was pressed
+    /// keeps it in the keypad shadow for `KEYUP_RELEASE_DURATION` of VM time so FX0A can see it.
+    pub fn set_key(&mut self, key: usize, pressed: bool) {
+        if key >= self.keypad.len() {
+            return;
+        }
+
+        if !pressed && self.keypad[key] {
+            self.keypad_shadow[key] = true;
+            // Start timing how long a key has been released for.
+            self.keypad_shadow_timers[key] = KEYUP_RELEASE_DURATION;
+        }
+
+        self.keypad[key] = pressed;
+    }
+
+    /// Simulates one operation cycle (not clock cycle) of the Chip-8 VM. `host_elapsed` is how
+    /// long the caller has spent since the start of its current loop iteration (actual time) and
+    /// is counted against the frame time budget alongside the simulated operation time.
+    pub fn simulate_operation_cycle(&mut self, host_elapsed: Duration) -> Result<(), Chip8Error> {
+        self.step()?;
+        self.advance_time(host_elapsed);
+
+        // Out of frame time budget (or the rest of it is being spent waiting for the display), set
+        // everything up for the next frame and tell the virtual screen to render the frame buffer.
+        if self.frame_time > MAX_FRAME_TIME || self.waiting_for_vblank {
+            self.tick_timers();
+        }
+
+        Ok(())
+    }
+
+    /// Executes the instruction at the program counter if it fits in what is left of the current
+    /// frame time budget. Returns how long the operation would take on a real COSMAC VIP, or None
+    /// if it didn't fit and nothing was executed (call `tick_timers()` to start the next frame).
+    /// If the operation fails, the VM is left in a faulted state and the error is returned by
+    /// every call until the VM is reset. Once the program has exited, this always returns None.
+    /// The same goes for the rest of the frame after a sprite is drawn with the display wait
+    /// quirk on.
+    pub fn step(&mut self) -> Result<Option<Duration>, Chip8Error> {
+        if let Some(fault) = self.fault {
+            return Err(fault);
+        }
+
+        if self.exited || self.waiting_for_vblank {
+            return Ok(None);
+        }
 
         // This duration represents the average duration the operation would take on a real COSMIC
         // VIP system to get the execution timing right relative to other operations. The overall
         // execution speed can be adjusted with a multiple that gets applied to each of these
         // numbers.
-        let cycle_duration = self.decode_opcode_and_execute_operation(opcode);
+        let trace_before = self.trace_before();
+        let cycle_duration = match self
+            .fetch_opcode()
+            .and_then(|opcode| self.decode_opcode_and_execute_operation(opcode))
+        {
+            Ok(cycle_duration) => cycle_duration,
+            Err(error) => {
+                self.fault = Some(error);
+                return Err(error);
+            }
+        };
+
+        // Operations that didn't fit in the frame haven't been executed yet, so they're traced
+        // and counted once they are.
+        if cycle_duration.is_some() {
+            self.trace_after(trace_before);
+            self.cycles += 1;
+        }
+
+        // Update the frame time with how long the operation cycle took (simulated time). If the
+        // operation has to wait for the display, the rest of the frame goes by while it waits.
+        self.advance_time(cycle_duration.unwrap_or(Duration::ZERO));
+        if self.waiting_for_vblank {
+            self.advance_time(MAX_FRAME_TIME.saturating_sub(self.frame_time));
+        }
+
+        Ok(cycle_duration)
+    }
+
+    /// Captures what the trace line for the instruction about to be executed needs from before
+    /// it's executed, if the tracer wants a line for it.
+    fn trace_before(&self) -> Option<TraceEntry> {
+        let tracer = self.tracer.as_ref()?;
+        if !tracer.wants(self.cycles) {
+            return None;
+        }
+
+        // Disassemble before executing so self-modifying code is traced as what actually ran.
+        let (mnemonic, _) = disasm::disassemble_at(&self.mem, self.pc as usize)?;
+
+        Some(TraceEntry {
+            cycle: self.cycles,
+            pc: self.pc,
+            opcode: 0,
+            mnemonic,
+            v_before: self.v,
+            v_after: self.v,
+            i_before: self.i,
+            i_after: self.i,
+        })
+    }
+
+    fn trace_after(&mut self, before: Option<TraceEntry>) {
+        let (Some(mut entry), Some(tracer)) = (before, self.tracer.as_mut()) else {
+            return;
+        };
+
+        entry.opcode = self.opcode;
+        entry.v_after = self.v;
+        entry.i_after = self.i;
+        tracer.record(&entry);
+    }
 
-        // Update the frame time with how long the operation cycle took (simulated time) plus how
-        // long since the start of the current frame (actual time).
-        self.frame_time += cycle_duration.unwrap_or(Duration::ZERO);
-        self.frame_time = self.frame_time.saturating_add(mol_start_time.elapsed());
+    /// Ends the current frame. Counts the delay and sound timers down by one, flags the frame
+    /// buffer as ready to be rendered and starts a new frame time budget. Should be called 60
+    /// times per second. This is also the vertical blank that DXYN waits for with the display
+    /// wait quirk on.
+    pub fn tick_timers(&mut self) {
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+
+        self.draw_flag = true;
+        self.frame_time = Duration::ZERO;
+        self.waiting_for_vblank = false;
+    }
+
+    /// Adds elapsed time to the current frame and counts down the keypad shadow timers.
+    fn advance_time(&mut self, elapsed: Duration) {
+        self.frame_time = self.frame_time.saturating_add(elapsed);
 
         // After the release duration had passed for each key, set the key shadow of each to reflect
         // that state.
         for (key, pressed) in self.keypad_shadow.iter_mut().enumerate() {
-            if *pressed
-                && KEYUP_RELEASE_DURATION.saturating_sub(keypad_shadow_timers[key].elapsed())
-                    == Duration::ZERO
-            {
+            self.keypad_shadow_timers[key] = self.keypad_shadow_timers[key].saturating_sub(elapsed);
+            if *pressed && self.keypad_shadow_timers[key] == Duration::ZERO {
                 *pressed = false;
             }
         }
-
-        // Out of frame time budget, set everything up for the next frame and tell the virtual
-        // screen to render the frame buffer.
-        if self.frame_time > MAX_FRAME_TIME {
-            if self.delay_timer > 0 {
-                self.delay_timer -= 1;
-            }
-            if self.sound_timer > 0 {
-                self.sound_timer -= 1;
-            }
-
-            self.draw_flag = true;
-            self.frame_time = Duration::ZERO;
-        }
     }
 
     /// Fetches the opcode bytes from the next two locations in memory, constructs the opcode from
     /// those bytes, and returns the complete opcode.
-    fn fetch_opcode(&mut self) -> u16 {
-        let opcode: u16 = if self.pc + 1 < (self.mem.len() as u16) {
-            // The Chip-8 VM was written in big endian byte order and almost every modern computing
-            // context uses little endian byte order so a byte swap on the first read byte is required.
-            (self.mem[self.pc as usize] as u16).swap_bytes()
-                | (self.mem[(self.pc as usize) + 1] as u16)
-        } else {
-            panic!(
-                "Chip-8 VM program counter reached the end of memory and attempted to read another byte."
-            );
-        };
+    fn fetch_opcode(&mut self) -> Result<u16, Chip8Error> {
+        if self.pc as usize + 1 >= self.mem.len() {
+            return Err(Chip8Error::ProgramCounterOutOfRange { pc: self.pc });
+        }
+
+        // The Chip-8 VM was written in big endian byte order and almost every modern computing
+        // context uses little endian byte order so a byte swap on the first read byte is required.
+        self.opcode = (self.fetch_mem(self.pc as usize)? as u16).swap_bytes()
+            | (self.fetch_mem(self.pc as usize + 1)? as u16);
 
-        opcode
+        Ok(self.opcode)
     }
 
     /// Decodes the provided opcode and calls the appropriate operation function.
-    fn decode_opcode_and_execute_operation(&mut self, opcode: u16) -> Option<Duration> {
+    fn decode_opcode_and_execute_operation(&mut self, opcode: u16) -> OperationResult {
         // Extract the operands from the opcodes to pass into the operation functions. This technique
         // is known as bit masking and it's going to be used a lot in this module.
         let n: u8 = (opcode & 0x000f) as u8;
@@ -249,15 +813,26 @@ impl VirtualMachine {
         match opcode & 0xf000 {
             0x0000 => match opcode & 0x00ff {
                 0x0000 => self.call_routine(nnn),
+                0x00c0..=0x00cf => self.scroll_down(n),
+                0x00d0..=0x00df => self.scroll_up(n),
                 0x00e0 => self.clear_display(),
                 0x00ee => self.subroutine_return(),
-                _ => Self::invalid_operation(opcode),
+                0x00fb => self.scroll_right(),
+                0x00fc => self.scroll_left(),
+                0x00fd => self.exit(),
+                0x00fe => self.low_resolution_mode(),
+                0x00ff => self.high_resolution_mode(),
+                _ => self.invalid_operation(),
             },
             0x1000 => self.jump_to_nnn(nnn),
             0x2000 => self.call_subroutine(nnn),
             0x3000 => self.skip_if_eq_nn(x, nn),
             0x4000 => self.skip_if_neq_nn(x, nn),
-            0x5000 => self.skip_if_eq(x, y),
+            0x5000 => match opcode & 0xf00f {
+                0x5002 => self.save_vx_to_vy(x, y),
+                0x5003 => self.load_vx_to_vy(x, y),
+                _ => self.skip_if_eq(x, y),
+            },
             0x6000 => self.set_vx_to_nn(x, nn),
             0x7000 => self.add_nn_to_vx(x, nn),
             0x8000 => match opcode & 0xf00f {
@@ -279,279 +854,497 @@ impl VirtualMachine {
             0xe000 => match opcode & 0xf0ff {
                 0xe09e => self.skip_if_pressed(x),
                 0xe0a1 => self.skip_if_not_pressed(x),
-                _ => Self::invalid_operation(opcode),
+                _ => self.invalid_operation(),
             },
             0xf000 => match opcode & 0xf0ff {
+                0xf000 if x == 0 => self.set_i_to_nnnn(),
+                0xf001 => self.select_planes(x),
+                0xf002 if x == 0 => self.load_audio_pattern(),
                 0xf007 => self.clone_dt_into_vx(x),
                 0xf00a => self.store_keypress(x),
                 0xf015 => self.set_delay_timer(x),
                 0xf018 => self.set_sound_timer(x),
                 0xf01e => self.add_vx_to_i(x),
                 0xf029 => self.set_i_to_font_sprite_location(x),
+                0xf030 => self.set_i_to_big_font_sprite_location(x),
                 0xf033 => self.bcd_vx(x),
+                0xf03a => self.set_pitch(x),
                 0xf055 => self.dump_registers(x),
                 0xf065 => self.load_registers(x),
-                _ => Self::invalid_operation(opcode),
+                0xf075 => self.store_rpl_flags(x),
+                0xf085 => self.load_rpl_flags(x),
+                _ => self.invalid_operation(),
             },
-            _ => Self::invalid_operation(opcode),
+            _ => self.invalid_operation(),
         }
     }
 
+    /// Every access to memory made by executing a program goes through here so watchpoints see
+    /// all of them. Stores `write` at the address if it's given, then returns the byte at the
+    /// address.
+    fn access_mem(
+        &mut self,
+        address: usize,
+        access: MemoryAccess,
+        write: Option<u8>,
+    ) -> Result<u8, Chip8Error> {
+        let (pc, opcode) = (self.pc, self.opcode);
+        let byte = self
+            .mem
+            .get_mut(address)
+            .ok_or(Chip8Error::MemoryOutOfRange {
+                pc,
+                opcode,
+                address,
+            })?;
+
+        if let Some(value) = write {
+            *byte = value;
+        }
+        let value = *byte;
+
+        if self
+            .watchpoints
+            .iter()
+            .any(|watchpoint| watchpoint.matches(address, access))
+        {
+            self.watchpoint_hits.push(WatchpointHit {
+                pc,
+                opcode,
+                i: self.i,
+                address: address as u16,
+                access,
+                value,
+            });
+        }
+
+        Ok(value)
+    }
+
+    /// Reads a byte of an instruction.
+    fn fetch_mem(&mut self, address: usize) -> Result<u8, Chip8Error> {
+        self.access_mem(address, MemoryAccess::Fetch, None)
+    }
+
+    /// Reads a byte of memory on behalf of the current operation.
+    fn read_mem(&mut self, address: usize) -> Result<u8, Chip8Error> {
+        self.access_mem(address, MemoryAccess::Read, None)
+    }
+
+    /// Writes a byte of memory on behalf of the current operation.
+    fn write_mem(&mut self, address: usize, value: u8) -> Result<(), Chip8Error> {
+        self.access_mem(address, MemoryAccess::Write, Some(value))?;
+
+        Ok(())
+    }
+
     //-----------------------------------------------
     // Operation Functions
     //-----------------------------------------------
-    /// Panic as the VM has no idea what to do with an opcode that's not in the list.
-    fn invalid_operation(opcode: u16) -> Option<Duration> {
-        panic!("Chip-8 VM opcode '{:#06x}' not recognized.", opcode)
+    /// Fault as the VM has no idea what to do with an opcode that's not in the list.
+    fn invalid_operation(&self) -> OperationResult {
+        Err(Chip8Error::UnknownOpcode {
+            pc: self.pc,
+            opcode: self.opcode,
+        })
     }
 
     /// 0NNN: This instruction is only used on the old computers on which the Chip-8 VM was
     /// originally implemented. It is typically ignored by modern interpreters, including this one,
     /// but its signature is here for completeness and timing.
-    fn call_routine(&mut self, _nnn: u16) -> Option<Duration> {
+    fn call_routine(&mut self, _nnn: u16) -> OperationResult {
         let op_duration =
             Duration::from_micros((100.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         // Does literally nothing.
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
-    /// 00E0: Clear the display (clears the frame buffer in this implementation).
-    fn clear_display(&mut self) -> Option<Duration> {
+    /// 00E0: Clear the display (clears the selected bitplanes of the frame buffer in this
+    /// implementation).
+    fn clear_display(&mut self) -> OperationResult {
         let op_duration =
             Duration::from_micros((109.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         for pixel in self.fb.iter_mut() {
-            *pixel = false;
+            *pixel &= !self.planes;
         }
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// 00EE: Return from a subroutine. Sets the program counter to the address at the top of the
     /// stack (the return address), then pops the return address off the stack and sets the program
     /// counter to the next instruction.
-    fn subroutine_return(&mut self) -> Option<Duration> {
+    fn subroutine_return(&mut self) -> OperationResult {
         let op_duration =
             Duration::from_micros((105.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
-        self.pc = *self
-            .stack
-            .last()
-            .expect("Failed to return from a Chip-8 subroutine because the stack is empty.");
-        self.stack.pop();
+        self.pc = self.stack.pop().ok_or(Chip8Error::StackUnderflow {
+            pc: self.pc,
+            opcode: self.opcode,
+        })?;
 
         // This seems weird to do since this function just set the PC, but think of it as returning
         // to the where of the previous instruction left off. This progresses the program by getting
         // the next instruction. If this wasn't here, the program would do this operation repeatedly
         // until there were no more addresses on the stack and then the interpreter would crash.
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
+    }
+
+    /// 00CN: Scroll the selected bitplanes down by N pixels (SUPER-CHIP). Rows scrolled in from
+    /// the top are blank and rows scrolled off the bottom are lost.
+    fn scroll_down(&mut self, n: u8) -> OperationResult {
+        let op_duration =
+            Duration::from_micros((109.0 * self.settings.execution_speed_multiple) as u64);
+        if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
+            return Ok(None);
+        }
+
+        self.scroll_planes(0, n as isize);
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
+    }
+
+    /// 00DN: Scroll the selected bitplanes up by N pixels (XO-CHIP). Rows scrolled in from the
+    /// bottom are blank and rows scrolled off the top are lost.
+    fn scroll_up(&mut self, n: u8) -> OperationResult {
+        let op_duration =
+            Duration::from_micros((109.0 * self.settings.execution_speed_multiple) as u64);
+        if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
+            return Ok(None);
+        }
+
+        self.scroll_planes(0, -(n as isize));
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
+    }
+
+    /// 00FB: Scroll the selected bitplanes right by 4 pixels (SUPER-CHIP).
+    fn scroll_right(&mut self) -> OperationResult {
+        let op_duration =
+            Duration::from_micros((109.0 * self.settings.execution_speed_multiple) as u64);
+        if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
+            return Ok(None);
+        }
+
+        self.scroll_planes(4, 0);
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
+    }
+
+    /// 00FC: Scroll the selected bitplanes left by 4 pixels (SUPER-CHIP).
+    fn scroll_left(&mut self) -> OperationResult {
+        let op_duration =
+            Duration::from_micros((109.0 * self.settings.execution_speed_multiple) as u64);
+        if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
+            return Ok(None);
+        }
+
+        self.scroll_planes(-4, 0);
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
+    }
+
+    /// 00FD: Exit the interpreter (SUPER-CHIP). Execution stops until the VM is reset. The program
+    /// counter is left pointing at this instruction.
+    fn exit(&mut self) -> OperationResult {
+        let op_duration =
+            Duration::from_micros((100.0 * self.settings.execution_speed_multiple) as u64);
+        if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
+            return Ok(None);
+        }
+
+        self.exited = true;
+
+        Ok(Some(op_duration))
+    }
+
+    /// 00FE: Switch to the 64 x 32 low resolution mode (SUPER-CHIP). Clears the display.
+    fn low_resolution_mode(&mut self) -> OperationResult {
+        let op_duration =
+            Duration::from_micros((100.0 * self.settings.execution_speed_multiple) as u64);
+        if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
+            return Ok(None);
+        }
+
+        self.set_resolution(false);
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
+    }
+
+    /// 00FF: Switch to the 128 x 64 high resolution mode (SUPER-CHIP). Clears the display.
+    fn high_resolution_mode(&mut self) -> OperationResult {
+        let op_duration =
+            Duration::from_micros((100.0 * self.settings.execution_speed_multiple) as u64);
+        if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
+            return Ok(None);
+        }
+
+        self.set_resolution(true);
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// 1NNN: Jump to address NNN. Sets the program counter to NNN.
-    fn jump_to_nnn(&mut self, nnn: u16) -> Option<Duration> {
+    fn jump_to_nnn(&mut self, nnn: u16) -> OperationResult {
         let op_duration =
             Duration::from_micros((105.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         self.pc = nnn;
 
-        Some(op_duration)
+        Ok(Some(op_duration))
     }
 
     /// 2NNN: Call subroutine at NNN. Pushes the value of the program counter onto the stack and
     /// then sets the program counter to nnn.
-    fn call_subroutine(&mut self, nnn: u16) -> Option<Duration> {
+    fn call_subroutine(&mut self, nnn: u16) -> OperationResult {
         let op_duration =
             Duration::from_micros((105.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
+        }
+
+        if self.stack.len() >= STACK_DEPTH {
+            return Err(Chip8Error::StackOverflow {
+                pc: self.pc,
+                opcode: self.opcode,
+            });
         }
 
         self.stack.push(self.pc);
         self.pc = nnn;
 
-        Some(op_duration)
+        Ok(Some(op_duration))
     }
 
     /// 3XNN: Skip next instruction if Vx == NN. Compares value of register Vx to NN, and if they
     /// are equal, increments the program counter by 2 (usually the next instruction is a jump to
     /// skip a code block).
-    fn skip_if_eq_nn(&mut self, x: usize, nn: u8) -> Option<Duration> {
+    fn skip_if_eq_nn(&mut self, x: usize, nn: u8) -> OperationResult {
         let op_duration =
             Duration::from_micros((61.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         if self.v[x] == nn {
-            self.pc += 2;
+            self.skip_next_instruction();
         }
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// 4XNN: Skip next instruction if Vx != NN. Compares value of register Vx to NN, and if they
     /// are equal, increments the program counter by 2 (usually the next instruction is a jump to
     /// skip a code block).
-    fn skip_if_neq_nn(&mut self, x: usize, nn: u8) -> Option<Duration> {
+    fn skip_if_neq_nn(&mut self, x: usize, nn: u8) -> OperationResult {
         let op_duration =
             Duration::from_micros((61.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         if self.v[x] != nn {
-            self.pc += 2;
+            self.skip_next_instruction();
         }
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// 5XY0: Skip next instruction if Vx == Vy. Compares value of register Vx to the value of
     /// register Vy and, if they are equal, increments the program counter by 2 (usually the next
     /// instruction is a jump to skip a code block).
-    fn skip_if_eq(&mut self, x: usize, y: usize) -> Option<Duration> {
+    fn skip_if_eq(&mut self, x: usize, y: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((61.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         if self.v[x] == self.v[y] {
-            self.pc += 2;
+            self.skip_next_instruction();
+        }
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
+    }
+
+    /// 5XY2: Store registers Vx through Vy (inclusive) in memory starting at the location in I
+    /// (XO-CHIP). I is left unmodified. If x is greater than y, the registers are stored in
+    /// reverse order.
+    fn save_vx_to_vy(&mut self, x: usize, y: usize) -> OperationResult {
+        let op_duration =
+            Duration::from_micros((605.0 * self.settings.execution_speed_multiple) as u64);
+        if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
+            return Ok(None);
+        }
+
+        let i = self.i as usize;
+
+        for offset in 0..=x.abs_diff(y) {
+            let register = if x <= y { x + offset } else { x - offset };
+            self.write_mem(i + offset, self.v[register])?;
+        }
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
+    }
+
+    /// 5XY3: Fill registers Vx through Vy (inclusive) from memory starting at the location in I
+    /// (XO-CHIP). I is left unmodified. If x is greater than y, the registers are filled in
+    /// reverse order.
+    fn load_vx_to_vy(&mut self, x: usize, y: usize) -> OperationResult {
+        let op_duration =
+            Duration::from_micros((605.0 * self.settings.execution_speed_multiple) as u64);
+        if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
+            return Ok(None);
+        }
+
+        let i = self.i as usize;
+
+        for offset in 0..=x.abs_diff(y) {
+            let register = if x <= y { x + offset } else { x - offset };
+            self.v[register] = self.read_mem(i + offset)?;
         }
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// 6XNN: Set Vx to NN. Puts the value NN into register Vx.
-    fn set_vx_to_nn(&mut self, x: usize, nn: u8) -> Option<Duration> {
+    fn set_vx_to_nn(&mut self, x: usize, nn: u8) -> OperationResult {
         let op_duration =
             Duration::from_micros((27.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         self.v[x] = nn;
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// 7XNN: Add NN to Vx. Adds NN to the value of register Vx, then stores the result in Vx
     /// (carry flag is not changed).
-    fn add_nn_to_vx(&mut self, x: usize, nn: u8) -> Option<Duration> {
+    fn add_nn_to_vx(&mut self, x: usize, nn: u8) -> OperationResult {
         let op_duration =
             Duration::from_micros((45.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         self.v[x] = self.v[x].wrapping_add(nn);
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// 8XY0: Clone Vy to Vx. Stores the value of register Vy in register Vx (the value of Vy
     /// remains unchanged).
-    fn clone(&mut self, x: usize, y: usize) -> Option<Duration> {
+    fn clone(&mut self, x: usize, y: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((45.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         self.v[x] = self.v[y];
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// 8XY1: Set Vx to Vx OR Vy. Performs a bitwise OR on the values of Vx and Vy, then stores
     /// the result in Vx. Quirk: Reset the carry flag to zero after the operation.
-    fn or(&mut self, x: usize, y: usize) -> Option<Duration> {
+    fn or(&mut self, x: usize, y: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((200.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         self.v[x] |= self.v[y];
 
-        if self.settings.or_and_xor_quirk {
+        if self.quirks.or_and_xor {
             self.v[0xf] = 0;
         }
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// 8XY2: Set Vx to Vx AND Vy. Performs a bitwise AND on the values of Vx and Vy, then stores
     /// the result in Vx. Quirk: Reset the carry flag to zero after the operation.
-    fn and(&mut self, x: usize, y: usize) -> Option<Duration> {
+    fn and(&mut self, x: usize, y: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((200.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         self.v[x] &= self.v[y];
 
-        if self.settings.or_and_xor_quirk {
+        if self.quirks.or_and_xor {
             self.v[0xf] = 0;
         }
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// 8XY3: Set Vx to Vx XOR Vy. Performs a bitwise XOR on the values of Vx and Vy, then stores
     /// the result in Vx. Quirk: Reset the carry flag to zero after the operation.
-    fn xor(&mut self, x: usize, y: usize) -> Option<Duration> {
+    fn xor(&mut self, x: usize, y: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((200.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         self.v[x] ^= self.v[y];
 
-        if self.settings.or_and_xor_quirk {
+        if self.quirks.or_and_xor {
             self.v[0xf] = 0;
         }
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// 8XY4: Set Vx = Vx + Vy and set VF = carry. The values of Vx and Vy are added together.
     /// If the addition results in an overflow (i.e. > 255), VF is set to 1 and otherwise it's set
     /// to 0.
-    fn add(&mut self, x: usize, y: usize) -> Option<Duration> {
+    fn add(&mut self, x: usize, y: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((45.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         let result: (u8, bool) = self.v[x].overflowing_add(self.v[y]);
@@ -564,18 +1357,18 @@ impl VirtualMachine {
             self.v[0xf] = 0;
         }
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// 8XY5: Set Vx = Vx - Vy and set VF = !borrow. Vy is subtracted from Vx and the results
     /// stored in Vx. If the subtraction results in an underflow, then VF is set to 0 otherwise
     /// VF is set to 1 (opposite of what you expect).
-    fn subtract_vy_from_vx(&mut self, x: usize, y: usize) -> Option<Duration> {
+    fn subtract_vy_from_vx(&mut self, x: usize, y: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((200.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         let result: (u8, bool) = self.v[x].overflowing_sub(self.v[y]);
@@ -588,21 +1381,21 @@ impl VirtualMachine {
             self.v[0xf] = 1;
         }
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// 8XY6: Set Vx = Vy and then set Vx = Vx bit shifted right by 1. If the least-significant bit
     /// of Vx is 1, then VF is set to 1, otherwise it's set to 0. Then Vx is shifted right by 1.
     /// Quirk: Ignore Vy and just shift the contents of Vx as is.
-    fn shift_right(&mut self, x: usize, y: usize) -> Option<Duration> {
+    fn shift_right(&mut self, x: usize, y: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((200.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
-        if !self.settings.shift_quirk {
+        if !self.quirks.shift {
             self.v[x] = self.v[y];
         }
 
@@ -611,18 +1404,18 @@ impl VirtualMachine {
 
         self.v[0xf] = bit_shifted_out;
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// 8XY7: Set Vx = Vy - Vx and set VF = !borrow. Vx is subtracted from Vy and the result is
     /// stored in Vx. If the subtraction results in an underflow, then VF is set to 0 otherwise
     /// VF is set to 1 (opposite of what you expect).
-    fn subtract_vx_from_vy(&mut self, x: usize, y: usize) -> Option<Duration> {
+    fn subtract_vx_from_vy(&mut self, x: usize, y: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((200.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         let result: (u8, bool) = self.v[y].overflowing_sub(self.v[x]);
@@ -635,20 +1428,20 @@ impl VirtualMachine {
             self.v[0xf] = 1;
         }
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// 8XYE: Set Vx = Vy and then set Vx = Vx bit shifted left by 1. If the most-significant bit
     /// of Vx is 1, then VF is set to 1, it's set to 0. Then Vx is shifted left by 1.
     /// Quirk: Ignore Vy and just shift the contents of Vx as is.
-    fn shift_left(&mut self, x: usize, y: usize) -> Option<Duration> {
+    fn shift_left(&mut self, x: usize, y: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((200.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
-        if !self.settings.shift_quirk {
+        if !self.quirks.shift {
             self.v[x] = self.v[y];
         }
 
@@ -657,75 +1450,76 @@ impl VirtualMachine {
 
         self.v[0xf] = bit_shifted_out;
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// 9XY0: Skip next instruction if Vx != Vy. Compares value of register Vx to the value of
     /// register Vy and, if they are not equal, increments the program counter by 2 (usually
     /// the next instruction is a jump to skip a code block).
-    fn skip_if_neq(&mut self, x: usize, y: usize) -> Option<Duration> {
+    fn skip_if_neq(&mut self, x: usize, y: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((61.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         if self.v[x] != self.v[y] {
-            self.pc += 2;
+            self.skip_next_instruction();
         }
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// ANNN: Set I = nnn. The value of register I is set to nnn.
-    fn set_i_to_nnn(&mut self, nnn: u16) -> Option<Duration> {
+    fn set_i_to_nnn(&mut self, nnn: u16) -> OperationResult {
         let op_duration =
             Duration::from_micros((55.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         self.i = nnn;
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// BNNN: Jump to location NNN + V0. The program counter is set to NNN plus the value of V0.
     /// Quirk: The program counter is set to NNN plus the value of Vx where x is the most
     /// significant digit in NNN (ie. XNN) instead of V0.
-    fn jump_to_v0_plus_nnn(&mut self, nnn: u16) -> Option<Duration> {
+    fn jump_to_v0_plus_nnn(&mut self, nnn: u16) -> OperationResult {
         let op_duration =
             Duration::from_micros((105.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
-        if self.settings.jump_offset_quirk {
+        if self.quirks.jump_offset {
             let x = (nnn & 0xf00).swap_bytes() as usize;
             self.pc = nnn.wrapping_add(self.v[x] as u16);
         } else {
             self.pc = nnn.wrapping_add(self.v[0] as u16);
         }
 
-        Some(op_duration)
+        Ok(Some(op_duration))
     }
 
     /// CXNN: Set Vx = random byte AND NN. Generates a random number from 0 to 255 inclusive, which
-    /// is then bitwise ANDed with the value NN. The results are stored in Vx.
-    fn random_and_nn(&mut self, x: usize, nn: u8) -> Option<Duration> {
+    /// is then bitwise ANDed with the value NN. The results are stored in Vx. The random number
+    /// comes from the VM's own seeded generator so runs can be reproduced.
+    fn random_and_nn(&mut self, x: usize, nn: u8) -> OperationResult {
         let op_duration =
             Duration::from_micros((164.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
-        self.v[x] = nn & random::<u8>();
+        self.v[x] = nn & self.rng.random::<u8>();
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// DXYN: Display N height sprite starting at memory location I at (Vx, Vy), set VF = collision.
@@ -734,72 +1528,103 @@ impl VirtualMachine {
     /// height of N pixels. Each row of 8 pixels is read as a bit-coded byte starting from memory
     /// location I (I value does not change). Sprites are XORed onto the existing screen. If this
     /// causes any pixels to be erased, VF is set to 1, otherwise it is set to 0.
+    /// DXY0 (SUPER-CHIP): Same as above, but the sprite is 16 x 16 pixels and each row is 2 bytes.
+    /// XO-CHIP: The sprite is drawn to each selected bitplane, reading the data for the second
+    /// plane right after the data for the first.
     /// Quirk: If the sprite's starting position outside the coordinates of the display, it wraps
     /// around to the opposite side of the screen. Sprites themselves don't wrap once they begin
     /// to be drawn, but the starting of the sprite point wraps before drawing begins.
-    fn draw_sprite(&mut self, x: usize, y: usize, n: u8) -> Option<Duration> {
+    /// Quirk: Parts of the sprite that go past the edges of the screen are clipped. When this is
+    /// off, they wrap around to the opposite side of the screen instead.
+    /// Quirk: Wait for the display to refresh (vertical blank). Nothing else is executed until
+    /// the next frame starts, so at most one sprite is drawn per frame.
+    fn draw_sprite(&mut self, x: usize, y: usize, n: u8) -> OperationResult {
+        // The wait for the display (when the quirk is on) isn't included here. It's handled by
+        // suspending execution until the next frame once the sprite has been drawn.
         let op_duration =
-            Duration::from_micros((10_734.0 * self.settings.execution_speed_multiple) as u64);
+            Duration::from_micros((605.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
-        let i = self.i as usize;
-        let mut sprite_row: u8;
-        let mut sprite_pixel: u8;
-        let mut fb_pixel: bool;
+        let mut sprite_address = self.i as usize;
+        let (width, height) = self.resolution();
+        let (sprite_width, sprite_height) = if n == 0 { (16, 16) } else { (8, n as usize) };
+        let bytes_per_row = sprite_width / 8;
+        let mut sprite_row: u16;
+        let mut sprite_pixel: u16;
         let mut fb_pixel_index: usize;
         let mut collision = false;
         let mut x = self.v[x] as usize;
         let mut y = self.v[y] as usize;
 
-        if self.settings.sprite_wrapping_quirk {
+        if self.quirks.sprite_wrapping {
             // The modulo operator (%) is used on the x and y coordinates from Vx and Vy to properly
             // wrap the starting values inside the bounds of the screen.
-            x %= 64;
-            y %= 32;
+            x %= width;
+            y %= height;
         }
 
-        // Iterate over each row in a sprite
-        'get_sprite_rows: for current_row in 0..n as usize {
-            // If next sprite row would be drawn off the bottom of the screen, stop drawing sprite.
-            if y + current_row > 31 {
-                break 'get_sprite_rows;
+        // Iterate over each selected bitplane. When more than one is selected, the sprite data
+        // for each plane follows the previous plane's data in memory.
+        for plane in [0b01, 0b10] {
+            if self.planes & plane == 0 {
+                continue;
             }
 
-            // Sprite bytes are stored in big endian so their bits have to be reversed for modern
-            // computers
-            sprite_row = self.mem[i + current_row].reverse_bits();
-
-            // Iterate over each bit (pixel) in a row
-            'set_fb_pixel: for current_pixel in 0..8 {
-                // If next sprite pixel in row would be drawn off the right of the screen, stop
-                // drawing this row and move on to the next.
-                if x + current_pixel > 63 {
-                    break 'set_fb_pixel;
+            // Iterate over each row in a sprite
+            'get_sprite_rows: for current_row in 0..sprite_height {
+                // If next sprite row would be drawn off the bottom of the screen, stop drawing
+                // sprite. Quirk: Without clipping, the row wraps around to the top instead.
+                let mut fb_row = y + current_row;
+                if fb_row >= height {
+                    if self.quirks.clipping {
+                        break 'get_sprite_rows;
+                    }
+                    fb_row %= height;
                 }
 
-                // Get the value of each pixel in the sprite and frame buffer.
-                fb_pixel_index = (y + current_row) * 64 + (x + current_pixel);
-                fb_pixel = self.fb[fb_pixel_index];
-                sprite_pixel = (sprite_row >> current_pixel) % 2;
-
-                // This is effectively an XOR operation on the frame buffer pixel with the sprite
-                // pixel. A collision is if the frame buffer pixel turns off as result of the XOR
-                // operation.
-                if sprite_pixel == 1 {
-                    match fb_pixel {
-                        false => {
-                            self.fb[fb_pixel_index] = true;
+                // Sprite bytes are stored in big endian so their bits have to be reversed for
+                // modern computers. 8 pixel wide rows are read into the high byte so both sprite
+                // sizes line up the same way.
+                let row_start = sprite_address + current_row * bytes_per_row;
+                let high_byte = self.read_mem(row_start)?;
+                let low_byte = if bytes_per_row == 2 {
+                    self.read_mem(row_start + 1)?
+                } else {
+                    0
+                };
+                sprite_row = u16::from_be_bytes([high_byte, low_byte]).reverse_bits();
+
+                // Iterate over each bit (pixel) in a row
+                'set_fb_pixel: for current_pixel in 0..sprite_width {
+                    // If next sprite pixel in row would be drawn off the right of the screen, stop
+                    // drawing this row and move on to the next. Quirk: Without clipping, the pixel
+                    // wraps around to the left instead.
+                    let mut fb_column = x + current_pixel;
+                    if fb_column >= width {
+                        if self.quirks.clipping {
+                            break 'set_fb_pixel;
                         }
+                        fb_column %= width;
+                    }
 
-                        true => {
-                            self.fb[fb_pixel_index] = false;
+                    // Get the value of each pixel in the sprite and frame buffer.
+                    fb_pixel_index = fb_row * width + fb_column;
+                    sprite_pixel = (sprite_row >> current_pixel) % 2;
+
+                    // XOR the sprite pixel onto this plane of the frame buffer pixel. A collision
+                    // is if the frame buffer pixel turns off as result of the XOR operation.
+                    if sprite_pixel == 1 {
+                        if self.fb[fb_pixel_index] & plane != 0 {
                             collision = true;
                         }
+                        self.fb[fb_pixel_index] ^= plane;
                     }
                 }
             }
+
+            sprite_address += sprite_height * bytes_per_row;
         }
 
         // If any collision occurred during the drawing of the sprite, it is indicated in the flag
@@ -810,212 +1635,348 @@ impl VirtualMachine {
             self.v[0xf] = 0;
         }
 
-        self.pc += 2;
-        Some(op_duration)
+        if self.quirks.display_wait && (!self.hires || self.quirks.hires_display_wait) {
+            self.waiting_for_vblank = true;
+        }
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// EX9E: Skip next instruction if key with the value of Vx is pressed at time of check. Checks
     /// the keyboard, and if the key corresponding to the value of Vx (only considering the lowest
     /// nibble) is currently in the down position, program counter is increased by 2.
-    fn skip_if_pressed(&mut self, x: usize) -> Option<Duration> {
+    fn skip_if_pressed(&mut self, x: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((73.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         let key = (self.v[x] & 0x000f) as usize;
 
         if self.keypad[key] {
-            self.pc += 2;
+            self.skip_next_instruction();
         }
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// EXA1: Skip next instruction if key with the value of Vx is not pressed at time of check.
     /// Checks the keyboard, and if the key corresponding to the value of Vx (only considering
     /// the lowest nibble) is currently in the up position, program counter is increased by 2.
-    fn skip_if_not_pressed(&mut self, x: usize) -> Option<Duration> {
+    fn skip_if_not_pressed(&mut self, x: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((73.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         let key = (self.v[x] & 0x000f) as usize;
 
         if !self.keypad[key] {
-            self.pc += 2;
+            self.skip_next_instruction();
+        }
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
+    }
+
+    /// F000 NNNN: Set I = NNNN (XO-CHIP). The 16-bit address is stored in the two bytes after the
+    /// opcode, which makes this the only instruction that is 4 bytes long.
+    fn set_i_to_nnnn(&mut self) -> OperationResult {
+        let op_duration =
+            Duration::from_micros((55.0 * self.settings.execution_speed_multiple) as u64);
+        if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
+            return Ok(None);
+        }
+
+        let address = self.pc as usize + 2;
+        self.i = u16::from_be_bytes([self.fetch_mem(address)?, self.fetch_mem(address + 1)?]);
+
+        self.pc = self.pc.wrapping_add(4);
+        Ok(Some(op_duration))
+    }
+
+    /// FN01: Select the bitplanes (XO-CHIP) that drawing, clearing and scrolling affect. N is a
+    /// bitmask so 0 selects no planes, 1 and 2 select a single plane and 3 selects both.
+    fn select_planes(&mut self, n: usize) -> OperationResult {
+        let op_duration =
+            Duration::from_micros((45.0 * self.settings.execution_speed_multiple) as u64);
+        if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
+            return Ok(None);
         }
 
-        self.pc += 2;
-        Some(op_duration)
+        self.planes = (n as u8) & 0b11;
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
+    }
+
+    /// F002: Load the 16 bytes starting at the location in I into the audio pattern buffer
+    /// (XO-CHIP). I is left unmodified.
+    fn load_audio_pattern(&mut self) -> OperationResult {
+        let op_duration =
+            Duration::from_micros((605.0 * self.settings.execution_speed_multiple) as u64);
+        if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
+            return Ok(None);
+        }
+
+        let i = self.i as usize;
+        let mut audio_pattern = [0; 16];
+
+        for (offset, byte) in audio_pattern.iter_mut().enumerate() {
+            *byte = self.read_mem(i + offset)?;
+        }
+        self.audio_pattern = Some(audio_pattern);
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// FX07: Set Vx = delay timer value.
-    fn clone_dt_into_vx(&mut self, x: usize) -> Option<Duration> {
+    fn clone_dt_into_vx(&mut self, x: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((27.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         self.v[x] = self.delay_timer;
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// FX0A: Wait for a key press, store which key is pressed in Vx. All execution stops (delay
     /// and sound timers continue processing) until a key is pressed and then released.
-    fn store_keypress(&mut self, x: usize) -> Option<Duration> {
+    fn store_keypress(&mut self, x: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((200.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         for (key, pressed) in self.keypad_shadow.iter().enumerate() {
             if *pressed && !self.keypad[key] {
                 self.v[x] = key as u8;
-                self.pc += 2;
-                return Some(op_duration); // Return early to allow program execution to continue.
+                self.pc = self.pc.wrapping_add(2);
+                return Ok(Some(op_duration)); // Return early to allow program execution to continue.
             }
         }
-        Some(op_duration)
+        Ok(Some(op_duration))
     }
 
     /// FX15: Set delay timer = Vx.
-    fn set_delay_timer(&mut self, x: usize) -> Option<Duration> {
+    fn set_delay_timer(&mut self, x: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((45.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         self.delay_timer = self.v[x];
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// FX18: Set sound timer = Vx.
-    fn set_sound_timer(&mut self, x: usize) -> Option<Duration> {
+    fn set_sound_timer(&mut self, x: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((45.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         self.sound_timer = self.v[x];
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// FX1E: Set I = I + Vx.
-    fn add_vx_to_i(&mut self, x: usize) -> Option<Duration> {
+    fn add_vx_to_i(&mut self, x: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((86.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         self.i = self.i.wrapping_add(self.v[x] as u16);
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// FX29: Set I to the memory location in of the sprite representing the character in Vx (only
     /// considering the lowest nibble).
-    fn set_i_to_font_sprite_location(&mut self, x: usize) -> Option<Duration> {
+    fn set_i_to_font_sprite_location(&mut self, x: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((91.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         let font_char: u8 = self.v[x] & 0x0f;
         self.i = self.font_locations[font_char as usize];
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
+    }
+
+    /// FX30: Set I to the memory location of the big font sprite representing the character in Vx
+    /// (only considering the lowest nibble) (SUPER-CHIP).
+    fn set_i_to_big_font_sprite_location(&mut self, x: usize) -> OperationResult {
+        let op_duration =
+            Duration::from_micros((91.0 * self.settings.execution_speed_multiple) as u64);
+        if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
+            return Ok(None);
+        }
+
+        let font_char: u8 = self.v[x] & 0x0f;
+        self.i = self.big_font_locations[font_char as usize];
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// FX33: Store binary-coded decimal (BCD) representation of Vx in memory locations I (hundreds
     /// digit), I+1(tens digit), and I+2 (ones digit).
-    fn bcd_vx(&mut self, x: usize) -> Option<Duration> {
+    fn bcd_vx(&mut self, x: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((927.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         let i = self.i as usize;
-        self.mem[i] = self.v[x] / 100;
-        self.mem[i + 1] = (self.v[x] / 10) % 10;
-        self.mem[i + 2] = (self.v[x] % 100) % 10;
+        self.write_mem(i, self.v[x] / 100)?;
+        self.write_mem(i + 1, (self.v[x] / 10) % 10)?;
+        self.write_mem(i + 2, (self.v[x] % 100) % 10)?;
 
-        self.pc += 2;
-        Some(op_duration)
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
+    }
+
+    /// FX3A: Set the audio pitch register = Vx (XO-CHIP).
+    fn set_pitch(&mut self, x: usize) -> OperationResult {
+        let op_duration =
+            Duration::from_micros((45.0 * self.settings.execution_speed_multiple) as u64);
+        if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
+            return Ok(None);
+        }
+
+        self.pitch = self.v[x];
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// FX55: Store registers V0 through Vx (inclusive) in memory starting at the location in I.
     /// The offset from I is increased by 1 for each value written, but I itself is left unmodified.
     /// Quirk: VI is also increased by 1 for each register stored and the final value of VI is
-    /// V[i] + x + 1.
-    fn dump_registers(&mut self, x: usize) -> Option<Duration> {
+    /// V[i] + x + 1 (or V[i] + x with the mem_increment_by_x quirk).
+    fn dump_registers(&mut self, x: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((605.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         let mut register: usize = 0;
         let mut i_offset = self.i as usize;
 
         while x >= register {
-            self.mem[i_offset] = self.v[register];
+            self.write_mem(i_offset, self.v[register])?;
             register += 1;
             i_offset += 1;
-
-            if self.settings.mem_quirk {
-                self.i += 1;
-            }
         }
 
-        self.pc += 2;
-        Some(op_duration)
+        self.advance_i_past_registers(x);
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 
     /// FX65: Fill registers V0 through Vx (inclusive) from memory starting at the location in I.
     /// The offset from I is increased by 1 for each value read, but I itself is left unmodified.
     /// Quirk: VI is also increased by 1 for each register stored and the final value of VI is
-    /// V[i] + x + 1.
-    fn load_registers(&mut self, x: usize) -> Option<Duration> {
+    /// V[i] + x + 1 (or V[i] + x with the mem_increment_by_x quirk).
+    fn load_registers(&mut self, x: usize) -> OperationResult {
         let op_duration =
             Duration::from_micros((605.0 * self.settings.execution_speed_multiple) as u64);
         if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
-            return None;
+            return Ok(None);
         }
 
         let mut register: usize = 0;
         let mut i_offset = self.i as usize;
 
         while x >= register {
-            self.v[register] = self.mem[i_offset];
+            self.v[register] = self.read_mem(i_offset)?;
             register += 1;
             i_offset += 1;
+        }
 
-            if self.settings.mem_quirk {
-                self.i += 1;
-            }
+        self.advance_i_past_registers(x);
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
+    }
+
+    /// Moves I on after FX55/FX65 stored or loaded registers V0 through Vx, if the mem quirk says
+    /// to. Usually it ends up just past the last register, but CHIP-48 leaves it on the last one.
+    fn advance_i_past_registers(&mut self, x: usize) {
+        if self.quirks.mem {
+            let registers = if self.quirks.mem_increment_by_x {
+                x
+            } else {
+                x + 1
+            };
+            self.i = self.i.wrapping_add(registers as u16);
+        }
+    }
+
+    /// FX75: Store registers V0 through Vx (inclusive) in the RPL user flags (SUPER-CHIP).
+    fn store_rpl_flags(&mut self, x: usize) -> OperationResult {
+        let op_duration =
+            Duration::from_micros((605.0 * self.settings.execution_speed_multiple) as u64);
+        if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
+            return Ok(None);
         }
 
-        self.pc += 2;
-        Some(op_duration)
+        self.rpl_flags[..=x].copy_from_slice(&self.v[..=x]);
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
+    }
+
+    /// FX85: Fill registers V0 through Vx (inclusive) from the RPL user flags (SUPER-CHIP).
+    fn load_rpl_flags(&mut self, x: usize) -> OperationResult {
+        let op_duration =
+            Duration::from_micros((605.0 * self.settings.execution_speed_multiple) as u64);
+        if self.frame_time.saturating_add(op_duration) > MAX_FRAME_TIME {
+            return Ok(None);
+        }
+
+        self.v[..=x].copy_from_slice(&self.rpl_flags[..=x]);
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(Some(op_duration))
     }
 }
+
+/// Parses a number in hex with a 0x prefix or in decimal without one.
+pub(crate) fn parse_number(number: &str) -> Result<u16, String> {
+    let parsed = match number.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => number.parse(),
+    };
+
+    parsed.map_err(|_| format!("'{number}' isn't a number between 0 and 0xffff"))
+}
+
+#[cfg(test)]
+pub(crate) mod tests;
@@ -0,0 +1,149 @@
+use super::{HIRES_RESOLUTION, LORES_RESOLUTION, STACK_DEPTH, VirtualMachine};
+use crate::SaveStateError;
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Every save state starts with these bytes so anything else can be rejected right away.
+const SAVE_STATE_MAGIC: &[u8; 4] = b"CH8S";
+
+/// Version of the save state format. Bump this whenever `SaveState` changes so old save states
+/// are rejected instead of being misread.
+pub const SAVE_STATE_VERSION: u16 = 2;
+
+/// A snapshot of everything a running program can change in the VM. Restoring it puts the VM
+/// back exactly where it was, random numbers included. The settings aren't part of it, so a
+/// save state is restored with whatever settings the VM currently has.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct SaveState {
+    mem: Vec<u8>,
+    v: [u8; 16],
+    i: u16,
+    stack: Vec<u16>,
+    pc: u16,
+    delay_timer: u8,
+    sound_timer: u8,
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
+    keypad: [bool; 16],
+    keypad_shadow: [bool; 16],
+    keypad_shadow_timers: [Duration; 16],
+    fb: Vec<u8>,
+    planes: u8,
+    hires: bool,
+    exited: bool,
+    rpl_flags: [u8; 16],
+    rng: Pcg32,
+    rng_seed: u64,
+    frame_time: Duration,
+    waiting_for_vblank: bool,
+    cycles: u64,
+}
+
+impl SaveState {
+    /// Encodes the save state as bytes for writing to a file. The bytes start with a header and
+    /// the format version so `from_bytes()` can tell if it's able to read them.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::from(*SAVE_STATE_MAGIC);
+        bytes.extend(SAVE_STATE_VERSION.to_le_bytes());
+        bytes.extend(bincode::serialize(self).expect("save states only contain serializable data"));
+
+        bytes
+    }
+
+    /// Decodes a save state previously encoded by `to_bytes()`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SaveStateError> {
+        let Some(version_and_state) = bytes.strip_prefix(SAVE_STATE_MAGIC) else {
+            return Err(SaveStateError::NotASaveState);
+        };
+        let Some((version, state)) = version_and_state.split_first_chunk::<2>() else {
+            return Err(SaveStateError::Corrupt);
+        };
+
+        let version = u16::from_le_bytes(*version);
+        if version != SAVE_STATE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion { version });
+        }
+
+        bincode::deserialize(state).map_err(|_| SaveStateError::Corrupt)
+    }
+}
+
+impl VirtualMachine {
+    /// Takes a snapshot of the VM that can be restored later with `load_state()`.
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            mem: self.mem.clone(),
+            v: self.v,
+            i: self.i,
+            stack: self.stack.clone(),
+            pc: self.pc,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            keypad: self.keypad,
+            keypad_shadow: self.keypad_shadow,
+            keypad_shadow_timers: self.keypad_shadow_timers,
+            fb: self.fb.clone(),
+            planes: self.planes,
+            hires: self.hires,
+            exited: self.exited,
+            rpl_flags: self.rpl_flags,
+            rng: self.rng.clone(),
+            rng_seed: self.rng_seed,
+            frame_time: self.frame_time,
+            waiting_for_vblank: self.waiting_for_vblank,
+            cycles: self.cycles,
+        }
+    }
+
+    /// Restores a snapshot taken by `save_state()`. Recovers from any fault, since the program
+    /// picks up from wherever it was when the snapshot was taken. The VM is left untouched if the
+    /// snapshot doesn't fit it, like one taken on a platform with a different amount of memory.
+    pub fn load_state(&mut self, state: &SaveState) -> Result<(), SaveStateError> {
+        let (width, height) = if state.hires {
+            HIRES_RESOLUTION
+        } else {
+            LORES_RESOLUTION
+        };
+
+        if state.mem.len() != self.mem.len()
+            || state.fb.len() != width * height
+            || state.stack.len() > STACK_DEPTH
+        {
+            return Err(SaveStateError::Corrupt);
+        }
+
+        self.mem.copy_from_slice(&state.mem);
+        self.v = state.v;
+        self.i = state.i;
+        self.stack.clone_from(&state.stack);
+        self.pc = state.pc;
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.audio_pattern = state.audio_pattern;
+        self.pitch = state.pitch;
+        self.keypad = state.keypad;
+        self.keypad_shadow = state.keypad_shadow;
+        self.keypad_shadow_timers = state.keypad_shadow_timers;
+        self.fb.clone_from(&state.fb);
+        self.planes = state.planes;
+        self.hires = state.hires;
+        self.exited = state.exited;
+        self.rpl_flags = state.rpl_flags;
+        self.rng = state.rng.clone();
+        self.rng_seed = state.rng_seed;
+        self.frame_time = state.frame_time;
+        self.waiting_for_vblank = state.waiting_for_vblank;
+        self.cycles = state.cycles;
+
+        // Whatever was going on before is gone, so recover from any fault and redraw the screen.
+        self.opcode = 0;
+        self.fault = None;
+        self.watchpoint_hits.clear();
+        self.draw_flag = true;
+
+        Ok(())
+    }
+}
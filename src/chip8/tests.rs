@@ -0,0 +1,947 @@
+use super::*;
+use crate::SaveStateError;
+
+/// Settings with no frame time budget to worry about, so every step executes exactly one
+/// operation, and a fixed seed so CXNN is reproducible.
+fn test_settings() -> Chip8Settings {
+    Chip8Settings {
+        execution_speed_multiple: 0.0,
+        display_wait_quirk: Some(false),
+        rng_seed: Some(0),
+        ..Chip8Settings::default()
+    }
+}
+
+/// Creates a VM with the opcodes loaded as a program starting at 0x200.
+pub(crate) fn vm_with(settings: &Chip8Settings, opcodes: &[u16]) -> VirtualMachine {
+    let program: Vec<u8> = opcodes
+        .iter()
+        .flat_map(|opcode| opcode.to_be_bytes())
+        .collect();
+
+    let mut vm = VirtualMachine::new(settings);
+    vm.load_rom(&program).unwrap();
+    vm
+}
+
+/// Executes a number of operations, failing the test if any of them fault or don't execute.
+fn run(vm: &mut VirtualMachine, operations: usize) {
+    for _ in 0..operations {
+        assert!(vm.step().unwrap().is_some());
+    }
+}
+
+//-----------------------------------------------
+// Faults
+//-----------------------------------------------
+#[test]
+fn unknown_opcode_faults_until_reset() {
+    let mut vm = vm_with(&test_settings(), &[0xe0ff]);
+    let error = Chip8Error::UnknownOpcode {
+        pc: 0x200,
+        opcode: 0xe0ff,
+    };
+
+    assert_eq!(vm.step(), Err(error));
+    assert_eq!(vm.fault(), Some(error));
+    assert_eq!(vm.step(), Err(error));
+
+    vm.reset();
+    assert_eq!(vm.fault(), None);
+    assert_eq!(vm.program_counter(), 0x200);
+}
+
+#[test]
+fn return_with_empty_stack_faults() {
+    let mut vm = vm_with(&test_settings(), &[0x00ee]);
+
+    assert_eq!(
+        vm.step(),
+        Err(Chip8Error::StackUnderflow {
+            pc: 0x200,
+            opcode: 0x00ee
+        })
+    );
+}
+
+#[test]
+fn call_with_full_stack_faults() {
+    // Calls itself forever.
+    let mut vm = vm_with(&test_settings(), &[0x2200]);
+    run(&mut vm, 16);
+
+    assert_eq!(
+        vm.step(),
+        Err(Chip8Error::StackOverflow {
+            pc: 0x200,
+            opcode: 0x2200
+        })
+    );
+}
+
+#[test]
+fn memory_access_out_of_range_faults() {
+    for (platform, last_address) in [(Platform::CosmacVip, 0x0fff), (Platform::XoChip, 0xffff)] {
+        let settings = Chip8Settings {
+            platform,
+            ..test_settings()
+        };
+        // I = the last address, then FX33 tries to write past the end of memory.
+        let mut vm = vm_with(&settings, &[0xf000, last_address, 0xf233]);
+        run(&mut vm, 1);
+
+        assert_eq!(
+            vm.step(),
+            Err(Chip8Error::MemoryOutOfRange {
+                pc: 0x204,
+                opcode: 0xf233,
+                address: last_address as usize + 1
+            })
+        );
+    }
+}
+
+#[test]
+fn rom_too_large_is_rejected() {
+    for platform in Platform::ALL {
+        let mut vm = VirtualMachine::new(&Chip8Settings {
+            platform,
+            ..test_settings()
+        });
+        let program_space = platform.memory_size() - PROGRAM_START;
+
+        assert_eq!(vm.load_rom(&vec![0; program_space]), Ok(()));
+        assert_eq!(
+            vm.load_rom(&vec![0; program_space + 1]),
+            Err(Chip8Error::RomTooLarge {
+                size: program_space + 1,
+                max: program_space
+            })
+        );
+    }
+}
+
+//-----------------------------------------------
+// Flow control
+//-----------------------------------------------
+#[test]
+fn call_and_return() {
+    // 0x200: call 0x206, 0x202: V1 = 1, 0x204: loop, 0x206: V0 = 5 and return.
+    let mut vm = vm_with(&test_settings(), &[0x2206, 0x6101, 0x1204, 0x6005, 0x00ee]);
+
+    run(&mut vm, 2);
+    assert_eq!(vm.stack(), &[0x200]);
+
+    run(&mut vm, 2);
+    assert_eq!(vm.stack(), &[] as &[u16]);
+    assert_eq!(vm.registers()[0], 5);
+    assert_eq!(vm.registers()[1], 1);
+    assert_eq!(vm.program_counter(), 0x204);
+}
+
+#[test]
+fn jump_to_nnn() {
+    let mut vm = vm_with(&test_settings(), &[0x1abc]);
+    run(&mut vm, 1);
+
+    assert_eq!(vm.program_counter(), 0xabc);
+}
+
+#[test]
+fn jump_to_v0_plus_nnn_with_and_without_quirk() {
+    for (jump_offset_quirk, expected_pc) in [(false, 0x312), (true, 0x315)] {
+        let settings = Chip8Settings {
+            jump_offset_quirk: Some(jump_offset_quirk),
+            ..test_settings()
+        };
+        let mut vm = vm_with(&settings, &[0x6002, 0x6305, 0xb310]);
+        run(&mut vm, 3);
+
+        assert_eq!(vm.program_counter(), expected_pc);
+    }
+}
+
+#[test]
+fn skips() {
+    // V0 = 5 and V1 = 5 (V2 = 0), then the skip. If it skips, V2 stays 0 and V3 is set instead.
+    let cases = [
+        (0x3005, true),
+        (0x3006, false),
+        (0x4005, false),
+        (0x4006, true),
+        (0x5010, true),
+        (0x5020, false),
+        (0x9010, false),
+        (0x9020, true),
+    ];
+
+    for (skip_opcode, skipped) in cases {
+        let mut vm = vm_with(
+            &test_settings(),
+            &[0x6005, 0x6105, skip_opcode, 0x6201, 0x6302],
+        );
+        run(&mut vm, 4);
+
+        assert_eq!(vm.registers()[2] == 0, skipped, "{skip_opcode:#06x}");
+        assert_eq!(vm.registers()[3] == 2, skipped, "{skip_opcode:#06x}");
+    }
+}
+
+#[test]
+fn skip_steps_over_long_load() {
+    // V0 == 0 so F000 NNNN is skipped as a whole instead of executing NNNN as an opcode.
+    let mut vm = vm_with(&test_settings(), &[0x3000, 0xf000, 0x1234, 0x6101]);
+    run(&mut vm, 2);
+
+    assert_eq!(vm.registers()[1], 1);
+    assert_eq!(vm.index_register(), 0);
+}
+
+#[test]
+fn skip_if_key_pressed_and_not_pressed() {
+    for (skip_opcode, pressed, skipped) in [
+        (0xe09e, true, true),
+        (0xe09e, false, false),
+        (0xe0a1, true, false),
+        (0xe0a1, false, true),
+    ] {
+        let mut vm = vm_with(&test_settings(), &[0x6005, skip_opcode, 0x6101, 0x6202]);
+        vm.set_key(5, pressed);
+        run(&mut vm, 3);
+
+        assert_eq!(vm.registers()[1] == 0, skipped, "{skip_opcode:#06x}");
+    }
+}
+
+//-----------------------------------------------
+// Registers and arithmetic
+//-----------------------------------------------
+#[test]
+fn set_and_add_nn() {
+    // 7XNN wraps around without touching the carry flag.
+    let mut vm = vm_with(&test_settings(), &[0x60ff, 0x7002]);
+    run(&mut vm, 2);
+
+    assert_eq!(vm.registers()[0], 1);
+    assert_eq!(vm.registers()[0xf], 0);
+}
+
+#[test]
+fn clone_vy_into_vx() {
+    let mut vm = vm_with(&test_settings(), &[0x6142, 0x8010]);
+    run(&mut vm, 2);
+
+    assert_eq!(vm.registers()[0], 0x42);
+}
+
+#[test]
+fn or_and_xor_with_and_without_quirk() {
+    for (opcode, expected) in [(0x8011, 0b1110), (0x8012, 0b1000), (0x8013, 0b0110)] {
+        for or_and_xor_quirk in [false, true] {
+            let settings = Chip8Settings {
+                or_and_xor_quirk: Some(or_and_xor_quirk),
+                ..test_settings()
+            };
+            let mut vm = vm_with(&settings, &[0x600c, 0x610a, 0x6f07, opcode]);
+            run(&mut vm, 4);
+
+            assert_eq!(vm.registers()[0], expected, "{opcode:#06x}");
+            assert_eq!(
+                vm.registers()[0xf],
+                if or_and_xor_quirk { 0 } else { 7 },
+                "{opcode:#06x}"
+            );
+        }
+    }
+}
+
+#[test]
+fn add_sets_carry_flag() {
+    for (vx, vy, result, flag) in [
+        (0x10, 0x20, 0x30, 0),
+        (0xff, 0x01, 0x00, 1),
+        (0x80, 0x80, 0x00, 1),
+    ] {
+        let mut vm = vm_with(&test_settings(), &[0x6000 | vx, 0x6100 | vy, 0x8014]);
+        run(&mut vm, 3);
+
+        assert_eq!(vm.registers()[0], result);
+        assert_eq!(vm.registers()[0xf], flag);
+    }
+}
+
+#[test]
+fn subtract_vy_from_vx_sets_not_borrow_flag() {
+    for (vx, vy, result, flag) in [(5, 3, 2, 1), (3, 5, 0xfe, 0), (5, 5, 0, 1)] {
+        let mut vm = vm_with(&test_settings(), &[0x6000 | vx, 0x6100 | vy, 0x8015]);
+        run(&mut vm, 3);
+
+        assert_eq!(vm.registers()[0], result);
+        assert_eq!(vm.registers()[0xf], flag);
+    }
+}
+
+#[test]
+fn subtract_vx_from_vy_sets_not_borrow_flag() {
+    for (vx, vy, result, flag) in [(3, 5, 2, 1), (5, 3, 0xfe, 0), (5, 5, 0, 1)] {
+        let mut vm = vm_with(&test_settings(), &[0x6000 | vx, 0x6100 | vy, 0x8017]);
+        run(&mut vm, 3);
+
+        assert_eq!(vm.registers()[0], result);
+        assert_eq!(vm.registers()[0xf], flag);
+    }
+}
+
+#[test]
+fn flag_overwrites_result_when_vf_is_vx() {
+    // VF = 0xff + 1 overflows, so VF ends up as the carry flag rather than the result.
+    let mut vm = vm_with(&test_settings(), &[0x6fff, 0x6101, 0x8f14]);
+    run(&mut vm, 3);
+
+    assert_eq!(vm.registers()[0xf], 1);
+}
+
+#[test]
+fn shifts_with_and_without_quirk() {
+    // V0 = 0x40 and V1 = 0x81 before each shift.
+    let cases = [
+        (0x8016, false, 0x40, 1),
+        (0x8016, true, 0x20, 0),
+        (0x801e, false, 0x02, 1),
+        (0x801e, true, 0x80, 0),
+    ];
+
+    for (opcode, shift_quirk, result, flag) in cases {
+        let settings = Chip8Settings {
+            shift_quirk: Some(shift_quirk),
+            ..test_settings()
+        };
+        let mut vm = vm_with(&settings, &[0x6040, 0x6181, opcode]);
+        run(&mut vm, 3);
+
+        assert_eq!(vm.registers()[0], result, "{opcode:#06x} {shift_quirk}");
+        assert_eq!(vm.registers()[0xf], flag, "{opcode:#06x} {shift_quirk}");
+    }
+}
+
+#[test]
+fn random_and_nn_is_masked_and_reproducible() {
+    let program = [0xc0ff, 0xc10f, 0xc2f0];
+    let mut vm = vm_with(&test_settings(), &program);
+    let mut same_seed_vm = vm_with(&test_settings(), &program);
+    run(&mut vm, 3);
+    run(&mut same_seed_vm, 3);
+
+    assert_eq!(vm.registers(), same_seed_vm.registers());
+    assert_eq!(vm.registers()[1] & 0xf0, 0);
+    assert_eq!(vm.registers()[2] & 0x0f, 0);
+
+    // A reset starts the random numbers over.
+    let registers = vm.registers();
+    vm.reset();
+    run(&mut vm, 3);
+
+    assert_eq!(vm.registers(), registers);
+}
+
+//-----------------------------------------------
+// Timers and input
+//-----------------------------------------------
+#[test]
+fn timers() {
+    let mut vm = vm_with(&test_settings(), &[0x6010, 0xf015, 0xf018, 0xf107]);
+    run(&mut vm, 4);
+
+    assert_eq!(vm.delay_timer(), 0x10);
+    assert_eq!(vm.sound_timer, 0x10);
+    assert_eq!(vm.registers()[1], 0x10);
+
+    vm.tick_timers();
+    assert_eq!(vm.delay_timer(), 0x0f);
+    assert_eq!(vm.sound_timer, 0x0f);
+}
+
+#[test]
+fn store_keypress_waits_for_press_and_release() {
+    let mut vm = vm_with(&test_settings(), &[0xf30a]);
+
+    run(&mut vm, 1);
+    assert_eq!(vm.program_counter(), 0x200);
+
+    vm.set_key(7, true);
+    run(&mut vm, 1);
+    assert_eq!(vm.program_counter(), 0x200);
+
+    vm.set_key(7, false);
+    run(&mut vm, 1);
+    assert_eq!(vm.program_counter(), 0x202);
+    assert_eq!(vm.registers()[3], 7);
+}
+
+//-----------------------------------------------
+// Index register and memory
+//-----------------------------------------------
+#[test]
+fn set_and_add_to_i() {
+    let mut vm = vm_with(&test_settings(), &[0xa010, 0x6005, 0xf01e]);
+    run(&mut vm, 3);
+
+    assert_eq!(vm.index_register(), 0x015);
+}
+
+#[test]
+fn font_sprite_locations() {
+    let mut vm = vm_with(&test_settings(), &[0x600a, 0xf029]);
+    run(&mut vm, 2);
+    assert_eq!(vm.index_register(), 0x050 + 0xa * 5);
+
+    let mut vm = vm_with(&test_settings(), &[0x600a, 0xf030]);
+    run(&mut vm, 2);
+    assert_eq!(vm.index_register(), 0x050 + 80 + 0xa * 10);
+    assert_eq!(
+        &vm.memory()[vm.index_register() as usize..][..10],
+        &BIG_FONT_DATA[100..110]
+    );
+}
+
+#[test]
+fn bcd_vx() {
+    let mut vm = vm_with(&test_settings(), &[0x60fe, 0xa300, 0xf033]);
+    run(&mut vm, 3);
+
+    assert_eq!(&vm.memory()[0x300..0x303], &[2, 5, 4]);
+    assert_eq!(vm.index_register(), 0x300);
+}
+
+#[test]
+fn dump_registers_with_and_without_mem_quirk() {
+    for (mem_quirk, expected_i) in [(false, 0x300), (true, 0x303)] {
+        let settings = Chip8Settings {
+            mem_quirk: Some(mem_quirk),
+            ..test_settings()
+        };
+        let mut vm = vm_with(&settings, &[0x6001, 0x6102, 0x6203, 0x6304, 0xa300, 0xf255]);
+        run(&mut vm, 6);
+
+        assert_eq!(&vm.memory()[0x300..0x304], &[1, 2, 3, 0]);
+        assert_eq!(vm.index_register(), expected_i);
+    }
+}
+
+#[test]
+fn load_registers_with_and_without_mem_quirk() {
+    for (mem_quirk, expected_i) in [(false, 0x300), (true, 0x303)] {
+        let settings = Chip8Settings {
+            mem_quirk: Some(mem_quirk),
+            ..test_settings()
+        };
+        let mut vm = vm_with(&settings, &[0xa300, 0xf265]);
+        vm.memory_mut()[0x300..0x304].copy_from_slice(&[9, 8, 7, 6]);
+        run(&mut vm, 2);
+
+        assert_eq!(&vm.registers()[..4], &[9, 8, 7, 0]);
+        assert_eq!(vm.index_register(), expected_i);
+    }
+}
+
+#[test]
+fn mem_increment_by_x_quirk_leaves_i_on_the_last_register() {
+    let settings = Chip8Settings {
+        mem_quirk: Some(true),
+        mem_increment_by_x_quirk: Some(true),
+        ..test_settings()
+    };
+    let mut vm = vm_with(&settings, &[0xa300, 0xf255, 0xf265]);
+
+    run(&mut vm, 2);
+    assert_eq!(vm.index_register(), 0x302);
+    run(&mut vm, 1);
+    assert_eq!(vm.index_register(), 0x304);
+}
+
+//-----------------------------------------------
+// Display
+//-----------------------------------------------
+#[test]
+fn draw_sprite_and_collision() {
+    // Draws the font's "0" at (0, 0) twice.
+    let mut vm = vm_with(&test_settings(), &[0x6000, 0xf029, 0xd005, 0xd005]);
+
+    run(&mut vm, 3);
+    assert_eq!(&vm.framebuffer()[..5], &[1, 1, 1, 1, 0]);
+    assert_eq!(&vm.framebuffer()[64..69], &[1, 0, 0, 1, 0]);
+    assert_eq!(vm.registers()[0xf], 0);
+
+    run(&mut vm, 1);
+    assert!(vm.framebuffer().iter().all(|pixel| *pixel == 0));
+    assert_eq!(vm.registers()[0xf], 1);
+}
+
+#[test]
+fn clear_display() {
+    let mut vm = vm_with(&test_settings(), &[0x00e0]);
+    vm.fb[100] = 1;
+    run(&mut vm, 1);
+
+    assert!(vm.framebuffer().iter().all(|pixel| *pixel == 0));
+}
+
+#[test]
+fn draw_sprite_with_and_without_clipping() {
+    // The top row of the font's "0" is 4 pixels wide and starts 2 pixels from the right edge.
+    for clipping_quirk in [true, false] {
+        let settings = Chip8Settings {
+            clipping_quirk: Some(clipping_quirk),
+            ..test_settings()
+        };
+        let mut vm = vm_with(&settings, &[0x6000, 0xf029, 0x603e, 0x6100, 0xd015]);
+        run(&mut vm, 5);
+
+        assert_eq!(&vm.framebuffer()[62..64], &[1, 1]);
+        let wrapped = if clipping_quirk { [0, 0] } else { [1, 1] };
+        assert_eq!(&vm.framebuffer()[..2], &wrapped);
+    }
+}
+
+#[test]
+fn draw_sprite_with_and_without_start_wrapping() {
+    // X = 66 is off screen, so it either wraps to X = 2 or nothing is drawn at all.
+    for sprite_wrapping_quirk in [true, false] {
+        let settings = Chip8Settings {
+            sprite_wrapping_quirk: Some(sprite_wrapping_quirk),
+            ..test_settings()
+        };
+        let mut vm = vm_with(&settings, &[0x6000, 0xf029, 0x6042, 0x6100, 0xd015]);
+        run(&mut vm, 5);
+
+        let drawn = if sprite_wrapping_quirk { 1 } else { 0 };
+        assert_eq!(vm.framebuffer()[2], drawn);
+    }
+}
+
+#[test]
+fn draw_sprite_waits_for_vblank_with_display_wait_quirk() {
+    let settings = Chip8Settings {
+        display_wait_quirk: Some(true),
+        ..test_settings()
+    };
+    let mut vm = vm_with(&settings, &[0x6000, 0xf029, 0xd005, 0x6105]);
+    run(&mut vm, 3);
+
+    assert_eq!(vm.step(), Ok(None));
+    assert_eq!(vm.registers()[1], 0);
+
+    vm.tick_timers();
+    run(&mut vm, 1);
+    assert_eq!(vm.registers()[1], 5);
+}
+
+#[test]
+fn draw_sprite_only_waits_in_low_resolution_without_hires_display_wait_quirk() {
+    let settings = Chip8Settings {
+        display_wait_quirk: Some(true),
+        hires_display_wait_quirk: Some(false),
+        ..test_settings()
+    };
+    let mut vm = vm_with(&settings, &[0x00ff, 0xd005, 0x00fe, 0xd005, 0x6105]);
+    run(&mut vm, 4);
+
+    assert_eq!(vm.step(), Ok(None));
+    vm.tick_timers();
+    run(&mut vm, 1);
+    assert_eq!(vm.registers()[1], 5);
+}
+
+//-----------------------------------------------
+// SUPER-CHIP
+//-----------------------------------------------
+#[test]
+fn resolution_modes() {
+    let mut vm = vm_with(&test_settings(), &[0x00ff, 0x00fe]);
+
+    run(&mut vm, 1);
+    assert_eq!(vm.resolution(), (128, 64));
+    assert_eq!(vm.framebuffer().len(), 128 * 64);
+
+    run(&mut vm, 1);
+    assert_eq!(vm.resolution(), (64, 32));
+    assert_eq!(vm.framebuffer().len(), 64 * 32);
+}
+
+#[test]
+fn scrolling() {
+    let mut vm = vm_with(&test_settings(), &[0x00c2, 0x00fb, 0x00fc, 0x00d1, 0x00d2]);
+    vm.fb[0] = 1;
+
+    run(&mut vm, 1);
+    assert_eq!(vm.framebuffer()[0], 0);
+    assert_eq!(vm.framebuffer()[2 * 64], 1);
+
+    run(&mut vm, 1);
+    assert_eq!(vm.framebuffer()[2 * 64 + 4], 1);
+
+    run(&mut vm, 1);
+    assert_eq!(vm.framebuffer()[2 * 64], 1);
+    assert_eq!(
+        vm.framebuffer().iter().filter(|pixel| **pixel != 0).count(),
+        1
+    );
+
+    // Scrolling up brings it back to the top row and then past it.
+    run(&mut vm, 1);
+    assert_eq!(vm.framebuffer()[64], 1);
+    run(&mut vm, 1);
+    assert!(vm.framebuffer().iter().all(|pixel| *pixel == 0));
+}
+
+#[test]
+fn draw_16_by_16_sprite() {
+    let mut vm = vm_with(&test_settings(), &[0x00ff, 0xa300, 0xd000]);
+    vm.memory_mut()[0x300..0x320].fill(0xff);
+    run(&mut vm, 3);
+
+    assert_eq!(
+        vm.framebuffer().iter().filter(|pixel| **pixel != 0).count(),
+        256
+    );
+    assert_eq!(vm.framebuffer()[15 * 128 + 15], 1);
+}
+
+#[test]
+fn exit_halts_until_reset() {
+    let mut vm = vm_with(&test_settings(), &[0x00fd, 0x6001]);
+    run(&mut vm, 1);
+
+    assert!(vm.exited());
+    assert_eq!(vm.step(), Ok(None));
+    assert_eq!(vm.registers()[0], 0);
+
+    vm.reset();
+    assert!(!vm.exited());
+}
+
+#[test]
+fn rpl_flags_survive_reset() {
+    let mut vm = vm_with(
+        &test_settings(),
+        &[0x6001, 0x6102, 0xf175, 0x6000, 0x6100, 0xf185],
+    );
+    run(&mut vm, 6);
+    assert_eq!(&vm.registers()[..2], &[1, 2]);
+
+    vm.reset();
+    assert_eq!(&vm.rpl_flags()[..2], &[1, 2]);
+}
+
+#[test]
+fn rpl_flags_are_saved_and_loaded() {
+    let path = std::env::temp_dir().join(format!("chip8-rpl-flags-test-{}", std::process::id()));
+    let mut vm = vm_with(&test_settings(), &[]);
+    vm.set_rpl_flags([7; 16]);
+    vm.write_rpl_flags_file(&path).unwrap();
+
+    let mut new_vm = vm_with(&test_settings(), &[]);
+    let loaded = new_vm.read_rpl_flags_file(&path);
+    fs::remove_file(&path).unwrap();
+    loaded.unwrap();
+    assert_eq!(new_vm.rpl_flags(), [7; 16]);
+
+    // A program that never saved any flags keeps the ones it has.
+    new_vm.read_rpl_flags_file(&path).unwrap();
+    assert_eq!(new_vm.rpl_flags(), [7; 16]);
+}
+
+//-----------------------------------------------
+// XO-CHIP
+//-----------------------------------------------
+#[test]
+fn long_load() {
+    let mut vm = vm_with(&test_settings(), &[0xf000, 0xabcd]);
+    run(&mut vm, 1);
+
+    assert_eq!(vm.index_register(), 0xabcd);
+    assert_eq!(vm.program_counter(), 0x204);
+}
+
+#[test]
+fn draw_and_clear_bitplanes() {
+    // Both planes selected: the first plane's row lights pixel 0 and the second lights 0 and 1.
+    let mut vm = vm_with(
+        &test_settings(),
+        &[0xf301, 0xa300, 0x6000, 0xd001, 0xf201, 0x00e0],
+    );
+    vm.memory_mut()[0x300..0x302].copy_from_slice(&[0x80, 0xc0]);
+
+    run(&mut vm, 4);
+    assert_eq!(&vm.framebuffer()[..3], &[3, 2, 0]);
+
+    // Only the second plane selected, so only it gets cleared.
+    run(&mut vm, 2);
+    assert_eq!(&vm.framebuffer()[..3], &[1, 0, 0]);
+}
+
+#[test]
+fn save_and_load_register_ranges() {
+    let mut vm = vm_with(
+        &test_settings(),
+        &[0x6101, 0x6202, 0x6303, 0xa300, 0x5132, 0x5313],
+    );
+    run(&mut vm, 5);
+    assert_eq!(&vm.memory()[0x300..0x304], &[1, 2, 3, 0]);
+
+    // Loading with X > Y fills the registers in reverse order.
+    run(&mut vm, 1);
+    assert_eq!(&vm.registers()[1..4], &[3, 2, 1]);
+    assert_eq!(vm.index_register(), 0x300);
+}
+
+#[test]
+fn audio_pattern_and_pitch() {
+    let mut vm = vm_with(&test_settings(), &[0xa300, 0xf002, 0x6070, 0xf03a]);
+    for (offset, byte) in vm.memory_mut()[0x300..0x310].iter_mut().enumerate() {
+        *byte = offset as u8;
+    }
+    run(&mut vm, 4);
+
+    let expected_pattern: [u8; 16] = std::array::from_fn(|offset| offset as u8);
+    assert_eq!(vm.audio_pattern(), Some(expected_pattern));
+    assert_eq!(vm.audio_playback_rate(), 8000.0);
+}
+
+//-----------------------------------------------
+// Settings
+//-----------------------------------------------
+#[test]
+fn individual_quirks_override_platform() {
+    let settings = Chip8Settings {
+        platform: Platform::XoChip,
+        clipping_quirk: Some(true),
+        ..Chip8Settings::default()
+    };
+
+    let quirks = settings.quirks();
+    assert!(quirks.clipping);
+    assert_eq!(
+        Quirks {
+            clipping: false,
+            ..quirks
+        },
+        Platform::XoChip.quirks()
+    );
+}
+
+#[test]
+fn very_fast_speeds_have_operations_that_take_no_time() {
+    for (execution_speed_multiple, takes_time) in [(1.0, true), (0.04, true), (0.001, false)] {
+        let settings = Chip8Settings {
+            execution_speed_multiple,
+            ..Chip8Settings::default()
+        };
+        assert_eq!(settings.every_operation_takes_time(), takes_time);
+    }
+
+    // LD V0, 0 is one of the quickest operations.
+    let settings = Chip8Settings {
+        execution_speed_multiple: 0.001,
+        ..test_settings()
+    };
+    let mut vm = vm_with(&settings, &[0x6000]);
+    assert_eq!(vm.step(), Ok(Some(Duration::ZERO)));
+}
+
+#[test]
+fn every_platform_has_its_own_quirks() {
+    for (index, platform) in Platform::ALL.iter().enumerate() {
+        for other in &Platform::ALL[index + 1..] {
+            assert_ne!(platform.quirks(), other.quirks(), "{platform} and {other}");
+        }
+    }
+}
+
+//-----------------------------------------------
+// Save states
+//-----------------------------------------------
+#[test]
+fn load_state_restores_everything_a_program_can_change() {
+    // Draws a random sprite somewhere random, over and over.
+    let program = [0xc0ff, 0xc13f, 0xc21f, 0xa300, 0xd125, 0x1200];
+    let mut vm = vm_with(&test_settings(), &program);
+    run(&mut vm, 12);
+
+    let state = SaveState::from_bytes(&vm.save_state().to_bytes()).unwrap();
+    run(&mut vm, 12);
+    let expected_registers = vm.registers();
+    let expected_framebuffer = vm.framebuffer().to_vec();
+    let expected_cycles = vm.cycles();
+
+    vm.load_state(&state).unwrap();
+    run(&mut vm, 12);
+
+    assert_eq!(vm.registers(), expected_registers);
+    assert_eq!(vm.framebuffer(), expected_framebuffer);
+    assert_eq!(vm.cycles(), expected_cycles);
+}
+
+#[test]
+fn load_state_recovers_from_fault() {
+    let mut vm = vm_with(&test_settings(), &[0x6001, 0x00ee]);
+    run(&mut vm, 1);
+    let state = vm.save_state();
+    assert!(vm.step().is_err());
+
+    vm.load_state(&state).unwrap();
+
+    assert_eq!(vm.fault(), None);
+    assert_eq!(vm.program_counter(), 0x202);
+    assert_eq!(vm.registers()[0], 1);
+}
+
+#[test]
+fn save_states_from_other_formats_are_rejected() {
+    let vm = vm_with(&test_settings(), &[0x1200]);
+    let mut bytes = vm.save_state().to_bytes();
+
+    assert_eq!(
+        SaveState::from_bytes(b"not a save state").err(),
+        Some(SaveStateError::NotASaveState)
+    );
+
+    bytes[4] = bytes[4].wrapping_add(1);
+    assert_eq!(
+        SaveState::from_bytes(&bytes).err(),
+        Some(SaveStateError::UnsupportedVersion {
+            version: SAVE_STATE_VERSION + 1
+        })
+    );
+
+    bytes[4] = bytes[4].wrapping_sub(1);
+    assert_eq!(
+        SaveState::from_bytes(&bytes[..16]).err(),
+        Some(SaveStateError::Corrupt)
+    );
+}
+
+//-----------------------------------------------
+// Watchpoints
+//-----------------------------------------------
+#[test]
+fn watchpoints_report_reads_and_writes() {
+    // BCD of 0x42 to 0x300, dump V0-V1 to 0x300, load V0-V1 from 0x300 and draw a sprite from it,
+    // pointing I back at 0x300 each time since the mem quirk moves it.
+    let mut vm = vm_with(
+        &test_settings(),
+        &[
+            0x6042, 0xa300, 0xf033, 0xf155, 0xa300, 0xf165, 0xa300, 0xd001,
+        ],
+    );
+    vm.add_watchpoint("0x300-0x301".parse().unwrap());
+    run(&mut vm, 8);
+
+    let hits: Vec<(u16, u16, MemoryAccess, u8)> = vm
+        .take_watchpoint_hits()
+        .iter()
+        .map(|hit| (hit.opcode, hit.address, hit.access, hit.value))
+        .collect();
+
+    assert_eq!(
+        hits,
+        [
+            (0xf033, 0x300, MemoryAccess::Write, 0),
+            (0xf033, 0x301, MemoryAccess::Write, 6),
+            (0xf155, 0x300, MemoryAccess::Write, 0x42),
+            (0xf155, 0x301, MemoryAccess::Write, 0),
+            (0xf165, 0x300, MemoryAccess::Read, 0x42),
+            (0xf165, 0x301, MemoryAccess::Read, 0),
+            (0xd001, 0x300, MemoryAccess::Read, 0x42),
+        ]
+    );
+    assert!(vm.take_watchpoint_hits().is_empty());
+}
+
+#[test]
+fn watchpoints_only_report_what_they_watch() {
+    let mut vm = vm_with(&test_settings(), &[0xa200, 0xf065, 0xf055]);
+    vm.add_watchpoint("0x200-0x205 w".parse().unwrap());
+    run(&mut vm, 2);
+
+    // Neither executing the program nor reading it is a write.
+    assert!(vm.take_watchpoint_hits().is_empty());
+
+    run(&mut vm, 1);
+    assert_eq!(vm.take_watchpoint_hits().len(), 1);
+
+    assert_eq!(
+        vm.remove_watchpoint(0).map(|w| w.to_string()),
+        Some(String::from("0x200-0x205 w"))
+    );
+    assert!(vm.watchpoints().is_empty());
+}
+
+//-----------------------------------------------
+// Tracing
+//-----------------------------------------------
+/// A trace output the test can still read after handing it to the VM.
+#[derive(Clone, Default)]
+struct SharedOutput(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl std::io::Write for SharedOutput {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(bytes)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedOutput {
+    fn lines(&self) -> Vec<String> {
+        String::from_utf8(self.0.lock().unwrap().clone())
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+}
+
+#[test]
+fn tracer_records_changes_and_vf() {
+    let output = SharedOutput::default();
+    let mut vm = vm_with(&test_settings(), &[0x60ff, 0x6102, 0x8014, 0xa300, 0x1208]);
+    vm.set_tracer(Some(Tracer::new(output.clone())));
+    run(&mut vm, 5);
+
+    assert_eq!(
+        output.lines(),
+        [
+            "       0  0x0200  60ff  LD V0, 0xFF               VF=00  V0=ff",
+            "       1  0x0202  6102  LD V1, 0x02               VF=00  V1=02",
+            "       2  0x0204  8014  ADD V0, V1                VF=01  V0=01",
+            "       3  0x0206  a300  LD I, 0x300               VF=01  I=0300",
+            "       4  0x0208  1208  JP 0x208                  VF=01",
+        ]
+    );
+    assert_eq!(vm.cycles(), 5);
+}
+
+#[test]
+fn tracer_only_records_its_cycles_while_recording() {
+    let output = SharedOutput::default();
+    let mut vm = vm_with(&test_settings(), &[0x7001, 0x1200]);
+    vm.set_tracer(Some(Tracer::new(output.clone()).with_cycles(2..6)));
+    run(&mut vm, 4);
+
+    vm.tracer_mut().unwrap().set_recording(false);
+    run(&mut vm, 4);
+
+    let cycles: Vec<String> = output
+        .lines()
+        .iter()
+        .map(|line| line.split_whitespace().next().unwrap().to_string())
+        .collect();
+    assert_eq!(cycles, ["2", "3"]);
+
+    // Cycles start over with the program.
+    vm.reset();
+    assert_eq!(vm.cycles(), 0);
+}
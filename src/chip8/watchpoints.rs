@@ -0,0 +1,122 @@
+use super::parse_number;
+use std::{fmt, str::FromStr};
+
+/// The ways an operation can access memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryAccess {
+    /// Reading an instruction to execute it. Breakpoints already cover these, so they never
+    /// trigger watchpoints.
+    Fetch,
+    /// Reading data, like sprites for DXYN or registers for FX65.
+    Read,
+    /// Writing data, like FX33 and FX55 do.
+    Write,
+}
+
+/// A range of memory to watch, from `start` to `end` inclusive. Any operation that reads or
+/// writes an address in the range (depending on which are being watched) is reported by the VM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub reads: bool,
+    pub writes: bool,
+}
+
+impl Watchpoint {
+    /// Returns true if the watchpoint should report an access to an address.
+    pub fn matches(&self, address: usize, access: MemoryAccess) -> bool {
+        let watched = match access {
+            MemoryAccess::Fetch => false,
+            MemoryAccess::Read => self.reads,
+            MemoryAccess::Write => self.writes,
+        };
+
+        watched && (self.start as usize..=self.end as usize).contains(&address)
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{:#05x}", self.start)?;
+        } else {
+            write!(f, "{:#05x}-{:#05x}", self.start, self.end)?;
+        }
+
+        match (self.reads, self.writes) {
+            (true, true) => write!(f, " rw"),
+            (true, false) => write!(f, " r"),
+            (false, true) => write!(f, " w"),
+            (false, false) => Ok(()),
+        }
+    }
+}
+
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    /// Parses watchpoints like `0x300`, `0x300-0x30f` or `0x300-0x30f w`. Both reads and writes
+    /// are watched unless just `r` or `w` is given.
+    fn from_str(watchpoint: &str) -> Result<Self, Self::Err> {
+        let (range, accesses) = watchpoint
+            .trim()
+            .split_once(' ')
+            .unwrap_or((watchpoint.trim(), "rw"));
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+
+        let (reads, writes) = match accesses.trim() {
+            "rw" | "wr" => (true, true),
+            "r" => (true, false),
+            "w" => (false, true),
+            accesses => {
+                return Err(format!(
+                    "'{accesses}' isn't an access to watch, expected r, w or rw"
+                ));
+            }
+        };
+
+        let start = parse_number(start.trim())?;
+        let end = parse_number(end.trim())?;
+        if end < start {
+            return Err(format!("the range {range} ends before it starts"));
+        }
+
+        Ok(Self {
+            start,
+            end,
+            reads,
+            writes,
+        })
+    }
+}
+
+/// An access to memory that matched a watchpoint, along with what the VM was doing at the time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchpointHit {
+    /// Address of the instruction that accessed memory.
+    pub pc: u16,
+    pub opcode: u16,
+    /// The index register when the access happened. Almost every access is relative to it.
+    pub i: u16,
+    pub address: u16,
+    pub access: MemoryAccess,
+    /// The byte that was read, or the byte that was written.
+    pub value: u8,
+}
+
+impl fmt::Display for WatchpointHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self.access {
+            MemoryAccess::Fetch => "fetched",
+            MemoryAccess::Read => "read",
+            MemoryAccess::Write => "wrote",
+        };
+
+        write!(
+            f,
+            "opcode {:#06x} at {:#05x} {action} {:#04x} at {:#05x} (I = {:#05x})",
+            self.opcode, self.pc, self.value, self.address, self.i
+        )
+    }
+}
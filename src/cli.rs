@@ -0,0 +1,160 @@
+use chip_8_interpreter::Platform;
+use clap::Parser;
+use std::path::PathBuf;
+use toml::{Table, Value};
+
+/// Width and height of the Chip-8 screen, which --scale multiplies to get the window size.
+const SCREEN_SIZE: (u32, u32) = (64, 32);
+
+/// Command line arguments. Everything except the ROM and the settings file overrides a setting
+/// from the settings file for this run only.
+#[derive(Parser)]
+#[command(
+    about = "Runs a Chip-8 program. Without a program, a browser of the programs in the program \
+             folder from the settings is shown to pick them from."
+)]
+pub(crate) struct Cli {
+    /// Path to the program (ROM or Octo source) to run.
+    pub rom: Option<PathBuf>,
+    /// Settings file to use instead of settings.toml.
+    #[arg(long, value_name = "FILE")]
+    pub settings: Option<PathBuf>,
+    /// Platform the program was written for. Sets all the quirks at once.
+    #[arg(long)]
+    platform: Option<Platform>,
+    /// Multiplied by how long each operation takes. Larger is slower, 0 is as fast as possible.
+    #[arg(long)]
+    speed: Option<f32>,
+    /// Size of each Chip-8 pixel in the window. Sets the window size.
+    #[arg(long)]
+    scale: Option<u32>,
+    /// Foreground color, as #rrggbb or r,g,b.
+    #[arg(long, value_parser = parse_color)]
+    fg: Option<[u8; 3]>,
+    /// Background color, as #rrggbb or r,g,b.
+    #[arg(long, value_parser = parse_color)]
+    bg: Option<[u8; 3]>,
+    /// Turn the sound off.
+    #[arg(long)]
+    mute: bool,
+    /// Start in fullscreen.
+    #[arg(long, overrides_with = "no_fullscreen")]
+    fullscreen: bool,
+    /// Start in a window even if the settings file says fullscreen.
+    #[arg(long, overrides_with = "fullscreen")]
+    no_fullscreen: bool,
+    /// Set any setting, like `--set chip8.shift_quirk=true` or `--set sound.tone=440`. The value
+    /// is written like it would be in the settings file. Can be given more than once.
+    #[arg(long = "set", value_name = "SECTION.FIELD=VALUE", value_parser = parse_override)]
+    overrides: Vec<(String, Value)>,
+}
+
+impl Cli {
+    /// Returns every setting the arguments override, as a table laid out like the settings file.
+    pub fn overrides(&self) -> Table {
+        let mut overrides = Vec::new();
+        overrides.extend(self.overrides.iter().cloned());
+
+        if let Some(platform) = self.platform {
+            overrides.push(setting("chip8.platform", platform.name()));
+        }
+        if let Some(speed) = self.speed {
+            overrides.push(setting("chip8.execution_speed_multiple", speed as f64));
+        }
+        if let Some(scale) = self.scale {
+            let scale = i64::from(scale);
+            overrides.push(setting("window.width", i64::from(SCREEN_SIZE.0) * scale));
+            overrides.push(setting("window.height", i64::from(SCREEN_SIZE.1) * scale));
+        }
+        if let Some(fg) = self.fg {
+            overrides.push(setting("window.foreground_color", color(fg)));
+        }
+        if let Some(bg) = self.bg {
+            overrides.push(setting("window.background_color", color(bg)));
+        }
+        if self.mute {
+            overrides.push(setting("sound.volume", 0.0));
+        }
+        if self.fullscreen {
+            overrides.push(setting("window.fullscreen", true));
+        } else if self.no_fullscreen {
+            overrides.push(setting("window.fullscreen", false));
+        }
+
+        let mut table = Table::new();
+        for (key, value) in overrides {
+            insert(&mut table, &key, value);
+        }
+        table
+    }
+}
+
+fn setting(key: &str, value: impl Into<Value>) -> (String, Value) {
+    (key.to_string(), value.into())
+}
+
+fn color(rgb: [u8; 3]) -> Value {
+    Value::Array(rgb.iter().map(|&channel| Value::from(channel)).collect())
+}
+
+/// Puts a value into a table at a dotted key, making the tables on the way if they're not there.
+fn insert(table: &mut Table, key: &str, value: Value) {
+    match key.split_once('.') {
+        Some((section, rest)) => {
+            let section = table
+                .entry(section)
+                .or_insert_with(|| Value::Table(Table::new()));
+            if !section.is_table() {
+                *section = Value::Table(Table::new());
+            }
+            if let Value::Table(section) = section {
+                insert(section, rest, value);
+            }
+        }
+        None => {
+            table.insert(key.to_string(), value);
+        }
+    }
+}
+
+/// Parses `SECTION.FIELD=VALUE`. Values that aren't valid TOML, like a path without quotes, are
+/// taken as strings.
+fn parse_override(argument: &str) -> Result<(String, Value), String> {
+    let (key, value) = argument
+        .split_once('=')
+        .ok_or_else(|| format!("expected SECTION.FIELD=VALUE, got \"{argument}\""))?;
+    let key = key.trim();
+    if key.is_empty() || key.split('.').any(str::is_empty) {
+        return Err(format!("\"{key}\" isn't a setting name"));
+    }
+
+    let value = value.trim();
+    let value = match format!("value = {value}").parse::<Table>() {
+        Ok(mut parsed) => parsed.remove("value").unwrap_or_else(|| value.into()),
+        Err(_) => value.into(),
+    };
+
+    Ok((key.to_string(), value))
+}
+
+/// Parses a color written as `#rrggbb`, `rrggbb` or `r,g,b`.
+fn parse_color(argument: &str) -> Result<[u8; 3], String> {
+    let invalid = || format!("\"{argument}\" isn't a color, use #rrggbb or r,g,b");
+
+    if argument.contains(',') {
+        let channels: Vec<u8> = argument
+            .split(',')
+            .map(|channel| channel.trim().parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        return channels.try_into().map_err(|_| invalid());
+    }
+
+    let hex = argument.strip_prefix('#').unwrap_or(argument);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let channel =
+        |start: usize| u8::from_str_radix(&hex[start..start + 2], 16).map_err(|_| invalid());
+
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
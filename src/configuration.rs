@@ -1,24 +1,46 @@
-use config::Config;
+use chip_8_interpreter::{
+    Chip8Settings, DatabaseEntry, Platform, ProgramDatabase, rom::extension_platform,
+};
+use config::{Config, FileFormat};
 use serde::{Deserialize, Serialize};
 use std::{
-    ffi::OsStr,
-    fs, io,
+    fs,
     path::{Path, PathBuf},
 };
-use terminal_menu::{TerminalMenuItem, button, label, menu, mut_menu, run};
+use terminal_menu::{TerminalMenuItem, button, label, menu, mut_menu, numeric, run, scroll};
+use toml::{Table, Value};
 
-/// Contains all the settings related to the operation of Chip-8 VM.
-#[derive(Clone, Deserialize, Serialize)]
-pub(crate) struct Chip8Settings {
-    pub shift_quirk: bool,
-    pub or_and_xor_quirk: bool,
-    pub mem_quirk: bool,
-    pub sprite_wrapping_quirk: bool,
-    pub jump_offset_quirk: bool,
-    pub execution_speed_multiple: f32,
-    pub font_memory_starting_location: u16,
-    pub program_folder_path: String,
-}
+/// The settings file used when no other one is given on the command line.
+const SETTINGS_PATH: &str = "settings.toml";
+
+/// The settings sections a program's profile is saved with.
+const PROFILE_SECTIONS: [&str; 3] = ["chip8", "window", "keymap"];
+
+/// The buttons in the keymap settings, which are named the same as in the program database.
+const KEYMAP_BUTTONS: [&str; 6] = ["up", "down", "left", "right", "a", "b"];
+
+/// The window settings for each frame buffer pixel value, background first.
+const COLOR_NAMES: [&str; 4] = [
+    "background_color",
+    "foreground_color",
+    "plane_2_color",
+    "both_planes_color",
+];
+
+/// The quirk settings the tweak menu has a toggle for, in the same order as `quirk_values()`.
+const QUIRK_NAMES: [&str; 9] = [
+    "shift_quirk",
+    "or_and_xor_quirk",
+    "mem_quirk",
+    "mem_increment_by_x_quirk",
+    "sprite_wrapping_quirk",
+    "jump_offset_quirk",
+    "display_wait_quirk",
+    "hires_display_wait_quirk",
+    "clipping_quirk",
+];
+
+const SAVE_PROFILE: &str = "Save as this program's profile and run";
 
 /// Contains all the settings related to the interpreter window.
 #[derive(Deserialize, Serialize)]
@@ -27,11 +49,25 @@ pub(crate) struct WindowSettings {
     pub height: u32,
     pub background_color: [u8; 3],
     pub foreground_color: [u8; 3],
+    // XO-CHIP colors for pixels in only the second bitplane and pixels in both bitplanes. These
+    // have defaults so settings files from before XO-CHIP support still load.
+    #[serde(default = "default_plane_2_color")]
+    pub plane_2_color: [u8; 3],
+    #[serde(default = "default_both_planes_color")]
+    pub both_planes_color: [u8; 3],
     pub fullscreen: bool,
     pub sprite_flicker_filter: bool,
     pub pixel_fade_micros: u64,
 }
 
+fn default_plane_2_color() -> [u8; 3] {
+    [85, 85, 85]
+}
+
+fn default_both_planes_color() -> [u8; 3] {
+    [170, 170, 170]
+}
+
 /// Contains all the settings related to sound.
 #[derive(Deserialize, Serialize)]
 pub(crate) struct SoundSettings {
@@ -39,6 +75,70 @@ pub(crate) struct SoundSettings {
     pub volume: f32,
 }
 
+/// Contains the settings for tracing executed instructions to a file.
+#[derive(Deserialize, Serialize)]
+pub(crate) struct TraceSettings {
+    /// The file traces are written to. It's replaced every time the interpreter starts tracing.
+    pub path: String,
+    /// Trace from the first cycle up to (not including) the second one as soon as the program
+    /// starts. F8 pauses and resumes tracing either way.
+    pub cycles: Option<[u64; 2]>,
+}
+
+impl Default for TraceSettings {
+    fn default() -> Self {
+        Self {
+            path: String::from("trace.log"),
+            cycles: None,
+        }
+    }
+}
+
+/// Extra keys for the keypad keys a program uses the most, like the arrow keys for moving around.
+/// Each one is the keypad key (0x0 to 0xf) it presses, and the program database fills them in for
+/// the programs it knows. The arrow keys are up, down, left and right, Space is a and left Ctrl
+/// is b.
+#[derive(Default, Deserialize, Serialize)]
+pub(crate) struct KeymapSettings {
+    pub up: Option<u8>,
+    pub down: Option<u8>,
+    pub left: Option<u8>,
+    pub right: Option<u8>,
+    pub a: Option<u8>,
+    pub b: Option<u8>,
+}
+
+/// Contains the settings for the local copy of the community program database.
+#[derive(Deserialize, Serialize)]
+pub(crate) struct DatabaseSettings {
+    /// A copy of programs.json from the database. Nothing is looked up if it doesn't exist.
+    pub path: String,
+}
+
+impl Default for DatabaseSettings {
+    fn default() -> Self {
+        Self {
+            path: String::from("chip-8-database.json"),
+        }
+    }
+}
+
+impl DatabaseSettings {
+    /// Loads the program database, or an empty one if there's no file. A broken database only
+    /// gets a warning since the interpreter works fine without it.
+    pub fn load(&self) -> ProgramDatabase {
+        let path = Path::new(&self.path);
+        if !path.exists() {
+            return ProgramDatabase::default();
+        }
+
+        ProgramDatabase::load(path).unwrap_or_else(|error| {
+            eprintln!("Couldn't load the program database: {error}.");
+            ProgramDatabase::default()
+        })
+    }
+}
+
 /// A container that contains all the settings categories. Used for distribution of the appropriate
 /// category of settings to each module.
 #[derive(Deserialize, Serialize)]
@@ -46,18 +146,67 @@ pub(crate) struct Settings {
     pub chip8: Chip8Settings,
     pub window: WindowSettings,
     pub sound: SoundSettings,
+    // These have defaults so settings files from before they were added still load.
+    #[serde(default)]
+    pub trace: TraceSettings,
+    #[serde(default)]
+    pub keymap: KeymapSettings,
+    #[serde(default)]
+    pub database: DatabaseSettings,
 }
 
 impl Settings {
-    /// Loads all the settings in settings.toml into a container and returns it.
-    pub fn load() -> anyhow::Result<Self> {
-        // If settings.toml does not exist in the project root directory, create it.
-        if !fs::exists("settings.toml")? {
-            Self::create_settings_file()?;
+    /// Loads all the settings from a settings file into a container and returns it. A program's
+    /// profile (with what the program database recommends under it) is merged over the file's
+    /// settings and then `overrides` (laid out like the file) over everything else. Without a
+    /// file, settings.toml is used and created if it doesn't exist yet.
+    pub fn load(
+        path: Option<&Path>,
+        profile: Option<&RomProfile>,
+        overrides: &Table,
+    ) -> anyhow::Result<Self> {
+        let path = match path {
+            Some(path) => path,
+            None => {
+                // If settings.toml does not exist in the project root directory, create it.
+                if !fs::exists(SETTINGS_PATH)? {
+                    Self::create_settings_file()?;
+                }
+                Path::new(SETTINGS_PATH)
+            }
+        };
+
+        let mut builder =
+            Config::builder().add_source(config::File::from(path).format(FileFormat::Toml));
+
+        if let Some(profile) = profile {
+            builder = builder.add_source(config::File::from_str(
+                &profile.recommended.to_string(),
+                FileFormat::Toml,
+            ));
+
+            let file: Table = fs::read_to_string(path)?.parse()?;
+            if let Some(Value::Table(rom_settings)) =
+                file.get("roms").and_then(|roms| roms.get(&profile.hash))
+            {
+                builder = builder.add_source(config::File::from_str(
+                    &rom_settings.to_string(),
+                    FileFormat::Toml,
+                ));
+            }
+
+            builder = builder.add_source(
+                config::File::from(profile.path.as_path())
+                    .format(FileFormat::Toml)
+                    .required(false),
+            );
         }
 
-        let settings = Config::builder()
-            .add_source(config::File::with_name("settings"))
+        let settings = builder
+            .add_source(config::File::from_str(
+                &overrides.to_string(),
+                FileFormat::Toml,
+            ))
             .build()?;
 
         // ? operator is not possible here due to the Deserialize trait so ConfigError must be
@@ -74,11 +223,7 @@ impl Settings {
         #[rustfmt::skip]
         let settings_toml = toml::toml! {
             [chip8]
-            shift_quirk = false
-            or_and_xor_quirk = true
-            mem_quirk = true
-            sprite_wrapping_quirk = true
-            jump_offset_quirk = false
+            platform = "cosmac-vip"
             execution_speed_multiple = 1.0
             font_memory_starting_location = 0x050
             program_folder_path = "programs"
@@ -89,113 +234,267 @@ impl Settings {
             fullscreen = false
             background_color = [0, 0, 0]
             foreground_color = [255, 255, 255]
+            plane_2_color = [85, 85, 85]
+            both_planes_color = [170, 170, 170]
             sprite_flicker_filter = true
             pixel_fade_micros = (100)
 
             [sound]
             tone = 330.0
             volume = 0.5
+
+            [trace]
+            path = "trace.log"
+
+            [database]
+            path = "chip-8-database.json"
         }.to_string();
 
-        fs::write("settings.toml", settings_toml)?;
+        fs::write(SETTINGS_PATH, settings_toml)?;
 
         Ok(())
     }
 }
 
-/// Spawns a menu with all the programs in their programs folder. Returns a result containing the
-/// path to the selected program as a String. No idea how this will behave with symbolic links or
-/// a program folder that is located outside the project root folder.
-pub fn ask_for_program(settings: &Chip8Settings) -> anyhow::Result<PathBuf> {
-    // Get the pathbufs of all the files in the program folder and put them in a Vec.
-    let program_folder_path = Path::new(&settings.program_folder_path);
+/// A program's own settings, which are merged over the global ones whenever it's run. They can be
+/// in a `[roms."<sha1>"]` table in the settings file or in a file named after the program's hash
+/// in a profiles folder next to the programs folder, which is where the menu saves them. The
+/// profile file wins when both have a setting, and both win over what the program database
+/// recommends, which wins over the platform the program's extension says it's for.
+pub(crate) struct RomProfile {
+    hash: String,
+    path: PathBuf,
+    /// What the program database and the program's extension recommend, laid out like the
+    /// settings file.
+    recommended: Table,
+}
 
-    // If the programs folder specified in setting.toml doesn't exit, create it.
-    if !fs::exists(program_folder_path)? {
-        fs::create_dir(program_folder_path)?;
+impl RomProfile {
+    pub fn new(
+        settings: &Chip8Settings,
+        program_path: &Path,
+        rom_hash: &str,
+        database: &ProgramDatabase,
+    ) -> Self {
+        let path = Path::new(&settings.program_folder_path)
+            .with_file_name("profiles")
+            .join(format!("{rom_hash}.toml"));
+
+        // Extensions like .sc8 say what platform a program is for, but the database knows better.
+        let mut recommended = Table::new();
+        if let Some(platform) = extension_platform(program_path) {
+            let chip8 = Table::from_iter([("platform".to_string(), platform.name().into())]);
+            recommended.insert("chip8".to_string(), Value::Table(chip8));
+        }
+        if let Some(entry) = database.get(rom_hash) {
+            merge(&mut recommended, &recommended_settings(entry));
+        }
+
+        Self {
+            hash: rom_hash.to_string(),
+            path,
+            recommended,
+        }
     }
 
-    let mut program_pathbufs: Vec<PathBuf> = fs::read_dir(program_folder_path)?
-        .map(|result| result.map(|dir_entry| dir_entry.path()))
-        .collect::<Result<Vec<_>, io::Error>>()?;
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Saves settings (laid out like the settings file) to the profile file on top of whatever it
+    /// has already. Only the chip8, window, and keymap sections are saved.
+    pub fn save(&self, settings: &Table) -> anyhow::Result<()> {
+        let mut profile: Table = if fs::exists(&self.path)? {
+            fs::read_to_string(&self.path)?.parse()?
+        } else {
+            Table::new()
+        };
+
+        let mut saved = Table::new();
+        for section in PROFILE_SECTIONS {
+            if let Some(section_settings) = settings.get(section) {
+                saved.insert(section.to_string(), section_settings.clone());
+            }
+        }
+        merge(&mut profile, &saved);
+
+        if let Some(folder) = self.path.parent() {
+            fs::create_dir_all(folder)?;
+        }
+        fs::write(&self.path, profile.to_string())?;
 
-    // Remove any files that are not Chip-8 programs from the list. This will show any hidden programs.
-    program_pathbufs.retain(|path_buf| {
-        path_buf
-            .to_str()
-            .is_some_and(|file_name| file_name.ends_with(".ch8"))
-    });
+        Ok(())
+    }
+}
 
-    if program_pathbufs.is_empty() {
-        panic!("No programs found in the folder specified in settings.toml.");
+/// Lays out what the program database knows about running a program like the settings file.
+fn recommended_settings(entry: &DatabaseEntry) -> Table {
+    let mut chip8 = Table::new();
+    if let Some(platform) = entry.platform {
+        chip8.insert("platform".to_string(), platform.name().into());
     }
 
-    // Sort this collection so all the rest will be sorted too.
-    program_pathbufs.sort();
+    let quirks = entry.quirks;
+    for (name, quirk) in [
+        ("shift_quirk", quirks.shift_quirk),
+        ("or_and_xor_quirk", quirks.or_and_xor_quirk),
+        ("mem_quirk", quirks.mem_quirk),
+        ("mem_increment_by_x_quirk", quirks.mem_increment_by_x_quirk),
+        ("jump_offset_quirk", quirks.jump_offset_quirk),
+        ("display_wait_quirk", quirks.display_wait_quirk),
+        ("clipping_quirk", quirks.clipping_quirk),
+    ] {
+        if let Some(on) = quirk {
+            chip8.insert(name.to_string(), on.into());
+        }
+    }
 
-    // Convert those pathbufs into &Paths.
-    let program_paths: Vec<&Path> = program_pathbufs
-        .iter()
-        .map(|path_buf| path_buf.as_path())
-        .collect::<Vec<&Path>>();
+    if let Some(speed) = entry.execution_speed_multiple() {
+        chip8.insert(
+            "execution_speed_multiple".to_string(),
+            (speed as f64).into(),
+        );
+    }
 
-    // Convert those &Paths to &OsStrs
-    let program_os_strs: Vec<&OsStr> = program_paths
+    let window: Table = COLOR_NAMES
         .iter()
-        .map(|program_path| {
-            program_path
-                .file_name()
-                .expect("No file exists at the provided program path.")
-        })
+        .zip(&entry.colors)
+        .map(|(name, color)| (name.to_string(), Value::from(color.to_vec())))
         .collect();
 
-    // Convert those &OsStrs to &strs for better readability in the menu.
-    let program_names: Vec<&str> = program_os_strs
+    let keymap: Table = entry
+        .keys
         .iter()
-        .map(|program_os_str| {
-            program_os_str
-                .to_str()
-                .expect("Could not convert program &OsStr to &str")
-        })
-        .map(|with_extension| with_extension.trim_end_matches(".ch8"))
+        .filter(|(button, _)| KEYMAP_BUTTONS.contains(&button.as_str()))
+        .map(|(button, key)| (button.clone(), Value::from(*key)))
         .collect();
 
-    // Convert the program names into menu buttons.
-    let mut program_buttons: Vec<TerminalMenuItem> = program_names
+    [("chip8", chip8), ("window", window), ("keymap", keymap)]
+        .into_iter()
+        .filter(|(_, section)| !section.is_empty())
+        .map(|(name, section)| (name.to_string(), Value::Table(section)))
+        .collect()
+}
+
+/// Copies every setting in `other` into `table`, replacing the ones that are in both. Sections
+/// that are in both are merged the same way.
+pub fn merge(table: &mut Table, other: &Table) {
+    for (key, value) in other {
+        match (table.get_mut(key), value) {
+            (Some(Value::Table(section)), Value::Table(other_section)) => {
+                merge(section, other_section)
+            }
+            _ => {
+                table.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// What was picked in the tweak menu.
+pub(crate) struct Tweaks {
+    /// The settings that were changed, laid out like the settings file.
+    pub settings: Table,
+    /// Whether to save them as the program's profile.
+    pub save: bool,
+}
+
+/// Spawns a menu for changing the platform, quirks and speed a program runs with before it's run.
+/// Only what was changed from `settings` ends up in the returned tweaks.
+pub fn ask_for_tweaks(settings: &Chip8Settings, program_name: &str) -> anyhow::Result<Tweaks> {
+    // Scrolls start on their first value, so put the current one first.
+    let mut platforms: Vec<&str> = Platform::ALL
         .iter()
-        .map(|program_name| button(program_name.to_string()))
+        .map(|platform| platform.name())
         .collect();
+    let current_platform = Platform::ALL
+        .iter()
+        .position(|platform| *platform == settings.platform)
+        .unwrap_or(0);
+    platforms.rotate_left(current_platform);
+
+    let quirk_values = quirk_values(settings);
+    let on_and_off = |on: bool| if on { ["on", "off"] } else { ["off", "on"] };
 
-    // Create the menu instructions labels.
     let mut menu_items: Vec<TerminalMenuItem> = vec![
         label("-----------------------------------------"),
-        label("Select a program to run."),
-        label("Use 'WASD' or arrow keys to navigate,"),
-        label("enter to select, and 'Q' or esc to exit."),
+        label(format!("Settings for {program_name}.")),
+        label("Use left and right to change a setting,"),
+        label("enter to run, and 'Q' or esc to exit."),
         label("-----------------------------------------"),
+        scroll("platform", platforms),
     ];
+    menu_items.extend(
+        QUIRK_NAMES
+            .iter()
+            .zip(quirk_values)
+            .map(|(name, on)| scroll(*name, on_and_off(on))),
+    );
+    menu_items.extend([
+        numeric(
+            "execution_speed_multiple",
+            settings.execution_speed_multiple as f64,
+            Some(0.1),
+            Some(0.0),
+            None,
+        ),
+        button("Run"),
+        button(SAVE_PROFILE),
+    ]);
 
-    let number_of_labels = menu_items.len();
+    let tweak_menu = menu(menu_items);
+    run(&tweak_menu);
 
-    // Combine all menu items into one Vec.
-    menu_items.append(&mut program_buttons);
+    let tweak_menu = mut_menu(&tweak_menu);
+    if tweak_menu.canceled() {
+        return Err(anyhow::Error::msg("User exited the settings menu."));
+    }
+
+    let mut chip8 = Table::new();
 
-    // Create the menu with the program file names as buttons.
-    let pick_program_menu = menu(menu_items);
+    let platform = tweak_menu.selection_value("platform");
+    if platform != settings.platform.name() {
+        chip8.insert("platform".to_string(), platform.into());
+    }
 
-    // Present the menu and le the user choose a program
-    run(&pick_program_menu);
+    // Quirks are only set when they're toggled so the platform still picks the rest.
+    for (name, was_on) in QUIRK_NAMES.iter().zip(quirk_values) {
+        let on = tweak_menu.selection_value(name) == "on";
+        if on != was_on {
+            chip8.insert(name.to_string(), on.into());
+        }
+    }
 
-    if mut_menu(&pick_program_menu).canceled() {
-        return Err(anyhow::Error::msg(
-            "User exited the program selection menu.",
-        ));
+    // Stepping by 0.1 adds up floating point error, so round it back off.
+    let speed = (tweak_menu.numeric_value("execution_speed_multiple") * 100.0).round() / 100.0;
+    if speed as f32 != settings.execution_speed_multiple {
+        chip8.insert("execution_speed_multiple".to_string(), speed.into());
     }
 
-    // Get the selected item index to use on the original Pathbuf Vec.
-    let selection_index = mut_menu(&pick_program_menu).selected_item_index() - number_of_labels;
+    let mut tweaked_settings = Table::new();
+    if !chip8.is_empty() {
+        tweaked_settings.insert("chip8".to_string(), chip8.into());
+    }
+
+    Ok(Tweaks {
+        settings: tweaked_settings,
+        save: tweak_menu.selected_item_name() == SAVE_PROFILE,
+    })
+}
+
+/// The quirks a program runs with, in the same order as `QUIRK_NAMES`.
+fn quirk_values(settings: &Chip8Settings) -> [bool; 9] {
+    let quirks = settings.quirks();
 
-    // Convert the &Pathbuf from the Vec into an owned type (Pathbuf) so it can be passed back
-    // to main(). Yes, path manipulation is terrible.
-    Ok(program_pathbufs[selection_index].to_owned())
+    [
+        quirks.shift,
+        quirks.or_and_xor,
+        quirks.mem,
+        quirks.mem_increment_by_x,
+        quirks.sprite_wrapping,
+        quirks.jump_offset,
+        quirks.display_wait,
+        quirks.hires_display_wait,
+        quirks.clipping,
+    ]
 }
@@ -0,0 +1,328 @@
+//! Lookups in a local copy of the community CHIP-8 program database
+//! (<https://github.com/chip-8/chip-8-database>), which knows the title and authors of thousands
+//! of programs and how to run them, by the SHA-1 hash of the program. Only its `programs.json` is
+//! needed.
+//!
+//! The database has more platforms than the VM, so each one is mapped to the closest platform it
+//! has. Its tick rate (instructions per frame) is turned into an execution speed multiple, which
+//! is only an approximation since the VM times each instruction like a COSMAC VIP would.
+
+use crate::Platform;
+use serde::Deserialize;
+use std::{collections::HashMap, fs, path::Path};
+
+/// Tick rate the VM's timings come out to at an execution speed multiple of 1, about how many
+/// instructions a COSMAC VIP runs per frame.
+const VIP_TICKRATE: f32 = 15.0;
+
+/// What the database knows about a program.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DatabaseEntry {
+    pub title: String,
+    pub authors: Vec<String>,
+    /// The platform the program runs best on, if the VM has it (or something close enough).
+    pub platform: Option<Platform>,
+    pub quirks: QuirkOverrides,
+    /// Instructions per frame.
+    pub tickrate: Option<u32>,
+    /// Colors for frame buffer pixel values 0 (background) to 3, as many as the database has.
+    pub colors: Vec<[u8; 3]>,
+    /// Keypad keys for buttons like `up`, `left` or `a`.
+    pub keys: HashMap<String, u8>,
+}
+
+/// Quirks that a program needs on top of its platform's. None leaves the platform's alone. Named
+/// after the quirk settings in `Chip8Settings`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QuirkOverrides {
+    pub shift_quirk: Option<bool>,
+    pub or_and_xor_quirk: Option<bool>,
+    pub mem_quirk: Option<bool>,
+    pub mem_increment_by_x_quirk: Option<bool>,
+    pub jump_offset_quirk: Option<bool>,
+    pub display_wait_quirk: Option<bool>,
+    pub clipping_quirk: Option<bool>,
+}
+
+impl DatabaseEntry {
+    /// The execution speed multiple that gets about as many instructions per frame as the
+    /// database's tick rate.
+    pub fn execution_speed_multiple(&self) -> Option<f32> {
+        self.tickrate
+            .filter(|tickrate| *tickrate > 0)
+            .map(|tickrate| VIP_TICKRATE / tickrate as f32)
+    }
+
+    /// The title followed by the authors, for showing in lists of programs.
+    pub fn description(&self) -> String {
+        if self.authors.is_empty() {
+            self.title.clone()
+        } else {
+            format!("{} by {}", self.title, self.authors.join(", "))
+        }
+    }
+}
+
+/// The programs in the database by their hash (lowercase hex, see `rom::rom_hash()`).
+#[derive(Default)]
+pub struct ProgramDatabase {
+    entries: HashMap<String, DatabaseEntry>,
+}
+
+impl ProgramDatabase {
+    /// Loads the database from a copy of its programs.json.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        Self::parse(&json)
+            .map_err(|error| anyhow::Error::msg(format!("{}: {error}", path.display())))
+    }
+
+    /// Parses the contents of programs.json.
+    pub fn parse(json: &str) -> Result<Self, serde_json::Error> {
+        let programs: Vec<RawProgram> = serde_json::from_str(json)?;
+
+        let mut entries = HashMap::new();
+        for program in programs {
+            for (hash, rom) in program.roms {
+                entries.insert(
+                    hash.to_ascii_lowercase(),
+                    rom.entry(&program.title, &program.authors),
+                );
+            }
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn get(&self, rom_hash: &str) -> Option<&DatabaseEntry> {
+        self.entries.get(rom_hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[derive(Deserialize)]
+struct RawProgram {
+    title: String,
+    #[serde(default)]
+    authors: Vec<String>,
+    #[serde(default)]
+    roms: HashMap<String, RawRom>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawRom {
+    /// Every platform the program runs on, the best one first.
+    #[serde(default)]
+    platforms: Vec<String>,
+    /// Quirks that differ from the platform's, by platform.
+    #[serde(default)]
+    quirky_platforms: HashMap<String, RawQuirks>,
+    tickrate: Option<u32>,
+    colors: Option<RawColors>,
+    #[serde(default)]
+    keys: HashMap<String, u8>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawQuirks {
+    shift: Option<bool>,
+    memory_increment_by_x: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    wrap: Option<bool>,
+    jump: Option<bool>,
+    vblank: Option<bool>,
+    logic: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct RawColors {
+    /// `#rrggbb` colors, background first.
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+impl RawRom {
+    fn entry(self, title: &str, authors: &[String]) -> DatabaseEntry {
+        // The first platform the VM can run, along with the quirks the platform needs changed to
+        // match the database's.
+        let platform = self
+            .platforms
+            .iter()
+            .find_map(|id| platform(id).map(|(platform, quirks)| (id, platform, quirks)));
+
+        let quirks = match platform {
+            Some((id, _, quirks)) => match self.quirky_platforms.get(id) {
+                Some(raw) => raw.overrides().or(quirks),
+                None => quirks,
+            },
+            None => QuirkOverrides::default(),
+        };
+
+        DatabaseEntry {
+            title: title.to_string(),
+            authors: authors.to_vec(),
+            platform: platform.map(|(_, platform, _)| platform),
+            quirks,
+            tickrate: self.tickrate,
+            colors: self
+                .colors
+                .map(|colors| {
+                    colors
+                        .pixels
+                        .iter()
+                        .filter_map(|color| parse_color(color))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            keys: self.keys,
+        }
+    }
+}
+
+impl QuirkOverrides {
+    /// Takes each quirk that isn't overridden here from `fallback`.
+    fn or(self, fallback: Self) -> Self {
+        Self {
+            shift_quirk: self.shift_quirk.or(fallback.shift_quirk),
+            or_and_xor_quirk: self.or_and_xor_quirk.or(fallback.or_and_xor_quirk),
+            mem_quirk: self.mem_quirk.or(fallback.mem_quirk),
+            mem_increment_by_x_quirk: self
+                .mem_increment_by_x_quirk
+                .or(fallback.mem_increment_by_x_quirk),
+            jump_offset_quirk: self.jump_offset_quirk.or(fallback.jump_offset_quirk),
+            display_wait_quirk: self.display_wait_quirk.or(fallback.display_wait_quirk),
+            clipping_quirk: self.clipping_quirk.or(fallback.clipping_quirk),
+        }
+    }
+}
+
+impl RawQuirks {
+    fn overrides(&self) -> QuirkOverrides {
+        QuirkOverrides {
+            shift_quirk: self.shift,
+            or_and_xor_quirk: self.logic,
+            mem_quirk: self.memory_leave_i_unchanged.map(|unchanged| !unchanged),
+            mem_increment_by_x_quirk: self.memory_increment_by_x,
+            jump_offset_quirk: self.jump,
+            display_wait_quirk: self.vblank,
+            clipping_quirk: self.wrap.map(|wrap| !wrap),
+        }
+    }
+}
+
+/// The VM's platform for one of the database's platform ids, with whatever quirks need to be
+/// changed to make it behave like the database's platform.
+fn platform(id: &str) -> Option<(Platform, QuirkOverrides)> {
+    let platform = match id {
+        "originalChip8" | "hybridVIP" => Platform::CosmacVip,
+        "chip48" => Platform::Chip48,
+        "superchip1" => Platform::Schip11,
+        "superchip" => Platform::SchipModern,
+        "xochip" => Platform::XoChip,
+        // Modern CHIP-8 behaves just like XO-CHIP except that sprites are cut off at the edges.
+        "modernChip8" => {
+            return Some((
+                Platform::XoChip,
+                QuirkOverrides {
+                    clipping_quirk: Some(true),
+                    ..QuirkOverrides::default()
+                },
+            ));
+        }
+        _ => return None,
+    };
+
+    Some((platform, QuirkOverrides::default()))
+}
+
+/// Parses a `#rrggbb` color.
+fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |start: usize| u8::from_str_radix(&hex[start..start + 2], 16).ok();
+
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAMS_JSON: &str = r##"[
+        {
+            "title": "Breakout",
+            "authors": ["Carmelo Cortez"],
+            "release": "1979",
+            "roms": {
+                "A1B2C3D4E5F60718293A4B5C6D7E8F9012345678": {
+                    "file": "breakout.ch8",
+                    "platforms": ["megachip8", "originalChip8", "modernChip8"],
+                    "quirkyPlatforms": {
+                        "originalChip8": { "shift": true, "vblank": false }
+                    },
+                    "tickrate": 30,
+                    "colors": { "pixels": ["#000000", "#ffcc00"], "buzzer": "#990000" },
+                    "keys": { "left": 4, "right": 6 }
+                }
+            }
+        },
+        {
+            "title": "Untitled",
+            "roms": {
+                "0000000000000000000000000000000000000000": { "platforms": ["modernChip8"] }
+            }
+        }
+    ]"##;
+
+    #[test]
+    fn finds_programs_by_hash() {
+        let database = ProgramDatabase::parse(PROGRAMS_JSON).unwrap();
+        assert_eq!(database.len(), 2);
+
+        let breakout = database
+            .get("a1b2c3d4e5f60718293a4b5c6d7e8f9012345678")
+            .unwrap();
+        assert_eq!(breakout.description(), "Breakout by Carmelo Cortez");
+        assert_eq!(breakout.platform, Some(Platform::CosmacVip));
+        assert_eq!(
+            breakout.quirks,
+            QuirkOverrides {
+                shift_quirk: Some(true),
+                display_wait_quirk: Some(false),
+                ..QuirkOverrides::default()
+            }
+        );
+        assert_eq!(breakout.execution_speed_multiple(), Some(0.5));
+        assert_eq!(breakout.colors, vec![[0, 0, 0], [0xff, 0xcc, 0x00]]);
+        assert_eq!(breakout.keys.get("left"), Some(&4));
+
+        assert_eq!(
+            database.get("ffffffffffffffffffffffffffffffffffffffff"),
+            None
+        );
+    }
+
+    #[test]
+    fn maps_platforms_the_vm_doesnt_have() {
+        let database = ProgramDatabase::parse(PROGRAMS_JSON).unwrap();
+        let untitled = database
+            .get("0000000000000000000000000000000000000000")
+            .unwrap();
+
+        assert_eq!(untitled.description(), "Untitled");
+        assert_eq!(untitled.platform, Some(Platform::XoChip));
+        assert_eq!(untitled.quirks.clipping_quirk, Some(true));
+        assert_eq!(untitled.execution_speed_multiple(), None);
+    }
+}
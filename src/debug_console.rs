@@ -0,0 +1,234 @@
+use chip_8_interpreter::{
+    Chip8Error, VirtualMachine,
+    chip8::Watchpoint,
+    debugger::{Breakpoint, Debugger},
+};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use std::{
+    str::FromStr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// How often the reading thread checks whether it should stop while nothing is being typed.
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+const HELP: &str = "\
+Debugger commands:
+  p, pause              stop the program before its next instruction
+  c, continue           run until the next breakpoint
+  s, step               execute one instruction
+  n, next               execute one instruction, running a whole subroutine if it's a CALL
+  b, break <breakpoint> add a breakpoint, like 'b 0x204', 'b 0x204 if v3 == 5' or 'b if i >= 0x300'
+  d, delete <number>    remove a breakpoint by its number in the breakpoint list
+  w, watch <watchpoint> stop after memory is read or written, like 'w 0x300', 'w 0x300-0x30f w'
+                        (only writes) or 'w 0x300 r' (only reads)
+  u, unwatch <number>   remove a watchpoint by its number in the watchpoint list
+  l, list               list the breakpoints and watchpoints
+  v, view               show the registers, stack, timers and instructions around the PC
+  h, help               show this again";
+
+/// Everything the debugger can be told to do, either by typing into the terminal or with the
+/// debugger keys in the window.
+pub(crate) enum DebugCommand {
+    PauseOrContinue,
+    Pause,
+    Continue,
+    Step,
+    StepOver,
+    Break(Breakpoint),
+    Delete(usize),
+    Watch(Watchpoint),
+    Unwatch(usize),
+    List,
+    View,
+    Help,
+}
+
+impl FromStr for DebugCommand {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let (command, argument) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+
+        match command {
+            "p" | "pause" => Ok(Self::Pause),
+            "c" | "continue" => Ok(Self::Continue),
+            "s" | "step" => Ok(Self::Step),
+            "n" | "next" => Ok(Self::StepOver),
+            "b" | "break" => argument.parse().map(Self::Break),
+            "d" | "delete" => argument
+                .trim()
+                .parse()
+                .map(Self::Delete)
+                .map_err(|_| format!("'{argument}' isn't a breakpoint number")),
+            "w" | "watch" => argument.parse().map(Self::Watch),
+            "u" | "unwatch" => argument
+                .trim()
+                .parse()
+                .map(Self::Unwatch)
+                .map_err(|_| format!("'{argument}' isn't a watchpoint number")),
+            "l" | "list" => Ok(Self::List),
+            "v" | "view" => Ok(Self::View),
+            "h" | "help" => Ok(Self::Help),
+            _ => Err(format!("unknown command '{command}', type 'h' for help")),
+        }
+    }
+}
+
+/// Reads debugger commands typed into the terminal the interpreter was started from. Reading
+/// happens on its own thread so the window keeps running while nothing is being typed. The thread
+/// stops when the console is dropped so the program browser can have the terminal back.
+pub(crate) struct DebugConsole {
+    lines: Receiver<String>,
+    stop: Arc<AtomicBool>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl DebugConsole {
+    pub fn start() -> Self {
+        let (sender, lines) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        // The terminal still echoes and edits lines itself, so the keys only come through once
+        // Enter is pressed and they just have to be put back together into the line. Waiting for
+        // keys with a timeout instead of reading stdin is what lets the thread stop.
+        let reader = thread::spawn({
+            let stop = Arc::clone(&stop);
+            move || {
+                let mut line = String::new();
+
+                while !stop.load(Ordering::Relaxed) {
+                    if !event::poll(STOP_CHECK_INTERVAL).unwrap_or(false) {
+                        continue;
+                    }
+                    let Ok(Event::Key(key)) = event::read() else {
+                        continue;
+                    };
+                    if key.kind == KeyEventKind::Release {
+                        continue;
+                    }
+
+                    match key.code {
+                        KeyCode::Char(character) => line.push(character),
+                        KeyCode::Backspace => {
+                            line.pop();
+                        }
+                        KeyCode::Enter if sender.send(std::mem::take(&mut line)).is_err() => {
+                            break;
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        });
+
+        Self {
+            lines,
+            stop,
+            reader: Some(reader),
+        }
+    }
+
+    /// Returns the commands typed since the last call. Lines that aren't commands are reported
+    /// and skipped.
+    pub fn commands(&self) -> Vec<DebugCommand> {
+        self.lines
+            .try_iter()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| line.parse().inspect_err(|error| println!("{error}")).ok())
+            .collect()
+    }
+}
+
+impl Drop for DebugConsole {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+    }
+}
+
+/// Carries out a debugger command and prints the result. Returns the error if stepping the
+/// program made it fault.
+pub(crate) fn execute(
+    command: DebugCommand,
+    debugger: &mut Debugger,
+    vm: &mut VirtualMachine,
+) -> Result<(), Chip8Error> {
+    match command {
+        DebugCommand::PauseOrContinue if debugger.paused() => {
+            return execute(DebugCommand::Continue, debugger, vm);
+        }
+        DebugCommand::PauseOrContinue | DebugCommand::Pause => {
+            debugger.pause();
+            println!("{}\n", debugger.view(vm));
+        }
+        DebugCommand::Continue => {
+            debugger.resume(vm)?;
+            println!("Continuing.");
+        }
+        DebugCommand::Step => {
+            let result = debugger.step(vm);
+            show_step(debugger, vm);
+            result?;
+        }
+        DebugCommand::StepOver => {
+            let result = debugger.step_over(vm);
+            if debugger.paused() {
+                show_step(debugger, vm);
+            }
+            result?;
+        }
+        DebugCommand::Break(breakpoint) => {
+            debugger.add_breakpoint(breakpoint);
+            println!(
+                "Breakpoint {}: {breakpoint}",
+                debugger.breakpoints().len() - 1
+            );
+        }
+        DebugCommand::Delete(number) => match debugger.remove_breakpoint(number) {
+            Some(breakpoint) => println!("Deleted breakpoint {number}: {breakpoint}"),
+            None => println!("There's no breakpoint {number}."),
+        },
+        DebugCommand::Watch(watchpoint) => {
+            vm.add_watchpoint(watchpoint);
+            println!("Watchpoint {}: {watchpoint}", vm.watchpoints().len() - 1);
+        }
+        DebugCommand::Unwatch(number) => match vm.remove_watchpoint(number) {
+            Some(watchpoint) => println!("Deleted watchpoint {number}: {watchpoint}"),
+            None => println!("There's no watchpoint {number}."),
+        },
+        DebugCommand::List => {
+            if debugger.breakpoints().is_empty() {
+                println!("No breakpoints.");
+            }
+            for (number, breakpoint) in debugger.breakpoints().iter().enumerate() {
+                println!("Breakpoint {number}: {breakpoint}");
+            }
+
+            if vm.watchpoints().is_empty() {
+                println!("No watchpoints.");
+            }
+            for (number, watchpoint) in vm.watchpoints().iter().enumerate() {
+                println!("Watchpoint {number}: {watchpoint}");
+            }
+        }
+        DebugCommand::View => println!("{}\n", debugger.view(vm)),
+        DebugCommand::Help => println!("{HELP}"),
+    }
+
+    Ok(())
+}
+
+/// Shows the VM after a step, including what it drew so far.
+fn show_step(debugger: &Debugger, vm: &mut VirtualMachine) {
+    println!("{}\n", debugger.view(vm));
+    vm.draw_flag = true;
+}
@@ -0,0 +1,558 @@
+//! A step debugger for the Chip-8 VM. It only decides when execution should stop, so a frontend
+//! keeps running the VM exactly like it normally does and asks `check()` before each operation
+//! cycle whether to carry on. Besides breakpoints, it stops after any operation that accessed
+//! memory covered by one of the VM's watchpoints. While paused, the frontend stops simulating and
+//! steps the VM through the debugger instead, which uses the VM's regular `step()` to do it.
+
+use crate::{
+    Chip8Error, VirtualMachine,
+    chip8::{WatchpointHit, parse_number},
+    disasm,
+};
+use std::{fmt, str::FromStr};
+
+/// How many instructions before the program counter `view()` disassembles.
+const INSTRUCTIONS_BEFORE_PC: usize = 4;
+
+/// How many instructions from the program counter on `view()` disassembles.
+const INSTRUCTIONS_FROM_PC: usize = 8;
+
+/// A register a breakpoint condition can look at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    /// One of the general purpose registers V0 to VF.
+    V(usize),
+    /// The index register.
+    I,
+}
+
+/// How a breakpoint condition compares a register to a value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    /// Every comparison operator, with the two character ones first so they're matched before the
+    /// one character ones they start with.
+    const OPERATORS: [(&str, Comparison); 6] = [
+        ("==", Self::Equal),
+        ("!=", Self::NotEqual),
+        ("<=", Self::LessOrEqual),
+        (">=", Self::GreaterOrEqual),
+        ("<", Self::Less),
+        (">", Self::Greater),
+    ];
+
+    fn operator(self) -> &'static str {
+        Self::OPERATORS
+            .iter()
+            .find(|(_, comparison)| *comparison == self)
+            .map(|(operator, _)| *operator)
+            .unwrap_or_default()
+    }
+}
+
+/// A comparison between a register and a value, like `v3 == 5` or `i >= 0x300`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    /// Returns true if the condition holds for the VM as it is right now.
+    pub fn holds(&self, vm: &VirtualMachine) -> bool {
+        let register_value = match self.register {
+            Register::V(x) => vm.registers()[x] as u16,
+            Register::I => vm.index_register(),
+        };
+
+        match self.comparison {
+            Comparison::Equal => register_value == self.value,
+            Comparison::NotEqual => register_value != self.value,
+            Comparison::Less => register_value < self.value,
+            Comparison::LessOrEqual => register_value <= self.value,
+            Comparison::Greater => register_value > self.value,
+            Comparison::GreaterOrEqual => register_value >= self.value,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.register {
+            Register::V(x) => write!(f, "v{x:x}")?,
+            Register::I => write!(f, "i")?,
+        }
+
+        write!(f, " {} {:#x}", self.comparison.operator(), self.value)
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(condition: &str) -> Result<Self, Self::Err> {
+        let (register, comparison, value) = Comparison::OPERATORS
+            .iter()
+            .find_map(|(operator, comparison)| {
+                condition
+                    .split_once(operator)
+                    .map(|(register, value)| (register.trim(), *comparison, value.trim()))
+            })
+            .ok_or_else(|| format!("'{condition}' isn't a comparison like 'v3 == 5'"))?;
+
+        let register = match register.to_ascii_lowercase().as_str() {
+            "i" => Register::I,
+            name => name
+                .strip_prefix('v')
+                .filter(|x| x.len() == 1)
+                .and_then(|x| usize::from_str_radix(x, 16).ok())
+                .map(Register::V)
+                .ok_or_else(|| format!("unknown register '{register}', expected v0 to vf or i"))?,
+        };
+
+        Ok(Self {
+            register,
+            comparison,
+            value: parse_number(value)?,
+        })
+    }
+}
+
+/// Where and when execution should stop. A breakpoint with an address stops before the
+/// instruction at that address is executed, as long as its condition (if it has one) holds. A
+/// breakpoint with only a condition stops as soon as the condition becomes true, wherever the
+/// program is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub address: Option<u16>,
+    pub condition: Option<Condition>,
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.address, self.condition) {
+            (Some(address), Some(condition)) => write!(f, "{address:#05x} if {condition}"),
+            (Some(address), None) => write!(f, "{address:#05x}"),
+            (None, Some(condition)) => write!(f, "if {condition}"),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+impl FromStr for Breakpoint {
+    type Err = String;
+
+    /// Parses breakpoints like `0x204`, `0x204 if v3 == 5` or `if i >= 0x300`.
+    fn from_str(breakpoint: &str) -> Result<Self, Self::Err> {
+        let breakpoint = breakpoint.trim();
+        let (address, condition) = match breakpoint.split_once("if") {
+            Some((address, condition)) => (address.trim(), Some(condition.parse()?)),
+            None => (breakpoint, None),
+        };
+
+        let address = if address.is_empty() {
+            None
+        } else {
+            Some(parse_number(address)?)
+        };
+
+        if address.is_none() && condition.is_none() {
+            return Err(String::from(
+                "a breakpoint needs an address, a condition or both",
+            ));
+        }
+
+        Ok(Self { address, condition })
+    }
+}
+
+/// Decides when a running program should stop and steps it while it's stopped.
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    /// Whether each breakpoint's condition held the last time it was checked. Breakpoints with
+    /// only a condition use this to stop once when it becomes true instead of on every operation.
+    conditions_held: Vec<bool>,
+    paused: bool,
+    /// Set by step over. The return address of the subroutine that was stepped into and the stack
+    /// depth to expect when it gets back there.
+    step_over_return: Option<(u16, usize)>,
+    /// The watchpoint hits from the last operation that had any, cleared on the next step.
+    watchpoint_hits: Vec<WatchpointHit>,
+}
+
+impl Debugger {
+    /// Creates a debugger with no breakpoints that lets the program run.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns true if the program is stopped and shouldn't be simulated.
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Stops the program before the next operation.
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.step_over_return = None;
+    }
+
+    /// Returns every breakpoint in the order they were added.
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Adds a breakpoint to the end of `breakpoints()`.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+        self.conditions_held.push(false);
+    }
+
+    /// Removes the breakpoint at `index` in `breakpoints()`, returning it if there was one.
+    pub fn remove_breakpoint(&mut self, index: usize) -> Option<Breakpoint> {
+        if index >= self.breakpoints.len() {
+            return None;
+        }
+
+        self.conditions_held.remove(index);
+        Some(self.breakpoints.remove(index))
+    }
+
+    /// Returns the watchpoint hits that made the program stop, or that the last step caused.
+    pub fn watchpoint_hits(&self) -> &[WatchpointHit] {
+        &self.watchpoint_hits
+    }
+
+    /// Checks whether the program should stop before its next operation. Call this before each
+    /// operation cycle and skip simulating the VM while it returns true.
+    pub fn check(&mut self, vm: &mut VirtualMachine) -> bool {
+        if self.paused {
+            return true;
+        }
+
+        // The last operation touched watched memory.
+        let watchpoint_hits = vm.take_watchpoint_hits();
+        if !watchpoint_hits.is_empty() {
+            self.watchpoint_hits = watchpoint_hits;
+            self.pause();
+            return true;
+        }
+
+        let pc = vm.program_counter();
+
+        // Back from the subroutine that was stepped over.
+        if self.step_over_return == Some((pc, vm.stack().len())) {
+            self.pause();
+            return true;
+        }
+
+        let mut hit = false;
+        for (breakpoint, held) in self.breakpoints.iter().zip(self.conditions_held.iter_mut()) {
+            let holds = breakpoint
+                .condition
+                .is_none_or(|condition| condition.holds(vm));
+
+            hit |= match breakpoint.address {
+                Some(address) => pc == address && holds,
+                None => holds && !*held,
+            };
+            *held = holds;
+        }
+
+        if hit {
+            self.pause();
+        }
+
+        hit
+    }
+
+    /// Executes a single instruction and stays paused.
+    pub fn step(&mut self, vm: &mut VirtualMachine) -> Result<(), Chip8Error> {
+        self.pause();
+        vm.take_watchpoint_hits();
+
+        let result = execute_one_instruction(vm);
+        self.watchpoint_hits = vm.take_watchpoint_hits();
+
+        result
+    }
+
+    /// Executes a single instruction, but runs a subroutine called with 2NNN all the way until it
+    /// returns before stopping again.
+    pub fn step_over(&mut self, vm: &mut VirtualMachine) -> Result<(), Chip8Error> {
+        let pc = vm.program_counter();
+        let stack_depth = vm.stack().len();
+
+        self.step(vm)?;
+
+        if vm.stack().len() > stack_depth {
+            self.step_over_return = Some((pc.wrapping_add(2), stack_depth));
+            self.paused = false;
+        }
+
+        Ok(())
+    }
+
+    /// Lets the program run again until the next breakpoint. The instruction at the program
+    /// counter is executed right away so a breakpoint there doesn't immediately stop it again.
+    /// If that instruction hits a watchpoint, the program stays stopped after it instead.
+    pub fn resume(&mut self, vm: &mut VirtualMachine) -> Result<(), Chip8Error> {
+        self.step(vm)?;
+        self.paused = !self.watchpoint_hits.is_empty();
+
+        Ok(())
+    }
+
+    /// Returns a text view of the registers, stack, timers and the disassembled instructions
+    /// around the program counter. The next instruction is marked with '>' and instructions with a
+    /// breakpoint are marked with '*'.
+    pub fn view(&self, vm: &VirtualMachine) -> String {
+        let mut lines = Vec::new();
+        let pc = vm.program_counter();
+        let v = vm.registers();
+
+        lines.push(format!(
+            "PC {pc:#05x}  I {:#05x}  DT {}  ST {}",
+            vm.index_register(),
+            vm.delay_timer(),
+            vm.sound_timer
+        ));
+        for (row, registers) in v.chunks(8).enumerate() {
+            let registers: Vec<String> = registers
+                .iter()
+                .enumerate()
+                .map(|(x, value)| format!("V{:X} {value:02x}", row * 8 + x))
+                .collect();
+            lines.push(registers.join("  "));
+        }
+
+        let stack: Vec<String> = vm
+            .stack()
+            .iter()
+            .map(|address| format!("{address:#05x}"))
+            .collect();
+        lines.push(format!("Stack [{}]", stack.join(", ")));
+
+        for hit in &self.watchpoint_hits {
+            lines.push(format!("Watchpoint: {hit}"));
+        }
+
+        if let Some(fault) = vm.fault() {
+            lines.push(format!("Faulted: {fault}"));
+        } else if vm.exited() {
+            lines.push(String::from("Exited"));
+        }
+
+        lines.push(String::new());
+
+        // Instructions before the program counter can't be known for sure since there's no telling
+        // where they start, so this assumes they're all 2 bytes long.
+        let memory = vm.memory();
+        let mut address = (pc as usize).saturating_sub(INSTRUCTIONS_BEFORE_PC * 2);
+        for _ in 0..INSTRUCTIONS_BEFORE_PC + INSTRUCTIONS_FROM_PC {
+            let Some((mnemonic, length)) = disasm::disassemble_at(memory, address) else {
+                break;
+            };
+
+            let next_marker = if address == pc as usize { '>' } else { ' ' };
+            let breakpoint_marker = if self
+                .breakpoints
+                .iter()
+                .any(|breakpoint| breakpoint.address == Some(address as u16))
+            {
+                '*'
+            } else {
+                ' '
+            };
+            let bytes: String = memory[address..address + length]
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect();
+
+            lines.push(format!(
+                "{next_marker}{breakpoint_marker} {address:#06x}  {bytes:<8}  {mnemonic}"
+            ));
+            address += length;
+        }
+
+        lines.join("\n")
+    }
+}
+
+/// Executes the instruction at the program counter. If it doesn't fit in what's left of the frame,
+/// or the VM is waiting for the display, the frame is ended first like it would be if the program
+/// were running.
+fn execute_one_instruction(vm: &mut VirtualMachine) -> Result<(), Chip8Error> {
+    if vm.step()?.is_none() && !vm.exited() {
+        vm.tick_timers();
+        vm.step()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Chip8Settings,
+        chip8::{MemoryAccess, tests::vm_with},
+    };
+
+    /// Runs the VM like a frontend would until the debugger stops it.
+    fn run_until_paused(debugger: &mut Debugger, vm: &mut VirtualMachine) {
+        for _ in 0..1000 {
+            if debugger.check(vm) {
+                return;
+            }
+            if vm.step().unwrap().is_none() {
+                vm.tick_timers();
+            }
+        }
+
+        panic!("the debugger never stopped the program");
+    }
+
+    #[test]
+    fn parses_breakpoints() {
+        assert_eq!(
+            "0x204".parse(),
+            Ok(Breakpoint {
+                address: Some(0x204),
+                condition: None
+            })
+        );
+        assert_eq!(
+            "0x204 if VF != 1".parse(),
+            Ok(Breakpoint {
+                address: Some(0x204),
+                condition: Some(Condition {
+                    register: Register::V(0xf),
+                    comparison: Comparison::NotEqual,
+                    value: 1
+                })
+            })
+        );
+        assert_eq!(
+            "if i>=0x300".parse::<Breakpoint>().map(|b| b.to_string()),
+            Ok(String::from("if i >= 0x300"))
+        );
+        assert!("".parse::<Breakpoint>().is_err());
+        assert!("if v10 == 1".parse::<Breakpoint>().is_err());
+        assert!("if v1 = 1".parse::<Breakpoint>().is_err());
+    }
+
+    #[test]
+    fn stops_at_address_breakpoints_and_resumes_past_them() {
+        // Counts up in V0 forever.
+        let mut vm = vm_with(&Chip8Settings::default(), &[0x7001, 0x1200]);
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint("0x202".parse().unwrap());
+
+        run_until_paused(&mut debugger, &mut vm);
+        assert_eq!(vm.program_counter(), 0x202);
+        assert_eq!(vm.registers()[0], 1);
+
+        debugger.resume(&mut vm).unwrap();
+        run_until_paused(&mut debugger, &mut vm);
+        assert_eq!(vm.program_counter(), 0x202);
+        assert_eq!(vm.registers()[0], 2);
+    }
+
+    #[test]
+    fn stops_when_a_condition_becomes_true() {
+        let mut vm = vm_with(&Chip8Settings::default(), &[0x7001, 0x1200]);
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint("if v0 >= 3".parse().unwrap());
+
+        run_until_paused(&mut debugger, &mut vm);
+        assert_eq!(vm.registers()[0], 3);
+
+        // Still true, but it only stops again once it's been false.
+        debugger.resume(&mut vm).unwrap();
+        for _ in 0..10 {
+            assert!(!debugger.check(&mut vm));
+            vm.step().unwrap();
+        }
+    }
+
+    #[test]
+    fn steps_over_subroutine_calls() {
+        // 0x200: call 0x206, 0x202: V1 = 1, 0x204: loop, 0x206: V0 = 5 and return.
+        let mut vm = vm_with(
+            &Chip8Settings::default(),
+            &[0x2206, 0x6101, 0x1204, 0x6005, 0x00ee],
+        );
+        let mut debugger = Debugger::new();
+        debugger.pause();
+
+        debugger.step_over(&mut vm).unwrap();
+        assert!(!debugger.paused());
+        run_until_paused(&mut debugger, &mut vm);
+        assert_eq!(vm.program_counter(), 0x202);
+        assert_eq!(vm.registers()[0], 5);
+
+        debugger.step_over(&mut vm).unwrap();
+        assert!(debugger.paused());
+        assert_eq!(vm.program_counter(), 0x204);
+    }
+
+    #[test]
+    fn view_marks_next_instruction_and_breakpoints() {
+        let mut vm = vm_with(&Chip8Settings::default(), &[0x6a1f, 0xd015, 0x1200]);
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint("0x204".parse().unwrap());
+        debugger.step(&mut vm).unwrap();
+
+        let view = debugger.view(&vm);
+        assert!(view.contains("VA 1f"));
+        assert!(view.contains("   0x0200  6a1f      LD VA, 0x1F"));
+        assert!(view.contains(">  0x0202  d015      DRW V0, V1, 5"));
+        assert!(view.contains(" * 0x0204  1200      JP 0x200"));
+    }
+
+    #[test]
+    fn stops_after_accessing_watched_memory() {
+        // V0 = 0x42, then BCD into 0x300 and dump V0 to 0x300 over and over.
+        let mut vm = vm_with(
+            &Chip8Settings::default(),
+            &[0x6042, 0xa300, 0xf033, 0xf055, 0x1202],
+        );
+        vm.add_watchpoint("0x302 w".parse().unwrap());
+        let mut debugger = Debugger::new();
+
+        run_until_paused(&mut debugger, &mut vm);
+        assert_eq!(vm.program_counter(), 0x206);
+        assert_eq!(
+            debugger.watchpoint_hits(),
+            &[WatchpointHit {
+                pc: 0x204,
+                opcode: 0xf033,
+                i: 0x300,
+                address: 0x302,
+                access: MemoryAccess::Write,
+                value: 6
+            }]
+        );
+        assert!(
+            debugger
+                .view(&vm)
+                .contains("Watchpoint: opcode 0xf033 at 0x204 wrote 0x06 at 0x302 (I = 0x300)")
+        );
+
+        // FX55 only writes 0x300, so the next stop is the BCD again.
+        debugger.resume(&mut vm).unwrap();
+        run_until_paused(&mut debugger, &mut vm);
+        assert_eq!(vm.program_counter(), 0x206);
+    }
+}
@@ -0,0 +1,280 @@
+//! Differential testing against other interpreters. The VM runs a program in lockstep with a
+//! reference trace of the same program from another interpreter and stops at the first
+//! instruction after which the two disagree.
+//!
+//! A reference trace is JSON Lines: one JSON object per executed instruction, in order, describing
+//! the machine right after that instruction. Every field is optional and only the fields that are
+//! there get compared, so an interpreter only has to write out what it can:
+//!
+//! ```text
+//! {"pc": 514, "i": 0, "v": [255, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]}
+//! {"pc": 516, "i": 768, "stack": [], "fb": ["....####....", "..."]}
+//! ```
+//!
+//! - `pc` and `i` are numbers.
+//! - `v` is all 16 registers, V0 first.
+//! - `stack` is the return addresses, oldest first.
+//! - `fb` is the frame buffer as rows of text, one character per pixel: `.` for off and `#` for
+//!   on. XO-CHIP programs use `+` for pixels only in the second bitplane and `@` for pixels in
+//!   both. This is the same as `VirtualMachine::framebuffer_text()`.
+//!
+//! Anything else in the objects, like a cycle count or an opcode, is ignored.
+
+use crate::{Chip8Error, VirtualMachine, disasm};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The state of the machine after an instruction, as recorded by a reference interpreter.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ReferenceState {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pc: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub i: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub v: Option<[u8; 16]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stack: Option<Vec<u16>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fb: Option<Vec<String>>,
+}
+
+impl ReferenceState {
+    /// Records everything about the VM that can be compared, for making reference traces.
+    pub fn of(vm: &VirtualMachine) -> Self {
+        Self {
+            pc: Some(vm.program_counter()),
+            i: Some(vm.index_register()),
+            v: Some(vm.registers()),
+            stack: Some(vm.stack().to_vec()),
+            fb: Some(vm.framebuffer_text().lines().map(String::from).collect()),
+        }
+    }
+}
+
+/// One thing that's different between the reference and the VM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Difference {
+    /// What's different, like `V3`, `PC` or `fb row 12`.
+    pub what: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// The first instruction after which the VM and the reference disagree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// The instruction's number, counting from 0 at the start of the trace.
+    pub cycle: u64,
+    /// Address of the instruction.
+    pub pc: u16,
+    /// Disassembly of the instruction.
+    pub instruction: String,
+    pub differences: Vec<Difference>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Diverged at cycle {} after {:#06x}  {}",
+            self.cycle, self.pc, self.instruction
+        )?;
+
+        for difference in &self.differences {
+            write!(
+                f,
+                "\n  {}: expected {}, got {}",
+                difference.what, difference.expected, difference.actual
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Executes one instruction for each state in the reference and compares the VM to it, stopping
+/// at the first difference. Returns None if the VM matched the whole reference. A fault or the
+/// program exiting before the reference ends counts as a difference too.
+pub fn run_lockstep(
+    vm: &mut VirtualMachine,
+    reference: impl IntoIterator<Item = ReferenceState>,
+) -> Option<Divergence> {
+    for (cycle, expected) in (0..).zip(reference) {
+        let pc = vm.program_counter();
+        let instruction = disasm::disassemble_at(vm.memory(), pc as usize)
+            .map_or_else(|| String::from("(end of memory)"), |(mnemonic, _)| mnemonic);
+
+        let differences = match execute_one_instruction(vm) {
+            Ok(true) => compare(&expected, vm),
+            Ok(false) => vec![difference("program", "running", "exited")],
+            Err(error) => vec![difference("program", "running", error)],
+        };
+
+        if !differences.is_empty() {
+            return Some(Divergence {
+                cycle,
+                pc,
+                instruction,
+                differences,
+            });
+        }
+    }
+
+    None
+}
+
+/// Returns everything the VM has that's different from what the reference expects.
+pub fn compare(expected: &ReferenceState, vm: &VirtualMachine) -> Vec<Difference> {
+    let mut differences = Vec::new();
+
+    if let Some(pc) = expected.pc
+        && pc != vm.program_counter()
+    {
+        differences.push(difference("PC", hex(pc), hex(vm.program_counter())));
+    }
+
+    if let Some(i) = expected.i
+        && i != vm.index_register()
+    {
+        differences.push(difference("I", hex(i), hex(vm.index_register())));
+    }
+
+    if let Some(v) = expected.v {
+        for (x, (expected, actual)) in v.iter().zip(vm.registers()).enumerate() {
+            if *expected != actual {
+                differences.push(difference(
+                    &format!("V{x:X}"),
+                    format!("{expected:#04x}"),
+                    format!("{actual:#04x}"),
+                ));
+            }
+        }
+    }
+
+    if let Some(stack) = &expected.stack
+        && stack != vm.stack()
+    {
+        differences.push(difference(
+            "stack",
+            format!("{stack:x?}"),
+            format!("{:x?}", vm.stack()),
+        ));
+    }
+
+    if let Some(fb) = &expected.fb {
+        let actual = vm.framebuffer_text();
+        let actual: Vec<&str> = actual.lines().collect();
+
+        if fb.len() != actual.len() {
+            differences.push(difference("fb rows", fb.len(), actual.len()));
+        } else {
+            for (row, (expected, actual)) in fb.iter().zip(actual).enumerate() {
+                if expected != actual {
+                    differences.push(difference(
+                        &format!("fb row {row}"),
+                        format!("\n    {expected}\n   "),
+                        format!("\n    {actual}"),
+                    ));
+                }
+            }
+        }
+    }
+
+    differences
+}
+
+/// Executes exactly one instruction, starting a new frame if the current one is out of time.
+/// Returns false if the program has exited.
+fn execute_one_instruction(vm: &mut VirtualMachine) -> Result<bool, Chip8Error> {
+    let cycles = vm.cycles();
+
+    while vm.cycles() == cycles {
+        if vm.exited() {
+            return Ok(false);
+        }
+        if vm.step()?.is_none() {
+            vm.tick_timers();
+        }
+    }
+
+    Ok(true)
+}
+
+fn difference(what: &str, expected: impl fmt::Display, actual: impl fmt::Display) -> Difference {
+    Difference {
+        what: what.to_string(),
+        expected: expected.to_string(),
+        actual: actual.to_string(),
+    }
+}
+
+fn hex(address: u16) -> String {
+    format!("{address:#06x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chip8Settings, chip8::tests::vm_with};
+
+    /// LD V0, 0x03; LD V1, 0x08; SHR V0, V1; LD I, 0x300; JP 0x208
+    const SHIFT_PROGRAM: [u16; 5] = [0x6003, 0x6108, 0x8016, 0xa300, 0x1208];
+
+    fn reference_trace(settings: &Chip8Settings, cycles: usize) -> Vec<ReferenceState> {
+        let mut vm = vm_with(settings, &SHIFT_PROGRAM);
+        (0..cycles)
+            .map(|_| {
+                execute_one_instruction(&mut vm).unwrap();
+                ReferenceState::of(&vm)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matching_runs_dont_diverge() {
+        let settings = Chip8Settings::default();
+        let reference = reference_trace(&settings, 8);
+
+        assert_eq!(
+            run_lockstep(&mut vm_with(&settings, &SHIFT_PROGRAM), reference),
+            None
+        );
+    }
+
+    #[test]
+    fn stops_at_the_first_difference() {
+        let modern = Chip8Settings {
+            shift_quirk: Some(true),
+            ..Chip8Settings::default()
+        };
+        let reference = reference_trace(&modern, 8);
+
+        // Without the shift quirk SHR V0, V1 shifts V1 instead of V0.
+        let divergence = run_lockstep(
+            &mut vm_with(&Chip8Settings::default(), &SHIFT_PROGRAM),
+            reference,
+        )
+        .unwrap();
+
+        assert_eq!(divergence.cycle, 2);
+        assert_eq!(divergence.pc, 0x204);
+        assert_eq!(
+            divergence.to_string(),
+            "Diverged at cycle 2 after 0x0204  SHR V0, V1\n  V0: expected 0x01, got 0x04\n  VF: expected 0x01, got 0x00"
+        );
+    }
+
+    #[test]
+    fn missing_fields_are_not_compared() {
+        let reference: Vec<ReferenceState> = [r#"{"pc": 514}"#, r#"{"cycle": 1, "i": 0}"#]
+            .iter()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(reference[1].v, None);
+
+        // V0 and V1 are different after the first two instructions, but they're not compared.
+        let mut vm = vm_with(&Chip8Settings::default(), &SHIFT_PROGRAM);
+        assert_eq!(run_lockstep(&mut vm, reference), None);
+    }
+}
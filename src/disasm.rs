@@ -0,0 +1,363 @@
+//! Turns Chip-8 opcodes back into assembly mnemonics like `LD V3, 0x1F` and `DRW V0, V1, 5`. The
+//! decoding mirrors `decode_opcode_and_execute_operation()` in the chip8 module exactly, including
+//! the SUPER-CHIP and XO-CHIP extensions, so an instruction is always shown as what the VM would
+//! actually execute. Opcodes the VM doesn't know are shown as `DW` data words.
+
+use crate::{asm, chip8::PROGRAM_START};
+use std::fmt;
+
+/// How many bytes of data go on each `DB` line.
+const DATA_BYTES_PER_LINE: usize = 8;
+
+/// Returns how many bytes the instruction starting with `opcode` takes up. XO-CHIP's F000 NNNN is
+/// the only instruction that is 4 bytes long, every other one is 2.
+pub fn instruction_length(opcode: u16) -> usize {
+    if opcode == 0xf000 { 4 } else { 2 }
+}
+
+/// Returns the mnemonic for a single instruction. `operand` is the word following the opcode,
+/// which is only needed by F000 NNNN. Without it, F000 is shown as data.
+pub fn disassemble_instruction(opcode: u16, operand: Option<u16>) -> String {
+    let n: u8 = (opcode & 0x000f) as u8;
+    let nn: u8 = (opcode & 0x00ff) as u8;
+    let nnn: u16 = opcode & 0x0fff;
+    let x: usize = ((opcode & 0x0f00) >> 8) as usize;
+    let y: usize = ((opcode & 0x00f0) >> 4) as usize;
+
+    match opcode & 0xf000 {
+        0x0000 => match opcode & 0x00ff {
+            0x0000 => format!("SYS 0x{nnn:03X}"),
+            0x00c0..=0x00cf => format!("SCD {n}"),
+            0x00d0..=0x00df => format!("SCU {n}"),
+            0x00e0 => String::from("CLS"),
+            0x00ee => String::from("RET"),
+            0x00fb => String::from("SCR"),
+            0x00fc => String::from("SCL"),
+            0x00fd => String::from("EXIT"),
+            0x00fe => String::from("LOW"),
+            0x00ff => String::from("HIGH"),
+            _ => data_word(opcode),
+        },
+        0x1000 => format!("JP 0x{nnn:03X}"),
+        0x2000 => format!("CALL 0x{nnn:03X}"),
+        0x3000 => format!("SE V{x:X}, 0x{nn:02X}"),
+        0x4000 => format!("SNE V{x:X}, 0x{nn:02X}"),
+        0x5000 => match opcode & 0xf00f {
+            0x5002 => format!("SAVE V{x:X}, V{y:X}"),
+            0x5003 => format!("LOAD V{x:X}, V{y:X}"),
+            _ => format!("SE V{x:X}, V{y:X}"),
+        },
+        0x6000 => format!("LD V{x:X}, 0x{nn:02X}"),
+        0x7000 => format!("ADD V{x:X}, 0x{nn:02X}"),
+        0x8000 => match opcode & 0xf00f {
+            0x8001 => format!("OR V{x:X}, V{y:X}"),
+            0x8002 => format!("AND V{x:X}, V{y:X}"),
+            0x8003 => format!("XOR V{x:X}, V{y:X}"),
+            0x8004 => format!("ADD V{x:X}, V{y:X}"),
+            0x8005 => format!("SUB V{x:X}, V{y:X}"),
+            0x8006 => format!("SHR V{x:X}, V{y:X}"),
+            0x8007 => format!("SUBN V{x:X}, V{y:X}"),
+            0x800e => format!("SHL V{x:X}, V{y:X}"),
+            _ => format!("LD V{x:X}, V{y:X}"),
+        },
+        0x9000 => format!("SNE V{x:X}, V{y:X}"),
+        0xa000 => format!("LD I, 0x{nnn:03X}"),
+        0xb000 => format!("JP V0, 0x{nnn:03X}"),
+        0xc000 => format!("RND V{x:X}, 0x{nn:02X}"),
+        0xd000 => format!("DRW V{x:X}, V{y:X}, {n}"),
+        0xe000 => match opcode & 0xf0ff {
+            0xe09e => format!("SKP V{x:X}"),
+            0xe0a1 => format!("SKNP V{x:X}"),
+            _ => data_word(opcode),
+        },
+        0xf000 => match opcode & 0xf0ff {
+            0xf000 if x == 0 => match operand {
+                Some(nnnn) => format!("LD I, LONG 0x{nnnn:04X}"),
+                None => data_word(opcode),
+            },
+            0xf001 => format!("PLANE {x}"),
+            0xf002 if x == 0 => String::from("AUDIO"),
+            0xf007 => format!("LD V{x:X}, DT"),
+            0xf00a => format!("LD V{x:X}, K"),
+            0xf015 => format!("LD DT, V{x:X}"),
+            0xf018 => format!("LD ST, V{x:X}"),
+            0xf01e => format!("ADD I, V{x:X}"),
+            0xf029 => format!("LD F, V{x:X}"),
+            0xf030 => format!("LD HF, V{x:X}"),
+            0xf033 => format!("LD B, V{x:X}"),
+            0xf03a => format!("PITCH V{x:X}"),
+            0xf055 => format!("LD [I], V{x:X}"),
+            0xf065 => format!("LD V{x:X}, [I]"),
+            0xf075 => format!("LD R, V{x:X}"),
+            0xf085 => format!("LD V{x:X}, R"),
+            _ => data_word(opcode),
+        },
+        _ => data_word(opcode),
+    }
+}
+
+/// Reads the instruction at an address in memory and returns its mnemonic along with how many
+/// bytes it takes up. Returns None if there isn't a whole opcode left at that address.
+pub fn disassemble_at(memory: &[u8], address: usize) -> Option<(String, usize)> {
+    let opcode = read_word(memory, address)?;
+    let length = instruction_length(opcode);
+    let operand = if length == 4 {
+        read_word(memory, address + 2)
+    } else {
+        None
+    };
+
+    // An F000 at the very end of memory has no address to load, so it's only 2 bytes of data.
+    let length = if operand.is_some() { length } else { 2 };
+
+    Some((disassemble_instruction(opcode, operand), length))
+}
+
+fn read_word(memory: &[u8], address: usize) -> Option<u16> {
+    let high = *memory.get(address)?;
+    let low = *memory.get(address + 1)?;
+
+    Some(u16::from_be_bytes([high, low]))
+}
+
+fn data_word(word: u16) -> String {
+    format!("DW 0x{word:04X}")
+}
+
+/// A line of a disassembly listing. Either a single instruction or a run of data bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    /// Address of the first byte, as loaded into the VM.
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    /// The instruction's mnemonic, or a `DB` directive listing the bytes for data.
+    pub text: String,
+    pub is_code: bool,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Data bytes are already listed by the DB directive.
+        let bytes: String = if self.is_code {
+            self.bytes
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect()
+        } else {
+            String::new()
+        };
+
+        write!(f, "{:#06x}  {bytes:<8}  {}", self.address, self.text)
+    }
+}
+
+/// Disassembles a program (ROM) as it would be loaded at 0x200. Code is told apart from data by
+/// following every path the program can take from 0x200: jumps, subroutine calls and both sides of
+/// every skip. Anything never reached that way is listed as data. BNNN jumps somewhere that
+/// depends on V0, so code only reached through one shows up as data too.
+pub fn disassemble(program: &[u8]) -> Vec<Line> {
+    let is_code = find_code(program);
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    while offset < program.len() {
+        if is_code[offset] {
+            // find_code() only marks whole instructions, so there's always one here.
+            let (text, length) = disassemble_at(program, offset)
+                .expect("instructions marked as code are always complete");
+            lines.push(Line {
+                address: (PROGRAM_START + offset) as u16,
+                bytes: program[offset..offset + length].to_vec(),
+                text,
+                is_code: true,
+            });
+            offset += length;
+        } else {
+            let data_length = is_code[offset..]
+                .iter()
+                .take(DATA_BYTES_PER_LINE)
+                .take_while(|is_code| !**is_code)
+                .count();
+            let bytes = &program[offset..offset + data_length];
+            let listed_bytes: Vec<String> =
+                bytes.iter().map(|byte| format!("0x{byte:02X}")).collect();
+
+            lines.push(Line {
+                address: (PROGRAM_START + offset) as u16,
+                bytes: bytes.to_vec(),
+                text: format!("DB {}", listed_bytes.join(", ")),
+                is_code: false,
+            });
+            offset += data_length;
+        }
+    }
+
+    lines
+}
+
+/// Disassembles a program and returns the listing as text, one line per instruction or run of data.
+pub fn listing(program: &[u8]) -> String {
+    disassemble(program)
+        .iter()
+        .map(Line::to_string)
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Disassembles a program into source that `asm::assemble()` turns back into the exact same
+/// bytes, with the address of each line in a comment. Some opcodes run the same as another, like
+/// 5XY1 running as 5XY0, so those are written as `DW` words with their mnemonic in the comment.
+pub fn source(program: &[u8]) -> String {
+    disassemble(program)
+        .iter()
+        .map(|line| {
+            let reassembles = asm::assemble(&line.text).is_ok_and(|bytes| bytes == line.bytes);
+            if reassembles {
+                format!("    {:<28}; {:#06x}", line.text, line.address)
+            } else {
+                let words: Vec<String> = line
+                    .bytes
+                    .chunks(2)
+                    .map(|word| format!("0x{:02X}{:02X}", word[0], word[1]))
+                    .collect();
+                let text = format!("DW {}", words.join(", "));
+
+                format!("    {text:<28}; {:#06x}  {}", line.address, line.text)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Follows every path through the program from its start and returns which bytes are the start
+/// or the rest of an instruction that can be reached.
+fn find_code(program: &[u8]) -> Vec<bool> {
+    let mut is_code = vec![false; program.len()];
+    let mut to_visit = vec![PROGRAM_START];
+
+    while let Some(address) = to_visit.pop() {
+        let Some(offset) = address.checked_sub(PROGRAM_START) else {
+            continue;
+        };
+        if offset >= program.len() || is_code[offset] {
+            continue;
+        }
+
+        let Some(opcode) = read_word(program, offset) else {
+            continue;
+        };
+        let length = instruction_length(opcode);
+        if offset + length > program.len() || is_code[offset..offset + length].contains(&true) {
+            continue;
+        }
+
+        // Anything the VM doesn't know would fault, so it's not code and nothing comes after it.
+        if disassemble_instruction(opcode, read_word(program, offset + 2)).starts_with("DW") {
+            continue;
+        }
+
+        is_code[offset..offset + length].fill(true);
+        let next = address + length;
+        let nnn = (opcode & 0x0fff) as usize;
+
+        match opcode & 0xf000 {
+            0x0000 if opcode == 0x00ee || opcode == 0x00fd => (),
+            0x1000 => to_visit.push(nnn),
+            0x2000 => to_visit.extend([next, nnn]),
+            // Jumps relative to V0 can't be followed without running the program.
+            0xb000 => (),
+            0x3000 | 0x4000 | 0x9000 => to_visit.extend([next, skip_target(program, next)]),
+            0x5000 if !matches!(opcode & 0x000f, 0x2 | 0x3) => {
+                to_visit.extend([next, skip_target(program, next)])
+            }
+            0xe000 => to_visit.extend([next, skip_target(program, next)]),
+            _ => to_visit.push(next),
+        }
+    }
+
+    is_code
+}
+
+/// Returns where a skip lands when it skips the instruction at `next`, which takes the length of
+/// XO-CHIP's F000 NNNN into account just like the VM does.
+fn skip_target(program: &[u8], next: usize) -> usize {
+    let next_opcode = next
+        .checked_sub(PROGRAM_START)
+        .and_then(|offset| read_word(program, offset));
+
+    if next_opcode == Some(0xf000) {
+        next + 4
+    } else {
+        next + 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_instructions_and_data_with_addresses() {
+        let program = [
+            0x63, 0x1f, 0x22, 0x08, 0x12, 0x04, 0xd0, 0x15, 0x00, 0xee, 0xf0, 0x90,
+        ];
+
+        assert_eq!(
+            listing(&program),
+            "0x0200  631f      LD V3, 0x1F\n\
+             0x0202  2208      CALL 0x208\n\
+             0x0204  1204      JP 0x204\n\
+             0x0206            DB 0xD0, 0x15\n\
+             0x0208  00ee      RET\n\
+             0x020a            DB 0xF0, 0x90"
+        );
+    }
+
+    #[test]
+    fn skips_follow_both_paths_and_step_over_long_loads() {
+        let texts = |program: &[u8]| -> Vec<String> {
+            disassemble(program)
+                .into_iter()
+                .map(|line| line.text)
+                .collect()
+        };
+
+        // Not skipping jumps over the data to the second EXIT, skipping lands on the first.
+        let program = [0x30, 0x00, 0x12, 0x08, 0x00, 0xfd, 0xff, 0xff, 0x00, 0xfd];
+        assert_eq!(
+            texts(&program),
+            ["SE V0, 0x00", "JP 0x208", "EXIT", "DB 0xFF, 0xFF", "EXIT"]
+        );
+
+        // Skipping F000 NNNN skips all 4 bytes, so its address is never read as an instruction.
+        let program = [0x30, 0x00, 0xf0, 0x00, 0x03, 0x00, 0x00, 0xfd];
+        assert_eq!(
+            texts(&program),
+            ["SE V0, 0x00", "LD I, LONG 0x0300", "EXIT"]
+        );
+    }
+
+    #[test]
+    fn code_only_reached_through_jp_v0_is_data() {
+        let program = [0xb2, 0x04, 0x00, 0x00, 0x00, 0xe0];
+        let lines = disassemble(&program);
+
+        assert!(lines[0].is_code);
+        assert_eq!(lines[1].text, "DB 0x00, 0x00, 0x00, 0xE0");
+    }
+
+    #[test]
+    fn source_assembles_back_into_every_opcode() {
+        for opcode in 0..=u16::MAX {
+            // The extra word is the address for F000 NNNN.
+            let mut program = opcode.to_be_bytes().to_vec();
+            program.extend([0x03, 0x00]);
+
+            assert_eq!(
+                asm::assemble(&source(&program)).as_deref(),
+                Ok(program.as_slice()),
+                "{opcode:#06x} didn't round trip"
+            );
+        }
+    }
+}
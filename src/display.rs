@@ -1,4 +1,5 @@
-use crate::{chip8::VirtualMachine, configuration::WindowSettings};
+use crate::configuration::WindowSettings;
+use chip_8_interpreter::VirtualMachine;
 use lerp::Lerp;
 use sdl2::{
     EventPump, Sdl,
@@ -12,9 +13,13 @@ use std::time::{Duration, Instant};
 pub struct VirtualScreen {
     pub canvas: Canvas<Window>,
     pub event_pump: EventPump,
-    background_color: Color,
-    foreground_color: Color,
-    fading_pixels: [Duration; 2048],
+    /// Color of each frame buffer pixel value. 0 is the background, 1 is the first bitplane, 2 is
+    /// the second bitplane and 3 is both bitplanes.
+    palette: [Color; 4],
+    fading_pixels: Vec<Duration>,
+    /// The color each fading pixel had before it turned off so it can fade from that color.
+    fading_colors: Vec<Color>,
+    resolution: (usize, usize),
     pixel_fade_duration: Duration,
 }
 
@@ -24,16 +29,13 @@ impl VirtualScreen {
         title: &str,
         settings: &WindowSettings,
     ) -> anyhow::Result<Self> {
-        let background_color: Color = Color::RGB(
-            settings.background_color[0],
-            settings.background_color[1],
-            settings.background_color[2],
-        );
-        let foreground_color: Color = Color::RGB(
-            settings.foreground_color[0],
-            settings.foreground_color[1],
-            settings.foreground_color[2],
-        );
+        let palette: [Color; 4] = [
+            settings.background_color,
+            settings.foreground_color,
+            settings.plane_2_color,
+            settings.both_planes_color,
+        ]
+        .map(|[r, g, b]| Color::RGB(r, g, b));
 
         let event_pump = sdl_context.event_pump().map_err(anyhow::Error::msg)?;
         let video_subsystem = sdl_context.video().map_err(anyhow::Error::msg)?;
@@ -48,8 +50,10 @@ impl VirtualScreen {
 
         let mut canvas = window.into_canvas().present_vsync().build()?;
 
-        // Set the canvas to the same size as Chip-8 VM frame buffer
-        canvas.set_logical_size(64, 32)?;
+        // Set the canvas to the same size as Chip-8 VM frame buffer. This changes if a program
+        // switches resolution, see render_chip_8_frame().
+        let resolution = (64, 32);
+        canvas.set_logical_size(resolution.0 as u32, resolution.1 as u32)?;
 
         canvas.set_draw_color(Color::BLACK);
         canvas.clear();
@@ -57,13 +61,20 @@ impl VirtualScreen {
         Ok(Self {
             canvas,
             event_pump,
-            background_color,
-            foreground_color,
-            fading_pixels: [Duration::ZERO; 2048],
+            palette,
+            fading_pixels: vec![Duration::ZERO; resolution.0 * resolution.1],
+            fading_colors: vec![palette[1]; resolution.0 * resolution.1],
+            resolution,
             pixel_fade_duration: Duration::from_micros(settings.pixel_fade_micros),
         })
     }
 
+    /// Changes the text in the title bar of the window.
+    pub fn set_title(&mut self, title: &str) -> anyhow::Result<()> {
+        self.canvas.window_mut().set_title(title)?;
+        Ok(())
+    }
+
     /// Renders the CHip-8 VM frame buffer to the SDL2 canvas pixel-by-pixel
     pub fn render_chip_8_frame(
         &mut self,
@@ -71,21 +82,37 @@ impl VirtualScreen {
         mol_start_time: &Instant,
         settings: &WindowSettings,
     ) -> Result<(), String> {
+        // Follow the Chip-8 VM into whatever resolution it's in. Pixels that were fading out
+        // don't line up with anything anymore, so they're dropped.
+        if vm.resolution() != self.resolution {
+            self.resolution = vm.resolution();
+            self.canvas
+                .set_logical_size(self.resolution.0 as u32, self.resolution.1 as u32)
+                .map_err(|error| error.to_string())?;
+            self.fading_pixels.clear();
+            self.fading_pixels
+                .resize(self.resolution.0 * self.resolution.1, Duration::ZERO);
+            self.fading_colors
+                .resize(self.resolution.0 * self.resolution.1, self.palette[1]);
+        }
+
         let mut x: i32 = 0;
         let mut y: i32 = 0;
         let mut current_pixel: Point;
 
-        for (screen_location, buffer_pixel_on) in vm.fb.iter().enumerate() {
+        for (screen_location, buffer_pixel_planes) in vm.framebuffer().iter().enumerate() {
             // This is actually faster than using .offset() on an existing point
             current_pixel = Point::new(x, y);
 
             //Draw pixels to screen
-            if *buffer_pixel_on {
-                // Draw pixel as on foreground color
-                self.canvas.set_draw_color(self.foreground_color);
+            if *buffer_pixel_planes != 0 {
+                // Draw pixel as the palette color for the bitplanes it's on in
+                let pixel_color = self.palette[(*buffer_pixel_planes & 0b11) as usize];
+                self.canvas.set_draw_color(pixel_color);
                 self.canvas.draw_point(current_pixel)?;
                 if settings.sprite_flicker_filter {
                     self.fading_pixels[screen_location] = self.pixel_fade_duration;
+                    self.fading_colors[screen_location] = pixel_color;
                 }
             } else if
             // Draw pixels with anti-flicker feature by blending previously on pixels towards
@@ -98,26 +125,22 @@ impl VirtualScreen {
 
                 let ratio = (self.fading_pixels[screen_location].as_micros()
                     / self.pixel_fade_duration.as_micros()) as f32;
-                let r = (self.foreground_color.r as f32)
-                    .lerp_bounded(self.background_color.r as f32, ratio)
-                    as u8;
-                let g = (self.foreground_color.g as f32)
-                    .lerp_bounded(self.background_color.g as f32, ratio)
-                    as u8;
-                let b = (self.foreground_color.b as f32)
-                    .lerp_bounded(self.background_color.b as f32, ratio)
-                    as u8;
+                let faded_from = self.fading_colors[screen_location];
+                let background_color = self.palette[0];
+                let r = (faded_from.r as f32).lerp_bounded(background_color.r as f32, ratio) as u8;
+                let g = (faded_from.g as f32).lerp_bounded(background_color.g as f32, ratio) as u8;
+                let b = (faded_from.b as f32).lerp_bounded(background_color.b as f32, ratio) as u8;
                 let fade_color: Color = Color::RGB(r, g, b);
 
                 self.canvas.set_draw_color(fade_color);
                 self.canvas.draw_point(current_pixel)?;
             } else {
                 // Draw fully off pixels as background color
-                self.canvas.set_draw_color(self.background_color);
+                self.canvas.set_draw_color(self.palette[0]);
                 self.canvas.draw_point(current_pixel)?;
             }
 
-            match (screen_location + 1) % 64 {
+            match (screen_location + 1) % self.resolution.0 {
                 0 => {
                     x = 0;
                     y += 1;
@@ -0,0 +1,125 @@
+use std::{fmt, path::PathBuf};
+
+/// Everything that can go wrong while loading or running a program on the Chip-8 VM. Errors that
+/// happen while running a program carry the program counter and opcode of the operation that
+/// caused them so the frontend can point at exactly where the program went wrong.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chip8Error {
+    /// The opcode doesn't match any operation the VM knows about.
+    UnknownOpcode { pc: u16, opcode: u16 },
+    /// 00EE tried to return from a subroutine, but the stack was empty.
+    StackUnderflow { pc: u16, opcode: u16 },
+    /// 2NNN tried to call a subroutine, but the stack was already 16 addresses deep.
+    StackOverflow { pc: u16, opcode: u16 },
+    /// An operation tried to read or write an address outside of the VM's memory.
+    MemoryOutOfRange {
+        pc: u16,
+        opcode: u16,
+        address: usize,
+    },
+    /// The program counter ran off the end of memory before a whole opcode could be fetched.
+    ProgramCounterOutOfRange { pc: u16 },
+    /// The program is bigger than the memory available to hold it.
+    RomTooLarge { size: usize, max: usize },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownOpcode { pc, opcode } => {
+                write!(f, "opcode {opcode:#06x} at {pc:#05x} not recognized")
+            }
+            Self::StackUnderflow { pc, opcode } => write!(
+                f,
+                "opcode {opcode:#06x} at {pc:#05x} tried to return with an empty stack"
+            ),
+            Self::StackOverflow { pc, opcode } => write!(
+                f,
+                "opcode {opcode:#06x} at {pc:#05x} tried to call a subroutine with a full stack"
+            ),
+            Self::MemoryOutOfRange {
+                pc,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "opcode {opcode:#06x} at {pc:#05x} tried to access memory address {address:#x}, which is out of range"
+            ),
+            Self::ProgramCounterOutOfRange { pc } => write!(
+                f,
+                "program counter reached the end of memory at {pc:#05x} and attempted to read another byte"
+            ),
+            Self::RomTooLarge { size, max } => write!(
+                f,
+                "program is {size} bytes but only {max} bytes of Chip-8 VM memory are available"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+/// Everything that can go wrong while restoring a save state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SaveStateError {
+    /// The data doesn't start with the save state header, so it's something else entirely.
+    NotASaveState,
+    /// The save state was made by a version of the VM with a different save state format.
+    UnsupportedVersion { version: u16 },
+    /// The save state has the right header, but what comes after it doesn't describe a VM.
+    Corrupt,
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotASaveState => write!(f, "not a Chip-8 VM save state"),
+            Self::UnsupportedVersion { version } => {
+                write!(f, "save state version {version} is not supported")
+            }
+            Self::Corrupt => write!(f, "save state is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+/// A problem with a program's source that stopped it from being assembled, along with where it
+/// is. `path` is the file the line is in, or None for source that didn't come from a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssemblerError {
+    pub path: Option<PathBuf>,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}:{}: {}", path.display(), self.line, self.message),
+            None => write!(f, "line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+impl std::error::Error for AssemblerError {}
+
+/// A problem with Octo source that stopped it from being compiled, along with where it starts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OctoError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for OctoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for OctoError {}
@@ -1,12 +1,38 @@
-use crate::chip8::VirtualMachine;
-use sdl2::{EventPump, event::Event, keyboard::Scancode};
-use std::time::{Duration, Instant};
+use crate::configuration::KeymapSettings;
+use chip_8_interpreter::VirtualMachine;
+use sdl2::{
+    EventPump,
+    event::Event,
+    keyboard::{Mod, Scancode},
+};
 
 const VALID_KEYS: std::ops::RangeInclusive<usize> = 0x0..=0xf;
-pub const KEYUP_RELEASE_DURATION: Duration = Duration::from_millis(30);
 
-/// Polls the keyboard for input events and passes it back to the caller wrapped in an Option.
-pub fn poll_for_input(event_pump: &mut EventPump) -> Vec<Option<usize>> {
+/// Holding the rewind key (Backspace) is reported as these two events when it's pressed and
+/// released.
+pub const REWIND_START: usize = usize::MAX - 2;
+pub const REWIND_STOP: usize = usize::MAX - 3;
+
+/// The debugger keys: F5 pauses or continues, F6 steps and F7 steps over.
+pub const DEBUG_PAUSE_OR_CONTINUE: usize = usize::MAX - 4;
+pub const DEBUG_STEP: usize = usize::MAX - 5;
+pub const DEBUG_STEP_OVER: usize = usize::MAX - 6;
+
+/// F8 starts, pauses and resumes tracing executed instructions to a file.
+pub const TRACE_TOGGLE: usize = usize::MAX - 7;
+
+/// Number of save state slots, each bound to one of the F1 to F4 keys.
+pub const SAVE_SLOTS: usize = 4;
+
+/// Saving to a save state slot (Shift + F1 to F4) is reported as this plus the slot number (1-4).
+pub const SAVE_STATE: usize = usize::MAX - 0x20;
+
+/// Loading from a save state slot (F1 to F4) is reported as this plus the slot number (1-4).
+pub const LOAD_STATE: usize = usize::MAX - 0x30;
+
+/// Polls the keyboard for input events and passes it back to the caller wrapped in an Option. The
+/// keymap adds extra keys on top of the keypad layout.
+pub fn poll_for_input(event_pump: &mut EventPump, keymap: &KeymapSettings) -> Vec<Option<usize>> {
     let mut input_events = Vec::new();
 
     // Poll for both KeyDown and KeyUp events. Both are needed to detect a change in state of each
@@ -27,6 +53,53 @@ pub fn poll_for_input(event_pump: &mut EventPump) -> Vec<Option<usize>> {
                 scancode: Some(Scancode::Return),
                 ..
             } => Some(usize::MAX - 1),
+            // Holding the key down repeats this event, but rewinding only needs to start once.
+            Event::KeyDown {
+                scancode: Some(Scancode::Backspace),
+                repeat: false,
+                ..
+            } => Some(REWIND_START),
+            Event::KeyUp {
+                scancode: Some(Scancode::Backspace),
+                ..
+            } => Some(REWIND_STOP),
+            Event::KeyDown {
+                scancode: Some(Scancode::F5),
+                ..
+            } => Some(DEBUG_PAUSE_OR_CONTINUE),
+            Event::KeyDown {
+                scancode: Some(Scancode::F6),
+                ..
+            } => Some(DEBUG_STEP),
+            Event::KeyDown {
+                scancode: Some(Scancode::F7),
+                ..
+            } => Some(DEBUG_STEP_OVER),
+            Event::KeyDown {
+                scancode: Some(Scancode::F8),
+                repeat: false,
+                ..
+            } => Some(TRACE_TOGGLE),
+            Event::KeyDown {
+                scancode:
+                    Some(scancode @ (Scancode::F1 | Scancode::F2 | Scancode::F3 | Scancode::F4)),
+                keymod,
+                ..
+            } => {
+                let slot = match scancode {
+                    Scancode::F1 => 1,
+                    Scancode::F2 => 2,
+                    Scancode::F3 => 3,
+                    _ => 4,
+                };
+
+                // Holding either shift key saves to the slot instead of loading from it.
+                if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                    Some(SAVE_STATE + slot)
+                } else {
+                    Some(LOAD_STATE + slot)
+                }
+            }
             Event::KeyDown {
                 scancode: Some(Scancode::Num1),
                 ..
@@ -155,6 +228,14 @@ pub fn poll_for_input(event_pump: &mut EventPump) -> Vec<Option<usize>> {
                 scancode: Some(Scancode::V),
                 ..
             } => Some(0xf0),
+            Event::KeyDown {
+                scancode: Some(scancode),
+                ..
+            } => mapped_key(keymap, scancode),
+            Event::KeyUp {
+                scancode: Some(scancode),
+                ..
+            } => mapped_key(keymap, scancode).map(|key| if key == 0x0 { 0x100 } else { key << 4 }),
             _ => None,
         });
     }
@@ -162,12 +243,23 @@ pub fn poll_for_input(event_pump: &mut EventPump) -> Vec<Option<usize>> {
     input_events
 }
 
+/// Returns the keypad key the keymap puts on a key, if any.
+fn mapped_key(keymap: &KeymapSettings, scancode: Scancode) -> Option<usize> {
+    let key = match scancode {
+        Scancode::Up => keymap.up,
+        Scancode::Down => keymap.down,
+        Scancode::Left => keymap.left,
+        Scancode::Right => keymap.right,
+        Scancode::Space => keymap.a,
+        Scancode::LCtrl => keymap.b,
+        _ => None,
+    };
+
+    key.map(usize::from).filter(|key| VALID_KEYS.contains(key))
+}
+
 /// Takes in an input event and sets the corresponding Chip-8 VM keypad value to pressed or not pressed.
-pub fn set_keypad_value(
-    vm: &mut VirtualMachine,
-    input_event: usize,
-    keypad_shadow_timers: &mut [Instant; 16],
-) {
+pub fn set_keypad_value(vm: &mut VirtualMachine, input_event: usize) {
     let key_event: usize = input_event;
 
     match key_event {
@@ -176,7 +268,7 @@ pub fn set_keypad_value(
             let key_down = key_event;
 
             if VALID_KEYS.contains(&key_down) {
-                vm.keypad[key_down] = true;
+                vm.set_key(key_down, true);
             }
         }
         //KeyUp
@@ -189,10 +281,7 @@ pub fn set_keypad_value(
             };
 
             if VALID_KEYS.contains(&key_up) {
-                vm.keypad[key_up] = false;
-                vm.keypad_shadow[key_up] = true;
-                // Start timing how long a key has been released for.
-                keypad_shadow_timers[key_up] = Instant::now();
+                vm.set_key(key_up, false);
             }
         }
     }
@@ -0,0 +1,39 @@
+//! A Chip-8 virtual machine with no window, audio or input dependencies so it can be driven by
+//! any frontend. The interpreter binary in this crate is just one such frontend built on SDL2 and
+//! the chip8-headless binary is another that runs programs without any window at all.
+//!
+//! A minimal frontend loads a program, steps the VM until the frame time budget runs out, ends
+//! the frame, and then draws the frame buffer. If the program does something the VM can't handle,
+//! `step()` returns a `Chip8Error` and the VM stays faulted until it's reset:
+//!
+//! ```
+//! use chip_8_interpreter::{Chip8Settings, VirtualMachine};
+//!
+//! let mut vm = VirtualMachine::new(&Chip8Settings::default());
+//! vm.load_rom(&[0x00, 0xe0, 0x12, 0x00]).unwrap(); // CLS, then jump back to 0x200 forever.
+//!
+//! while vm.step().unwrap().is_some() {}
+//! vm.tick_timers();
+//!
+//! assert!(vm.framebuffer().iter().all(|pixel| *pixel == 0));
+//! ```
+
+pub mod asm;
+pub mod chip8;
+pub mod database;
+pub mod debugger;
+pub mod difftest;
+pub mod disasm;
+pub mod error;
+pub mod octo;
+pub mod quirks;
+pub mod rewind;
+pub mod rom;
+pub mod trace;
+
+pub use chip8::{Chip8Settings, SaveState, VirtualMachine};
+pub use database::{DatabaseEntry, ProgramDatabase};
+pub use error::{AssemblerError, Chip8Error, OctoError, SaveStateError};
+pub use quirks::{Platform, Quirks};
+pub use rewind::RewindBuffer;
+pub use trace::Tracer;
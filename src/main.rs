@@ -1,36 +1,117 @@
 mod audio_handler;
-mod chip8;
+mod browser;
+mod cli;
 mod configuration;
+mod debug_console;
 mod display;
 mod input_handler;
+mod save_states;
 
 use audio_handler::*;
-use chip8::VirtualMachine;
+use browser::Browser;
+use chip_8_interpreter::{
+    Chip8Error, ProgramDatabase, RewindBuffer, Tracer, VirtualMachine,
+    chip8::MAX_FRAME_TIME,
+    debugger::Debugger,
+    rewind::DEFAULT_REWIND_FRAMES,
+    rom::{read_program, rom_hash},
+};
+use clap::Parser;
+use cli::Cli;
 use configuration::*;
+use debug_console::{DebugCommand, DebugConsole};
 use display::VirtualScreen;
 use input_handler as IH;
-use std::time::Instant;
+use save_states::SaveSlots;
+use sdl2::Sdl;
+use std::{path::Path, time::Instant};
 
 const QUIT: usize = usize::MAX;
 const RESET: usize = usize::MAX - 1;
+const WINDOW_TITLE: &str = "Chip 8";
 
 fn main() -> anyhow::Result<()> {
-    // An array of Instants that represent when each key changed state from pressed to released.
-    let mut keypad_shadow_timers: [Instant; 16] = [Instant::now(); 16];
+    // Setup all user settings, with whatever was given on the command line taking precedence.
+    let cli = Cli::parse();
+    let settings = Settings::load(cli.settings.as_deref(), None, &cli.overrides())?;
+    let database = settings.database.load();
+    let sdl_context = sdl2::init().map_err(anyhow::Error::msg)?;
+
+    // A program given on the command line just runs. Otherwise the user picks programs from the
+    // browser, which comes back whenever they quit one until they quit the browser too.
+    if let Some(rom) = &cli.rom {
+        return run_program(&sdl_context, &cli, &settings, &database, rom, false);
+    }
+
+    let mut browser = Browser::new(&settings.chip8, &database)?;
+    while let Some(choice) = browser.ask()? {
+        if let Err(error) = run_program(
+            &sdl_context,
+            &cli,
+            &settings,
+            &database,
+            &choice.path,
+            choice.tweak,
+        ) {
+            browser.set_message(format!("Couldn't run {}: {error}", choice.path.display()));
+        }
+    }
 
-    // Setup all user settings.
-    let settings = Settings::load()?;
+    Ok(())
+}
 
-    // Get the path of program the user selected so it can be passed to the Chip-8 VM to load.
-    let program_pathbuf = configuration::ask_for_program(&settings.chip8)?;
-    let program_path = program_pathbuf.as_path();
+/// Runs a program in a window until the user quits it. `settings` are the global settings, which
+/// get the program's profile merged in, and the user can tweak them first if they asked to.
+fn run_program(
+    sdl_context: &Sdl,
+    cli: &Cli,
+    settings: &Settings,
+    database: &ProgramDatabase,
+    program_path: &Path,
+    tweak: bool,
+) -> anyhow::Result<()> {
+    // Load the settings again with the program's profile and what the program database
+    // recommends for it merged in, and with whatever the user tweaked in the menu.
+    let mut overrides = cli.overrides();
+    let program = read_program(program_path)?;
+    let program_hash = rom_hash(&program);
+    let profile = RomProfile::new(&settings.chip8, program_path, &program_hash, database);
+    let mut settings = Settings::load(cli.settings.as_deref(), Some(&profile), &overrides)?;
+
+    if tweak {
+        let program_name = program_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let tweaks = configuration::ask_for_tweaks(&settings.chip8, &program_name)?;
+        configuration::merge(&mut overrides, &tweaks.settings);
+
+        if tweaks.save {
+            profile.save(&overrides)?;
+            println!("Saved the profile to {}.", profile.path().display());
+        }
+
+        settings = Settings::load(cli.settings.as_deref(), Some(&profile), &overrides)?;
+    }
 
     // Initialize everything needed to run the Main Operating Loop (MOL).
-    let sdl_context = sdl2::init().map_err(anyhow::Error::msg)?;
-    let buzzer = Buzzer::initialize(&sdl_context, &settings.sound).map_err(anyhow::Error::msg)?;
-    let mut vs = VirtualScreen::initialize(&sdl_context, "Chip 8", &settings.window)?;
-    let mut vm =
-        VirtualMachine::initialize(&settings.chip8, program_path).map_err(anyhow::Error::msg)?;
+    let mut buzzer =
+        Buzzer::initialize(sdl_context, &settings.sound).map_err(anyhow::Error::msg)?;
+    let mut vs = VirtualScreen::initialize(sdl_context, WINDOW_TITLE, &settings.window)?;
+    let mut vm = VirtualMachine::new(&settings.chip8);
+    vm.load_rom(&program)?;
+    let save_slots = SaveSlots::new(&settings.chip8, &program_hash);
+    if let Err(error) = save_slots.load_rpl_flags(&mut vm) {
+        eprintln!("Couldn't load the RPL user flags: {error}.");
+    }
+    let mut rewind_buffer = RewindBuffer::new(DEFAULT_REWIND_FRAMES);
+    let mut rewinding = false;
+    let mut debugger = Debugger::new();
+    let debug_console = DebugConsole::start();
+    if let Some([from, to]) = settings.trace.cycles {
+        let tracer = Tracer::create(Path::new(&settings.trace.path))?.with_cycles(from..to);
+        vm.set_tracer(Some(tracer));
+    }
 
     // Main Operating Loop (MOL). This will run until the user either hits the window close button
     // or presses the Quit key as specified in the input handler.
@@ -39,36 +120,198 @@ fn main() -> anyhow::Result<()> {
         let mol_start_time = Instant::now();
 
         // Get input events
-        let input_events = IH::poll_for_input(&mut vs.event_pump);
+        let input_events = IH::poll_for_input(&mut vs.event_pump, &settings.keymap);
+        let mut debug_commands = debug_console.commands();
         for event in input_events.iter() {
             match event {
                 Some(QUIT) => {
                     break 'MOL;
                 }
-                Some(RESET) => vm.reset(),
+                Some(RESET) => {
+                    vm.reset();
+                    rewind_buffer.clear();
+                    vs.set_title(WINDOW_TITLE)?;
+                }
+                Some(IH::REWIND_START) => {
+                    rewinding = true;
+                    vs.set_title(&format!("{WINDOW_TITLE} - Rewinding"))?;
+                }
+                Some(IH::REWIND_STOP) => {
+                    rewinding = false;
+                    vs.set_title(WINDOW_TITLE)?;
+                }
+                Some(IH::DEBUG_PAUSE_OR_CONTINUE) => {
+                    debug_commands.push(DebugCommand::PauseOrContinue)
+                }
+                Some(IH::DEBUG_STEP) => debug_commands.push(DebugCommand::Step),
+                Some(IH::DEBUG_STEP_OVER) => debug_commands.push(DebugCommand::StepOver),
+                Some(IH::TRACE_TOGGLE) => toggle_trace(&mut vm, &settings.trace)?,
+                Some(event)
+                    if (IH::SAVE_STATE + 1..=IH::SAVE_STATE + IH::SAVE_SLOTS).contains(event) =>
+                {
+                    let slot = event - IH::SAVE_STATE;
+
+                    match save_slots.save(&vm, slot) {
+                        Ok(()) => {
+                            vs.set_title(&format!("{WINDOW_TITLE} - Saved to slot {slot}"))?
+                        }
+                        Err(error) => {
+                            eprintln!("Couldn't save to slot {slot}: {error}.");
+                            vs.set_title(&format!(
+                                "{WINDOW_TITLE} - Couldn't save to slot {slot}"
+                            ))?;
+                        }
+                    }
+                }
+                Some(event)
+                    if (IH::LOAD_STATE + 1..=IH::LOAD_STATE + IH::SAVE_SLOTS).contains(event) =>
+                {
+                    let slot = event - IH::LOAD_STATE;
+
+                    match save_slots.load(&mut vm, slot) {
+                        Ok(()) => {
+                            rewind_buffer.clear();
+                            vs.set_title(&format!("{WINDOW_TITLE} - Loaded slot {slot}"))?;
+                        }
+                        Err(error) => {
+                            eprintln!("Couldn't load slot {slot}: {error}.");
+                            vs.set_title(&format!("{WINDOW_TITLE} - Couldn't load slot {slot}"))?;
+                        }
+                    }
+                }
                 // Whatever remaining event picked up by the input handler must be a keypad key
-                Some(key) => IH::set_keypad_value(&mut vm, *key, &mut keypad_shadow_timers),
+                Some(key) => IH::set_keypad_value(&mut vm, *key),
                 None => (),
             }
         }
 
-        // Simulate the Chip-8 VM for a single operation cycle
-        vm.simulate_operation_cycle(&mol_start_time, &mut keypad_shadow_timers);
+        // Carry out whatever the debugger was told to do, from the terminal or with the debugger
+        // keys. Stepping runs the program, so it can fault just like it can below.
+        for command in debug_commands {
+            if let Err(error) = debug_console::execute(command, &mut debugger, &mut vm) {
+                show_fault(&mut vs, error)?;
+            }
+        }
+
+        // While the rewind key is held, play the program backwards one frame per frame instead of
+        // running it. This also gets a faulted or exited program going again. The keypad is left
+        // as it is so keys released while rewinding aren't stuck down afterwards.
+        if rewinding {
+            buzzer.pause();
+
+            let keypad = vm.keypad;
+            if rewind_buffer.rewind(&mut vm) {
+                vm.keypad = keypad;
+                vs.render_chip_8_frame(&vm, &mol_start_time, &settings.window)
+                    .map_err(anyhow::Error::msg)?;
+                vm.draw_flag = false;
+            }
+
+            std::thread::sleep(MAX_FRAME_TIME.saturating_sub(mol_start_time.elapsed()));
+            continue 'MOL;
+        }
+
+        // The debugger stopped the program, either because it was told to or a breakpoint was hit.
+        // Nothing is simulated until it's told to continue, but anything it steps is still drawn.
+        let was_paused = debugger.paused();
+        if debugger.check(&mut vm) {
+            if !was_paused {
+                println!("Stopped.\n{}\n", debugger.view(&vm));
+            }
+
+            buzzer.pause();
+            if vm.draw_flag {
+                vs.render_chip_8_frame(&vm, &mol_start_time, &settings.window)
+                    .map_err(anyhow::Error::msg)?;
+                vm.draw_flag = false;
+            }
+
+            std::thread::sleep(MAX_FRAME_TIME);
+            continue 'MOL;
+        }
+
+        // A faulted or exited VM is halted until it's reset, so there's nothing to simulate. Wait
+        // a frame so this loop doesn't just spin while the user decides what to do.
+        if vm.fault().is_some() || vm.exited() {
+            buzzer.pause();
+            std::thread::sleep(MAX_FRAME_TIME);
+            continue 'MOL;
+        }
+
+        // Simulate the Chip-8 VM for a single operation cycle. If the program faults, tell the
+        // user what happened instead of crashing the whole window.
+        match vm.simulate_operation_cycle(mol_start_time.elapsed()) {
+            Err(error) => show_fault(&mut vs, error)?,
+            Ok(()) if vm.exited() => {
+                save_rpl_flags(&save_slots, &vm);
+                vs.set_title(&format!(
+                    "{WINDOW_TITLE} - Program exited (press Return to restart)"
+                ))?;
+            }
+            Ok(()) => (),
+        }
 
-        // Play or pause the buzzer as appropriate
+        // Play or pause the buzzer as appropriate. XO-CHIP programs can change what it plays.
         if vm.sound_timer > 0 {
+            buzzer
+                .lock()
+                .set_pattern(vm.audio_pattern(), vm.audio_playback_rate());
             buzzer.resume();
         } else {
             buzzer.pause();
         }
 
-        // Update VS with Chip-8 VM frame buffer data
+        // Update VS with Chip-8 VM frame buffer data. The frame is done, so also remember it for
+        // rewinding.
         if vm.draw_flag {
+            rewind_buffer.push(&vm);
             vs.render_chip_8_frame(&vm, &mol_start_time, &settings.window)
                 .map_err(anyhow::Error::msg)?;
             vm.draw_flag = false;
         }
     }
 
+    // The program is done, so keep its RPL user flags for the next time it runs.
+    save_rpl_flags(&save_slots, &vm);
+
+    Ok(())
+}
+
+/// Saves the program's RPL user flags, only telling the user if that didn't work since it's no
+/// reason to stop.
+fn save_rpl_flags(save_slots: &SaveSlots, vm: &VirtualMachine) {
+    if let Err(error) = save_slots.save_rpl_flags(vm) {
+        eprintln!("Couldn't save the RPL user flags: {error}.");
+    }
+}
+
+/// Tells the user the program faulted and how to get going again.
+fn show_fault(vs: &mut VirtualScreen, error: Chip8Error) -> anyhow::Result<()> {
+    eprintln!("Chip-8 VM halted: {error}.");
+    vs.set_title(&format!(
+        "{WINDOW_TITLE} - Halted: {error} (press Return to reset)"
+    ))
+}
+
+/// Starts tracing to the trace file if there's no trace yet, otherwise pauses or resumes it.
+fn toggle_trace(vm: &mut VirtualMachine, trace_settings: &TraceSettings) -> anyhow::Result<()> {
+    match vm.tracer_mut() {
+        Some(tracer) => {
+            let recording = !tracer.recording();
+            tracer.set_recording(recording);
+            tracer.flush()?;
+
+            if recording {
+                println!("Tracing resumed.");
+            } else {
+                println!("Tracing paused.");
+            }
+        }
+        None => {
+            vm.set_tracer(Some(Tracer::create(Path::new(&trace_settings.path))?));
+            println!("Tracing to {}.", trace_settings.path);
+        }
+    }
+
     Ok(())
 }
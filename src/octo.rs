@@ -0,0 +1,1158 @@
+//! Compiles programs written in Octo, the assembly language most modern Chip-8 programs are
+//! published in, into the bytes of a program (ROM) that loads at 0x200.
+//!
+//! Supported are all of Octo's instructions (including the SUPER-CHIP and XO-CHIP ones), labels,
+//! `:alias`, `:const`, `:calc`, `:macro`, `:byte`, `:org`, `:call`, `:unpack`, `if ... then`,
+//! `if ... begin ... else ... end`, `loop ... while ... again` and comments starting with `#`.
+//! Debugging directives like `:breakpoint` and `:monitor` are accepted and ignored.
+//!
+//! Like Octo, execution starts at the `main` label. If anything other than `: main` comes first,
+//! a jump to main is put at 0x200.
+
+use crate::{
+    chip8::{MEMORY_SIZE, PROGRAM_START},
+    error::OctoError,
+};
+use std::collections::{HashMap, VecDeque};
+
+/// Macros that expand into themselves would otherwise never stop expanding.
+const MAX_MACRO_EXPANSIONS: usize = 100_000;
+
+/// Compiles Octo source into a program.
+pub fn compile(source: &str) -> Result<Vec<u8>, OctoError> {
+    Compiler::new(source).compile()
+}
+
+/// A piece of source between whitespace, along with where it starts for error messages.
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+impl Token {
+    fn error(&self, message: impl Into<String>) -> OctoError {
+        OctoError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+}
+
+/// Splits source into tokens, leaving out comments.
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let mut token: Option<Token> = None;
+
+        for (column_index, character) in line.chars().enumerate() {
+            if character.is_whitespace() || (character == '#' && token.is_none()) {
+                tokens.extend(token.take());
+                if character == '#' {
+                    break;
+                }
+            } else {
+                token
+                    .get_or_insert_with(|| Token {
+                        text: String::new(),
+                        line: line_index + 1,
+                        column: column_index + 1,
+                    })
+                    .text
+                    .push(character);
+            }
+        }
+
+        tokens.extend(token);
+    }
+
+    tokens
+}
+
+/// How to fill in an address once the label it refers to is defined.
+#[derive(Clone, Copy)]
+enum ReferenceKind {
+    /// The low 12 bits of an opcode, like the NNN of 1NNN.
+    Nnn,
+    /// The 16-bit word after F000.
+    Long,
+    /// The two bytes of `v0 := NN` and `v1 := NN` that `:unpack` puts the address in.
+    Unpack,
+}
+
+/// A use of a label before it's defined.
+struct Reference {
+    address: usize,
+    kind: ReferenceKind,
+    name: Token,
+}
+
+struct Macro {
+    arguments: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// A `begin` or `loop` waiting for its `end` or `again`.
+enum Block {
+    Begin {
+        /// Address of the jump that skips over the block, filled in by `else` or `end`.
+        jump: usize,
+        has_else: bool,
+    },
+    Loop {
+        start: usize,
+        /// Addresses of the jumps out of the loop from `while`, filled in by `again`.
+        exits: Vec<usize>,
+    },
+}
+
+/// The ways `if` and `while` can compare things.
+enum Condition {
+    Equal(u16, Operand),
+    NotEqual(u16, Operand),
+    Less(u16, Operand),
+    Greater(u16, Operand),
+    LessOrEqual(u16, Operand),
+    GreaterOrEqual(u16, Operand),
+    Key(u16),
+    NotKey(u16),
+}
+
+/// The right hand side of a comparison.
+enum Operand {
+    Register(u16),
+    Byte(u16),
+}
+
+struct Compiler {
+    tokens: VecDeque<Token>,
+    /// The last token taken, for errors about the source ending too soon.
+    last: Token,
+    memory: Vec<u8>,
+    here: usize,
+    /// One past the highest address anything was compiled to.
+    end: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u16>,
+    macros: HashMap<String, Macro>,
+    references: Vec<Reference>,
+    blocks: Vec<(Block, Token)>,
+    macro_expansions: usize,
+    /// Whether it's still undecided if the program needs a jump to main at 0x200.
+    main_jump_pending: bool,
+}
+
+impl Compiler {
+    fn new(source: &str) -> Self {
+        Self {
+            tokens: tokenize(source),
+            last: Token {
+                text: String::new(),
+                line: 1,
+                column: 1,
+            },
+            memory: vec![0; MEMORY_SIZE],
+            here: PROGRAM_START,
+            end: PROGRAM_START,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            references: Vec::new(),
+            blocks: Vec::new(),
+            macro_expansions: 0,
+            main_jump_pending: true,
+        }
+    }
+
+    fn compile(mut self) -> Result<Vec<u8>, OctoError> {
+        while let Some(token) = self.next() {
+            self.statement(token)?;
+        }
+
+        if let Some((block, token)) = self.blocks.last() {
+            let missing = match block {
+                Block::Begin { .. } => "end",
+                Block::Loop { .. } => "again",
+            };
+            return Err(token.error(format!("'{}' is missing its '{missing}'", token.text)));
+        }
+
+        for reference in std::mem::take(&mut self.references) {
+            let Some(&address) = self.labels.get(&reference.name.text) else {
+                return Err(reference
+                    .name
+                    .error(format!("'{}' isn't defined", reference.name.text)));
+            };
+            self.fill_in(reference.address, reference.kind, address, &reference.name)?;
+        }
+
+        Ok(self.memory[PROGRAM_START..self.end].to_vec())
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.pop_front()?;
+        self.last = token.clone();
+
+        Some(token)
+    }
+
+    /// Takes the next token, which has to be there.
+    fn expect_token(&mut self) -> Result<Token, OctoError> {
+        self.next().ok_or_else(|| {
+            self.last
+                .error("the program ends in the middle of a statement")
+        })
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token, OctoError> {
+        let token = self.expect_token()?;
+        if token.text != text {
+            return Err(token.error(format!("expected '{text}' but found '{}'", token.text)));
+        }
+
+        Ok(token)
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens.front().is_some_and(|token| token.text == text)
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), OctoError> {
+        match token.text.as_str() {
+            ":" => {
+                let name = self.expect_name()?;
+                if self.labels.contains_key(&name.text) {
+                    return Err(name.error(format!("'{}' is already defined", name.text)));
+                }
+                if name.text == "main" && self.here == PROGRAM_START {
+                    self.main_jump_pending = false;
+                }
+                self.place_main_jump()?;
+                self.labels.insert(name.text, self.here);
+            }
+            ":alias" => {
+                let name = self.expect_name()?;
+                let register = self.expect_register()?;
+                self.aliases.insert(name.text, register);
+            }
+            ":const" => {
+                let name = self.expect_name()?;
+                let value_token = self.expect_token()?;
+                let value = self.number(&value_token)?;
+                self.constants.insert(name.text, value);
+            }
+            ":calc" => {
+                let name = self.expect_name()?;
+                let value = self.calc()?;
+                self.constants.insert(name.text, value);
+            }
+            ":macro" => self.define_macro()?,
+            ":byte" => {
+                let value = if self.peek_is("{") {
+                    let value = self.calc()?;
+                    self.integer(value, -128, 255, &token)? as u16 & 0xff
+                } else {
+                    let value_token = self.expect_token()?;
+                    self.byte(&value_token)?
+                };
+                self.emit(&[value as u8], &token)?;
+            }
+            ":org" => {
+                self.place_main_jump()?;
+                let value = if self.peek_is("{") {
+                    self.calc()?
+                } else {
+                    let value_token = self.expect_token()?;
+                    self.number(&value_token)?
+                };
+                self.here = self.integer(value, PROGRAM_START as i64, 0xffff, &token)? as usize;
+            }
+            ":call" => {
+                let target = self.expect_token()?;
+                self.emit_address_opcode(0x2000, &target)?;
+            }
+            ":unpack" => self.unpack(&token)?,
+            ":breakpoint" => {
+                self.expect_token()?;
+            }
+            ":monitor" => {
+                self.expect_token()?;
+                self.expect_token()?;
+            }
+            "clear" => self.emit_opcode(0x00e0, &token)?,
+            "return" | ";" => self.emit_opcode(0x00ee, &token)?,
+            "scroll-down" => {
+                let n = self.expect_nibble()?;
+                self.emit_opcode(0x00c0 | n, &token)?;
+            }
+            "scroll-up" => {
+                let n = self.expect_nibble()?;
+                self.emit_opcode(0x00d0 | n, &token)?;
+            }
+            "scroll-right" => self.emit_opcode(0x00fb, &token)?,
+            "scroll-left" => self.emit_opcode(0x00fc, &token)?,
+            "exit" => self.emit_opcode(0x00fd, &token)?,
+            "lores" => self.emit_opcode(0x00fe, &token)?,
+            "hires" => self.emit_opcode(0x00ff, &token)?,
+            "audio" => self.emit_opcode(0xf002, &token)?,
+            "plane" => {
+                let n = self.expect_nibble()?;
+                self.emit_opcode(0xf001 | (n << 8), &token)?;
+            }
+            "native" => {
+                let target = self.expect_token()?;
+                self.emit_address_opcode(0x0000, &target)?;
+            }
+            "jump" => {
+                let target = self.expect_token()?;
+                self.emit_address_opcode(0x1000, &target)?;
+            }
+            "jump0" => {
+                let target = self.expect_token()?;
+                self.emit_address_opcode(0xb000, &target)?;
+            }
+            "sprite" => {
+                let x = self.expect_register()?;
+                let y = self.expect_register()?;
+                let n = self.expect_nibble()?;
+                self.emit_opcode(0xd000 | (x << 8) | (y << 4) | n, &token)?;
+            }
+            "bcd" => self.register_opcode(0xf033, &token)?,
+            "saveflags" => self.register_opcode(0xf075, &token)?,
+            "loadflags" => self.register_opcode(0xf085, &token)?,
+            "save" | "load" => {
+                let x = self.expect_register()?;
+                if self.peek_is("-") {
+                    self.expect("-")?;
+                    let y = self.expect_register()?;
+                    let opcode = if token.text == "save" { 0x5002 } else { 0x5003 };
+                    self.emit_opcode(opcode | (x << 8) | (y << 4), &token)?;
+                } else {
+                    let opcode = if token.text == "save" { 0xf055 } else { 0xf065 };
+                    self.emit_opcode(opcode | (x << 8), &token)?;
+                }
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let opcode = match token.text.as_str() {
+                    "delay" => 0xf015,
+                    "buzzer" => 0xf018,
+                    _ => 0xf03a,
+                };
+                self.register_opcode(opcode, &token)?;
+            }
+            "i" => self.index_statement(&token)?,
+            "if" => self.if_statement(&token)?,
+            "else" => match self.blocks.pop() {
+                Some((
+                    Block::Begin {
+                        jump,
+                        has_else: false,
+                    },
+                    begin,
+                )) => {
+                    let else_jump = self.here;
+                    self.emit_opcode(0x1000, &token)?;
+                    self.fill_in(jump, ReferenceKind::Nnn, self.here, &token)?;
+                    self.blocks.push((
+                        Block::Begin {
+                            jump: else_jump,
+                            has_else: true,
+                        },
+                        begin,
+                    ));
+                }
+                _ => return Err(token.error("'else' without an 'if ... begin'")),
+            },
+            "end" => match self.blocks.pop() {
+                Some((Block::Begin { jump, .. }, _)) => {
+                    self.fill_in(jump, ReferenceKind::Nnn, self.here, &token)?;
+                }
+                _ => return Err(token.error("'end' without an 'if ... begin'")),
+            },
+            "loop" => self.blocks.push((
+                Block::Loop {
+                    start: self.here,
+                    exits: Vec::new(),
+                },
+                token,
+            )),
+            "while" => {
+                let condition = self.condition()?;
+                // Skip the jump out of the loop while the condition holds.
+                self.emit_skip(&condition, true, &token)?;
+                let exit = self.here;
+                self.emit_opcode(0x1000, &token)?;
+
+                let Some(exits) = self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find_map(|(block, _)| match block {
+                        Block::Loop { exits, .. } => Some(exits),
+                        Block::Begin { .. } => None,
+                    })
+                else {
+                    return Err(token.error("'while' outside of a 'loop'"));
+                };
+                exits.push(exit);
+            }
+            "again" => match self.blocks.pop() {
+                Some((Block::Loop { start, exits }, _)) => {
+                    self.emit_address(0x1000, start, &token)?;
+                    for exit in exits {
+                        self.fill_in(exit, ReferenceKind::Nnn, self.here, &token)?;
+                    }
+                }
+                _ => return Err(token.error("'again' without a 'loop'")),
+            },
+            _ => {
+                if let Some(register) = self.register(&token) {
+                    self.register_statement(register, &token)?;
+                } else if self.macros.contains_key(&token.text) {
+                    self.expand_macro(&token)?;
+                } else if let Ok(value) = self.number(&token) {
+                    let byte = self.integer(value, -128, 255, &token)?;
+                    self.emit(&[byte as u8], &token)?;
+                } else if is_name(&token.text) {
+                    // A name on its own calls the subroutine at that label.
+                    self.emit_address_opcode(0x2000, &token)?;
+                } else {
+                    return Err(token.error(format!("'{}' isn't a statement", token.text)));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compiles `vx := ...`, `vx += ...` and the other operations on a register.
+    fn register_statement(&mut self, x: u16, token: &Token) -> Result<(), OctoError> {
+        let operator = self.expect_token()?;
+        let operand = self.expect_token()?;
+        let x = x << 8;
+
+        if let Some(y) = self.register(&operand) {
+            let opcode = match operator.text.as_str() {
+                ":=" => 0x8000,
+                "|=" => 0x8001,
+                "&=" => 0x8002,
+                "^=" => 0x8003,
+                "+=" => 0x8004,
+                "-=" => 0x8005,
+                ">>=" => 0x8006,
+                "=-" => 0x8007,
+                "<<=" => 0x800e,
+                _ => return Err(operator.error(format!("'{}' isn't an operator", operator.text))),
+            };
+            return self.emit_opcode(opcode | x | (y << 4), token);
+        }
+
+        let opcode = match (operator.text.as_str(), operand.text.as_str()) {
+            (":=", "delay") => 0xf007 | x,
+            (":=", "key") => 0xf00a | x,
+            (":=", "random") => {
+                let mask = self.expect_token()?;
+                0xc000 | x | self.byte(&mask)?
+            }
+            (":=", _) => 0x6000 | x | self.byte(&operand)?,
+            ("+=", _) => 0x7000 | x | self.byte(&operand)?,
+            // There's no subtraction of a number, so add its two's complement instead.
+            ("-=", _) => 0x7000 | x | (self.byte(&operand)?.wrapping_neg() & 0xff),
+            _ => {
+                return Err(operand.error(format!(
+                    "'{}' can't be used with '{}'",
+                    operand.text, operator.text
+                )));
+            }
+        };
+
+        self.emit_opcode(opcode, token)
+    }
+
+    /// Compiles `i := ...` and `i += vx`.
+    fn index_statement(&mut self, token: &Token) -> Result<(), OctoError> {
+        let operator = self.expect_token()?;
+        match operator.text.as_str() {
+            "+=" => self.register_opcode(0xf01e, token),
+            ":=" => {
+                let operand = self.expect_token()?;
+                match operand.text.as_str() {
+                    "hex" => self.register_opcode(0xf029, token),
+                    "bighex" => self.register_opcode(0xf030, token),
+                    "long" => {
+                        let target = self.expect_token()?;
+                        self.emit(&[0xf0, 0x00], token)?;
+                        let address = self.here;
+                        self.emit(&[0x00, 0x00], token)?;
+                        self.address_operand(address, ReferenceKind::Long, &target)
+                    }
+                    _ => self.emit_address_opcode(0xa000, &operand),
+                }
+            }
+            _ => Err(operator.error(format!("'{}' can't be used with i", operator.text))),
+        }
+    }
+
+    fn if_statement(&mut self, token: &Token) -> Result<(), OctoError> {
+        let condition = self.condition()?;
+        let then_or_begin = self.expect_token()?;
+
+        match then_or_begin.text.as_str() {
+            // Skip the next statement unless the condition holds.
+            "then" => self.emit_skip(&condition, false, token),
+            // Skip the jump over the block if the condition holds.
+            "begin" => {
+                self.emit_skip(&condition, true, token)?;
+                let jump = self.here;
+                self.emit_opcode(0x1000, token)?;
+                self.blocks.push((
+                    Block::Begin {
+                        jump,
+                        has_else: false,
+                    },
+                    token.clone(),
+                ));
+                Ok(())
+            }
+            _ => Err(then_or_begin.error(format!(
+                "expected 'then' or 'begin' but found '{}'",
+                then_or_begin.text
+            ))),
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition, OctoError> {
+        let x = self.expect_register()?;
+        let operator = self.expect_token()?;
+
+        match operator.text.as_str() {
+            "key" => return Ok(Condition::Key(x)),
+            "-key" => return Ok(Condition::NotKey(x)),
+            _ => (),
+        }
+
+        let operand_token = self.expect_token()?;
+        let operand = match self.register(&operand_token) {
+            Some(y) => Operand::Register(y),
+            None => Operand::Byte(self.byte(&operand_token)?),
+        };
+
+        Ok(match operator.text.as_str() {
+            "==" => Condition::Equal(x, operand),
+            "!=" => Condition::NotEqual(x, operand),
+            "<" => Condition::Less(x, operand),
+            ">" => Condition::Greater(x, operand),
+            "<=" => Condition::LessOrEqual(x, operand),
+            ">=" => Condition::GreaterOrEqual(x, operand),
+            _ => {
+                return Err(operator.error(format!("'{}' isn't a comparison", operator.text)));
+            }
+        })
+    }
+
+    /// Compiles instructions that skip the next one when the condition is `skip_when`.
+    fn emit_skip(
+        &mut self,
+        condition: &Condition,
+        skip_when: bool,
+        token: &Token,
+    ) -> Result<(), OctoError> {
+        let (x, operand, equal) = match condition {
+            Condition::Key(x) | Condition::NotKey(x) => {
+                let pressed = matches!(condition, Condition::Key(_)) == skip_when;
+                let opcode = if pressed { 0xe09e } else { 0xe0a1 };
+                return self.emit_opcode(opcode | (x << 8), token);
+            }
+            Condition::Equal(x, operand) => (x, operand, skip_when),
+            Condition::NotEqual(x, operand) => (x, operand, !skip_when),
+            Condition::Less(x, operand)
+            | Condition::Greater(x, operand)
+            | Condition::LessOrEqual(x, operand)
+            | Condition::GreaterOrEqual(x, operand) => {
+                return self.emit_comparison(condition, *x, operand, skip_when, token);
+            }
+        };
+
+        let opcode = match (operand, equal) {
+            (Operand::Byte(nn), true) => 0x3000 | (x << 8) | nn,
+            (Operand::Byte(nn), false) => 0x4000 | (x << 8) | nn,
+            (Operand::Register(y), true) => 0x5000 | (x << 8) | (y << 4),
+            (Operand::Register(y), false) => 0x9000 | (x << 8) | (y << 4),
+        };
+
+        self.emit_opcode(opcode, token)
+    }
+
+    /// The Chip-8 can only skip on equality, so ordered comparisons subtract with VF and skip on
+    /// the borrow flag instead, just like Octo does. That means they overwrite VF.
+    fn emit_comparison(
+        &mut self,
+        condition: &Condition,
+        x: u16,
+        operand: &Operand,
+        skip_when: bool,
+        token: &Token,
+    ) -> Result<(), OctoError> {
+        match operand {
+            Operand::Register(y) => self.emit_opcode(0x8f00 | (y << 4), token)?,
+            Operand::Byte(nn) => self.emit_opcode(0x6f00 | nn, token)?,
+        }
+
+        // 8FX5 leaves VF at 1 when the operand >= vx, and 8FX7 leaves it at 1 when vx >= the
+        // operand. Pick whichever one answers the comparison, and what VF is when it holds.
+        let (subtraction, flag_when_true) = match condition {
+            Condition::Greater(..) => (0x8f05, 0),
+            Condition::Less(..) => (0x8f07, 0),
+            Condition::GreaterOrEqual(..) => (0x8f07, 1),
+            _ => (0x8f05, 1),
+        };
+        self.emit_opcode(subtraction | (x << 4), token)?;
+
+        let flag = if skip_when {
+            flag_when_true
+        } else {
+            1 - flag_when_true
+        };
+        self.emit_opcode(0x3f00 | flag, token)
+    }
+
+    /// Compiles `:unpack n label`, which loads the label's address into v0 and v1 with `n` in the
+    /// high nibble of v0.
+    fn unpack(&mut self, token: &Token) -> Result<(), OctoError> {
+        let nibble = self.expect_nibble()?;
+        let target = self.expect_token()?;
+
+        let address = self.here;
+        self.emit(&[0x60, (nibble << 4) as u8, 0x61, 0x00], token)?;
+        self.address_operand(address, ReferenceKind::Unpack, &target)
+    }
+
+    fn define_macro(&mut self) -> Result<(), OctoError> {
+        let name = self.expect_name()?;
+        let mut arguments = Vec::new();
+        loop {
+            let token = self.expect_token()?;
+            if token.text == "{" {
+                break;
+            }
+            arguments.push(token.text);
+        }
+
+        let body = self.braced_tokens()?;
+        self.macros.insert(name.text, Macro { arguments, body });
+
+        Ok(())
+    }
+
+    /// Takes tokens up to the `}` matching a `{` that was already taken.
+    fn braced_tokens(&mut self) -> Result<Vec<Token>, OctoError> {
+        let mut tokens = Vec::new();
+        let mut depth = 0;
+
+        loop {
+            let token = self.expect_token()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => return Ok(tokens),
+                "}" => depth -= 1,
+                _ => (),
+            }
+            tokens.push(token);
+        }
+    }
+
+    /// Replaces a macro's name and arguments with its body.
+    fn expand_macro(&mut self, name: &Token) -> Result<(), OctoError> {
+        self.macro_expansions += 1;
+        if self.macro_expansions > MAX_MACRO_EXPANSIONS {
+            return Err(name.error(format!("'{}' never stops expanding", name.text)));
+        }
+
+        let argument_names = self.macros[&name.text].arguments.clone();
+        let mut arguments = HashMap::new();
+        for argument_name in argument_names {
+            let argument = self.expect_token()?;
+            arguments.insert(argument_name, argument);
+        }
+
+        let body: Vec<Token> = self.macros[&name.text]
+            .body
+            .iter()
+            .map(|token| arguments.get(&token.text).unwrap_or(token).clone())
+            .collect();
+        for token in body.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+
+        Ok(())
+    }
+
+    /// Evaluates a `:calc` style expression in braces. Like in Octo, there's no precedence and
+    /// operators are evaluated from right to left, so `2 * 3 + 1` is 8. Use parentheses to group.
+    fn calc(&mut self) -> Result<f64, OctoError> {
+        let open = self.expect("{")?;
+        let tokens = self.braced_tokens()?;
+        let mut tokens = tokens.iter().peekable();
+
+        let value = self.calc_expression(&mut tokens, &open)?;
+        if let Some(token) = tokens.next() {
+            return Err(token.error(format!("unexpected '{}' in expression", token.text)));
+        }
+
+        Ok(value)
+    }
+
+    fn calc_expression<'t>(
+        &self,
+        tokens: &mut std::iter::Peekable<std::slice::Iter<'t, Token>>,
+        open: &Token,
+    ) -> Result<f64, OctoError> {
+        let left = self.calc_term(tokens, open)?;
+
+        let Some(operator) = tokens.peek().filter(|token| token.text != ")").copied() else {
+            return Ok(left);
+        };
+        tokens.next();
+        let right = self.calc_expression(tokens, open)?;
+        let (left_integer, right_integer) = (left as i64, right as i64);
+
+        Ok(match operator.text.as_str() {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" => left / right,
+            "%" => left % right,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "&" => (left_integer & right_integer) as f64,
+            "|" => (left_integer | right_integer) as f64,
+            "^" => (left_integer ^ right_integer) as f64,
+            "<<" => left_integer.checked_shl(right_integer as u32).unwrap_or(0) as f64,
+            ">>" => left_integer.checked_shr(right_integer as u32).unwrap_or(0) as f64,
+            "<" => f64::from(left < right),
+            ">" => f64::from(left > right),
+            "<=" => f64::from(left <= right),
+            ">=" => f64::from(left >= right),
+            "==" => f64::from(left == right),
+            "!=" => f64::from(left != right),
+            _ => {
+                return Err(operator.error(format!("'{}' isn't an operator", operator.text)));
+            }
+        })
+    }
+
+    fn calc_term<'t>(
+        &self,
+        tokens: &mut std::iter::Peekable<std::slice::Iter<'t, Token>>,
+        open: &Token,
+    ) -> Result<f64, OctoError> {
+        let Some(token) = tokens.next() else {
+            return Err(open.error("the expression is missing a value"));
+        };
+
+        let unary = |operation: fn(f64) -> f64, compiler: &Self, tokens: &mut _| {
+            compiler.calc_term(tokens, open).map(operation)
+        };
+
+        match token.text.as_str() {
+            "(" => {
+                let value = self.calc_expression(tokens, open)?;
+                match tokens.next() {
+                    Some(close) if close.text == ")" => Ok(value),
+                    _ => Err(token.error("'(' is missing its ')'")),
+                }
+            }
+            "-" => unary(|value| -value, self, tokens),
+            "~" => unary(|value| !(value as i64) as f64, self, tokens),
+            "!" => unary(|value| f64::from(value == 0.0), self, tokens),
+            "abs" => unary(f64::abs, self, tokens),
+            "sqrt" => unary(f64::sqrt, self, tokens),
+            "sin" => unary(f64::sin, self, tokens),
+            "cos" => unary(f64::cos, self, tokens),
+            "tan" => unary(f64::tan, self, tokens),
+            "exp" => unary(f64::exp, self, tokens),
+            "log" => unary(f64::ln, self, tokens),
+            "sign" => unary(f64::signum, self, tokens),
+            "ceil" => unary(f64::ceil, self, tokens),
+            "floor" => unary(f64::floor, self, tokens),
+            "HERE" => Ok(self.here as f64),
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            _ => match self.labels.get(&token.text) {
+                Some(address) => Ok(*address as f64),
+                None => self.number(token),
+            },
+        }
+    }
+
+    /// Returns the value of a number or a constant.
+    fn number(&self, token: &Token) -> Result<f64, OctoError> {
+        if let Some(value) = self.constants.get(&token.text) {
+            return Ok(*value);
+        }
+
+        let (negative, digits) = match token.text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, token.text.as_str()),
+        };
+        let value = if let Some(hex) = digits.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16).ok()
+        } else if let Some(binary) = digits.strip_prefix("0b") {
+            i64::from_str_radix(binary, 2).ok()
+        } else {
+            digits.parse().ok()
+        };
+
+        match value {
+            Some(value) if negative => Ok(-value as f64),
+            Some(value) => Ok(value as f64),
+            None => Err(token.error(format!("'{}' isn't a number or constant", token.text))),
+        }
+    }
+
+    /// Rounds a value down and checks that it's in range.
+    fn integer(&self, value: f64, min: i64, max: i64, token: &Token) -> Result<i64, OctoError> {
+        let integer = value.floor() as i64;
+        if !(min..=max).contains(&integer) {
+            return Err(token.error(format!("{value} isn't between {min} and {max}")));
+        }
+
+        Ok(integer)
+    }
+
+    /// Reads a byte operand. Negative numbers down to -128 are stored as their two's complement.
+    fn byte(&self, token: &Token) -> Result<u16, OctoError> {
+        let value = self.number(token)?;
+
+        Ok(self.integer(value, -128, 255, token)? as u16 & 0xff)
+    }
+
+    fn expect_nibble(&mut self) -> Result<u16, OctoError> {
+        let token = self.expect_token()?;
+        let value = self.number(&token)?;
+
+        Ok(self.integer(value, 0, 15, &token)? as u16)
+    }
+
+    fn register(&self, token: &Token) -> Option<u16> {
+        if let Some(register) = self.aliases.get(&token.text) {
+            return Some(*register);
+        }
+
+        token
+            .text
+            .strip_prefix(['v', 'V'])
+            .filter(|register| register.len() == 1)
+            .and_then(|register| u16::from_str_radix(register, 16).ok())
+    }
+
+    fn expect_register(&mut self) -> Result<u16, OctoError> {
+        let token = self.expect_token()?;
+
+        self.register(&token)
+            .ok_or_else(|| token.error(format!("'{}' isn't a register", token.text)))
+    }
+
+    fn expect_name(&mut self) -> Result<Token, OctoError> {
+        let token = self.expect_token()?;
+        if !is_name(&token.text) {
+            return Err(token.error(format!("'{}' isn't a valid name", token.text)));
+        }
+
+        Ok(token)
+    }
+
+    /// Compiles an opcode that only takes a register, like FX33.
+    fn register_opcode(&mut self, opcode: u16, token: &Token) -> Result<(), OctoError> {
+        let x = self.expect_register()?;
+
+        self.emit_opcode(opcode | (x << 8), token)
+    }
+
+    /// Compiles an opcode that takes an address, which can be a number, a constant or a label
+    /// that doesn't have to be defined yet.
+    fn emit_address_opcode(&mut self, opcode: u16, target: &Token) -> Result<(), OctoError> {
+        let address = self.here;
+        self.emit_opcode(opcode, target)?;
+
+        self.address_operand(address, ReferenceKind::Nnn, target)
+    }
+
+    fn emit_address(&mut self, opcode: u16, target: usize, token: &Token) -> Result<(), OctoError> {
+        let address = self.here;
+        self.emit_opcode(opcode, token)?;
+
+        self.fill_in(address, ReferenceKind::Nnn, target, token)
+    }
+
+    /// Fills in the address operand of what was just compiled at `address`, now if the target is
+    /// known and once every label is defined otherwise.
+    fn address_operand(
+        &mut self,
+        address: usize,
+        kind: ReferenceKind,
+        target: &Token,
+    ) -> Result<(), OctoError> {
+        if let Some(&label_address) = self.labels.get(&target.text) {
+            return self.fill_in(address, kind, label_address, target);
+        }
+        if let Ok(value) = self.number(target) {
+            let value = self.integer(value, 0, 0xffff, target)? as usize;
+            return self.fill_in(address, kind, value, target);
+        }
+        if !is_name(&target.text) {
+            return Err(target.error(format!("'{}' isn't an address", target.text)));
+        }
+
+        self.references.push(Reference {
+            address,
+            kind,
+            name: target.clone(),
+        });
+
+        Ok(())
+    }
+
+    fn fill_in(
+        &mut self,
+        address: usize,
+        kind: ReferenceKind,
+        target: usize,
+        token: &Token,
+    ) -> Result<(), OctoError> {
+        let max = match kind {
+            ReferenceKind::Nnn | ReferenceKind::Unpack => 0xfff,
+            ReferenceKind::Long => 0xffff,
+        };
+        if target > max {
+            return Err(token.error(format!(
+                "address {target:#x} is out of reach, the most this can use is {max:#x}"
+            )));
+        }
+
+        let [high, low] = (target as u16).to_be_bytes();
+        match kind {
+            ReferenceKind::Nnn => {
+                self.memory[address] |= high;
+                self.memory[address + 1] = low;
+            }
+            ReferenceKind::Long => {
+                self.memory[address] = high;
+                self.memory[address + 1] = low;
+            }
+            ReferenceKind::Unpack => {
+                self.memory[address + 1] |= high;
+                self.memory[address + 3] = low;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn emit_opcode(&mut self, opcode: u16, token: &Token) -> Result<(), OctoError> {
+        self.emit(&opcode.to_be_bytes(), token)
+    }
+
+    /// Puts a jump to main at 0x200 before anything else is, unless main is there already.
+    fn place_main_jump(&mut self) -> Result<(), OctoError> {
+        if !self.main_jump_pending {
+            return Ok(());
+        }
+
+        self.main_jump_pending = false;
+        let main = Token {
+            text: String::from("main"),
+            line: 1,
+            column: 1,
+        };
+
+        self.emit_address_opcode(0x1000, &main)
+    }
+
+    fn emit(&mut self, bytes: &[u8], token: &Token) -> Result<(), OctoError> {
+        self.place_main_jump()?;
+        if self.here + bytes.len() > MEMORY_SIZE {
+            return Err(token.error("the program doesn't fit in memory"));
+        }
+
+        self.memory[self.here..self.here + bytes.len()].copy_from_slice(bytes);
+        self.here += bytes.len();
+        self.end = self.end.max(self.here);
+
+        Ok(())
+    }
+}
+
+/// Names start with a letter or underscore and only have letters, digits, underscores and
+/// dashes after that.
+fn is_name(name: &str) -> bool {
+    let mut characters = name.chars();
+
+    characters
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && characters.all(|character| {
+            character.is_ascii_alphanumeric() || character == '_' || character == '-'
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chip8Settings, VirtualMachine};
+
+    /// Compiles and runs a program for a while, then returns its registers.
+    fn run(source: &str) -> [u8; 16] {
+        let mut vm = VirtualMachine::new(&Chip8Settings::default());
+        vm.load_rom(&compile(source).unwrap()).unwrap();
+        for _ in 0..500 {
+            if vm.step().unwrap().is_none() {
+                vm.tick_timers();
+            }
+        }
+
+        vm.registers()
+    }
+
+    #[test]
+    fn compiles_instructions() {
+        let source = "
+            : main
+                clear
+                v3 := 0x1F  v0 += -1  v1 -= 2  v2 =- v1  v4 := random 0b111
+                i := sprite  sprite v0 v1 5  i := long sprite
+                save v2  load v1 - v3  bcd v2  delay := v0  v5 := key
+                if v0 == 3 then v1 := 4
+                loop again
+            : sprite 0xF0 0x90 ; # a return, then a byte that happens to look like one
+        ";
+
+        assert_eq!(
+            compile(source).unwrap(),
+            [
+                0x00, 0xe0, 0x63, 0x1f, 0x70, 0xff, 0x71, 0xfe, 0x82, 0x17, 0xc4, 0x07, 0xa2, 0x24,
+                0xd0, 0x15, 0xf0, 0x00, 0x02, 0x24, 0xf2, 0x55, 0x51, 0x33, 0xf2, 0x33, 0xf0, 0x15,
+                0xf5, 0x0a, 0x40, 0x03, 0x61, 0x04, 0x12, 0x22, 0xf0, 0x90, 0x00, 0xee,
+            ]
+        );
+    }
+
+    #[test]
+    fn jumps_to_main_unless_it_comes_first() {
+        assert_eq!(
+            compile(": draw return : main draw").unwrap(),
+            [0x12, 0x04, 0x00, 0xee, 0x22, 0x02]
+        );
+        assert_eq!(
+            compile("does-not-exist").unwrap_err().message,
+            "'main' isn't defined"
+        );
+    }
+
+    #[test]
+    fn constants_calc_aliases_and_macros() {
+        let source = "
+            :const WIDTH 64
+            :calc HALF { WIDTH / 2 }
+            :calc ROUNDED { 2 * 3 + 1 }
+            :alias x v5
+            :macro set-both a b { x := a  v6 := b }
+            : main
+                set-both HALF ROUNDED
+                :byte { HALF - 1 }
+        ";
+
+        // No precedence, so 2 * 3 + 1 is 2 * 4.
+        assert_eq!(compile(source).unwrap(), [0x65, 0x20, 0x66, 0x08, 0x1f]);
+    }
+
+    #[test]
+    fn conditions_match_what_they_compare() {
+        let cases = [
+            (5, "==", 5),
+            (5, "!=", 5),
+            (4, "<", 5),
+            (5, "<", 5),
+            (6, ">", 5),
+            (5, ">", 5),
+            (5, "<=", 5),
+            (6, "<=", 5),
+            (5, ">=", 5),
+            (4, ">=", 5),
+        ];
+
+        for (a, operator, b) in cases {
+            let expected = match operator {
+                "==" => a == b,
+                "!=" => a != b,
+                "<" => a < b,
+                ">" => a > b,
+                "<=" => a <= b,
+                _ => a >= b,
+            };
+            let source = format!(
+                ": main
+                    v0 := {a}  v1 := {b}
+                    if v0 {operator} v1 then v2 := 1
+                    if v0 {operator} {b} begin v3 := 1 else v3 := 2 end
+                    loop again"
+            );
+            let registers = run(&source);
+
+            assert_eq!(registers[2] == 1, expected, "if {a} {operator} {b} then");
+            assert_eq!(
+                registers[3],
+                if expected { 1 } else { 2 },
+                "if {a} {operator} {b} begin"
+            );
+        }
+    }
+
+    #[test]
+    fn while_leaves_the_loop() {
+        let registers = run(": main
+            loop
+                while v0 != 10
+                v0 += 1
+                v1 += 2
+            again
+            v2 := 1
+            loop again");
+
+        assert_eq!(registers[..3], [10, 20, 1]);
+    }
+
+    #[test]
+    fn scroll_instructions_run() {
+        // Every scroll the compiler knows has to be one the VM can execute too.
+        let registers = run(": main
+            scroll-down 2  scroll-up 2  scroll-left  scroll-right
+            v0 := 1
+            loop again");
+
+        assert_eq!(registers[0], 1);
+    }
+
+    #[test]
+    fn errors_report_line_and_column() {
+        let error = compile(": main\n  v0 := 300").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 9));
+        assert_eq!(
+            error.to_string(),
+            "line 2, column 9: 300 isn't between -128 and 255"
+        );
+
+        let error = compile(": main\nloop\n  v0 += 1").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 1));
+
+        let error = compile(": main\n  if v0 == 1 sprite").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 14));
+    }
+}
@@ -0,0 +1,173 @@
+use crate::chip8::MEMORY_SIZE;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Memory of every platform before XO-CHIP.
+const SMALL_MEMORY_SIZE: usize = 0x1000;
+
+/// The platforms (and the interpreters that ran on them) that Chip-8 programs are commonly written
+/// for. Each one resolves the ambiguous operations a little differently, so picking the platform a
+/// program targets sets all the quirks at once.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Platform {
+    /// The original Chip-8 interpreter on the COSMAC VIP.
+    #[default]
+    CosmacVip,
+    /// CHIP-48 on the HP-48 graphing calculators.
+    #[serde(rename = "chip-48")]
+    Chip48,
+    /// SUPER-CHIP 1.1 on the HP-48 graphing calculators.
+    #[serde(rename = "schip-1.1")]
+    Schip11,
+    /// SUPER-CHIP as implemented by modern interpreters such as Octo.
+    SchipModern,
+    /// XO-CHIP as implemented by Octo.
+    XoChip,
+}
+
+impl Platform {
+    /// Every platform, in the order they're listed to users.
+    pub const ALL: [Platform; 5] = [
+        Self::CosmacVip,
+        Self::Chip48,
+        Self::Schip11,
+        Self::SchipModern,
+        Self::XoChip,
+    ];
+
+    /// Bytes of memory the platform has. Only XO-CHIP has more than the COSMAC VIP's 4 KiB.
+    pub fn memory_size(self) -> usize {
+        match self {
+            Self::XoChip => MEMORY_SIZE,
+            _ => SMALL_MEMORY_SIZE,
+        }
+    }
+
+    /// The name used for this platform in settings.toml and on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::CosmacVip => "cosmac-vip",
+            Self::Chip48 => "chip-48",
+            Self::Schip11 => "schip-1.1",
+            Self::SchipModern => "schip-modern",
+            Self::XoChip => "xo-chip",
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|platform| platform.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Self::ALL.iter().map(|platform| platform.name()).collect();
+                format!(
+                    "unknown platform '{name}', expected one of: {}",
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// How every ambiguous operation behaves. Each field is true when the quirk is enabled, see the
+/// operation functions in the chip8 module for exactly what each one changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift Vx in place instead of shifting Vy into Vx.
+    pub shift: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0.
+    pub or_and_xor: bool,
+    /// FX55/FX65 leave I pointing just past the last register stored or loaded.
+    pub mem: bool,
+    /// FX55/FX65 leave I pointing at the last register stored or loaded instead of just past it.
+    /// Only matters when `mem` is on.
+    pub mem_increment_by_x: bool,
+    /// DXYN wraps the starting coordinates of a sprite that starts off screen.
+    pub sprite_wrapping: bool,
+    /// BNNN jumps to XNN + Vx instead of NNN + V0.
+    pub jump_offset: bool,
+    /// DXYN draws the sprite and then pauses execution until the display refreshes (vertical
+    /// blank) at the start of the next frame.
+    pub display_wait: bool,
+    /// DXYN waits for the display to refresh in the high resolution mode too. Only matters when
+    /// `display_wait` is on.
+    pub hires_display_wait: bool,
+    /// DXYN cuts off the parts of a sprite that go past the edges of the screen instead of
+    /// wrapping them around to the opposite side.
+    pub clipping: bool,
+}
+
+impl Platform {
+    /// Returns the quirks that match how programs written for this platform expect to run.
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Self::CosmacVip => Quirks {
+                shift: false,
+                or_and_xor: true,
+                mem: true,
+                mem_increment_by_x: false,
+                sprite_wrapping: true,
+                jump_offset: false,
+                display_wait: true,
+                hires_display_wait: true,
+                clipping: true,
+            },
+            // CHIP-48 got FX55/FX65 wrong and leaves I one short of where the COSMAC VIP does.
+            Self::Chip48 => Quirks {
+                shift: true,
+                or_and_xor: false,
+                mem: true,
+                mem_increment_by_x: true,
+                sprite_wrapping: true,
+                jump_offset: true,
+                display_wait: false,
+                hires_display_wait: true,
+                clipping: true,
+            },
+            // SUPER-CHIP 1.1 leaves I alone and only waits for the display in low resolution.
+            Self::Schip11 => Quirks {
+                shift: true,
+                or_and_xor: false,
+                mem: false,
+                mem_increment_by_x: false,
+                sprite_wrapping: true,
+                jump_offset: true,
+                display_wait: true,
+                hires_display_wait: false,
+                clipping: true,
+            },
+            Self::SchipModern => Quirks {
+                shift: true,
+                or_and_xor: false,
+                mem: false,
+                mem_increment_by_x: false,
+                sprite_wrapping: true,
+                jump_offset: true,
+                display_wait: false,
+                hires_display_wait: true,
+                clipping: true,
+            },
+            Self::XoChip => Quirks {
+                shift: false,
+                or_and_xor: false,
+                mem: true,
+                mem_increment_by_x: false,
+                sprite_wrapping: true,
+                jump_offset: false,
+                display_wait: false,
+                hires_display_wait: true,
+                clipping: false,
+            },
+        }
+    }
+}
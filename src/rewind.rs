@@ -0,0 +1,99 @@
+//! A rewind buffer that lets a frontend play a program backwards, one frame at a time.
+
+use crate::{SaveState, VirtualMachine};
+use std::collections::VecDeque;
+
+/// Thirty seconds of frames at 60 Hz.
+pub const DEFAULT_REWIND_FRAMES: usize = 30 * 60;
+
+/// Ring buffer of save states, one per frame. Once it's full, the oldest frame is dropped to make
+/// room for each new one. Each state is compressed since most of the VM's memory (up to 64 KiB)
+/// is usually empty, which keeps thirty seconds of frames down to a few megabytes.
+pub struct RewindBuffer {
+    frames: VecDeque<Vec<u8>>,
+    capacity: usize,
+}
+
+impl RewindBuffer {
+    /// Creates an empty rewind buffer that holds up to `capacity` frames.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Records the current state of the VM as the newest frame. Call this at the end of each frame.
+    pub fn push(&mut self, vm: &VirtualMachine) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+
+        self.frames
+            .push_back(lz4_flex::compress_prepend_size(&vm.save_state().to_bytes()));
+    }
+
+    /// Restores the newest frame to the VM and removes it from the buffer, so calling this once per
+    /// frame plays the program backwards. Returns false if there's nothing left to rewind to.
+    pub fn rewind(&mut self, vm: &mut VirtualMachine) -> bool {
+        let Some(frame) = self.frames.pop_back() else {
+            return false;
+        };
+
+        // Every frame was compressed from a valid save state by push(), so this can only fail if
+        // the buffer itself was corrupted somehow. The frame is dropped either way.
+        lz4_flex::decompress_size_prepended(&frame)
+            .ok()
+            .and_then(|bytes| SaveState::from_bytes(&bytes).ok())
+            .is_some_and(|state| vm.load_state(&state).is_ok())
+    }
+
+    /// Returns how many frames can currently be rewound.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns true if there are no frames to rewind to.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Forgets every frame, like after loading a different program when going back to them
+    /// wouldn't make sense anymore.
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Chip8Settings;
+
+    #[test]
+    fn rewinds_newest_frames_first_and_drops_oldest_when_full() {
+        // Adds 1 to V0 forever.
+        let mut vm = VirtualMachine::new(&Chip8Settings::default());
+        vm.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut rewind_buffer = RewindBuffer::new(3);
+
+        for _ in 0..5 {
+            vm.step().unwrap();
+            vm.step().unwrap();
+            rewind_buffer.push(&vm);
+        }
+        assert_eq!(rewind_buffer.len(), 3);
+
+        for expected_v0 in [5, 4, 3] {
+            assert!(rewind_buffer.rewind(&mut vm));
+            assert_eq!(vm.registers()[0], expected_v0);
+        }
+
+        assert!(!rewind_buffer.rewind(&mut vm));
+        assert!(rewind_buffer.is_empty());
+    }
+}
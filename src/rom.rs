@@ -0,0 +1,231 @@
+//! Helpers for working with program (ROM) files outside of the VM itself.
+
+use crate::{Platform, chip8::PROGRAM_START, disasm, octo};
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+/// Extensions of program files. Octo source is `.8o`, the rest are the bytes of a program.
+pub const PROGRAM_EXTENSIONS: [&str; 5] = ["ch8", "c8", "sc8", "xo8", "8o"];
+
+/// Extension of the archives programs can be read from without unpacking them first.
+pub const ARCHIVE_EXTENSION: &str = "zip";
+
+/// Reads a program from a file. Octo source (.8o) is compiled, anything else is taken to be the
+/// bytes of a program as-is.
+///
+/// Programs in a zip archive are read through the archive as if it was a folder, like
+/// `packs/games.zip/puzzles/tetris.ch8`.
+pub fn read_program(program_path: &Path) -> anyhow::Result<Vec<u8>> {
+    let data = match split_archive_path(program_path) {
+        Some((archive, name)) => read_from_archive(archive, &name)?,
+        None => fs::read(program_path)?,
+    };
+
+    if has_extension(program_path, "8o") {
+        let source = String::from_utf8(data)?;
+        let program = octo::compile(&source)
+            .map_err(|error| anyhow::Error::msg(format!("{}: {error}", program_path.display())))?;
+
+        Ok(program)
+    } else {
+        Ok(data)
+    }
+}
+
+/// Lists the programs in a zip archive and every folder in it, as paths through the archive that
+/// `read_program()` can read.
+pub fn list_archive(archive_path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut archive = zip::ZipArchive::new(fs::File::open(archive_path)?)?;
+
+    let mut programs = Vec::new();
+    for index in 0..archive.len() {
+        let file = archive.by_index_raw(index)?;
+        // Names that would lead out of the archive, like ones starting with `../`, are skipped.
+        if let Some(name) = file.enclosed_name()
+            && file.is_file()
+            && is_program(&name)
+        {
+            programs.push(archive_path.join(name));
+        }
+    }
+
+    Ok(programs)
+}
+
+/// Whether a file has one of the program extensions, in any case.
+pub fn is_program(path: &Path) -> bool {
+    PROGRAM_EXTENSIONS
+        .iter()
+        .any(|extension| has_extension(path, extension))
+}
+
+/// Whether a file has the archive extension, in any case.
+pub fn is_archive(path: &Path) -> bool {
+    has_extension(path, ARCHIVE_EXTENSION)
+}
+
+/// The platform a program's extension says it was written for: SUPER-CHIP 1.1 for `.sc8`, since
+/// most of those were written for it and depend on its quirks, and XO-CHIP for `.xo8`. None for
+/// the other extensions, which don't say.
+pub fn extension_platform(program_path: &Path) -> Option<Platform> {
+    if has_extension(program_path, "sc8") {
+        Some(Platform::Schip11)
+    } else if has_extension(program_path, "xo8") {
+        Some(Platform::XoChip)
+    } else {
+        None
+    }
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|path_extension| path_extension.eq_ignore_ascii_case(extension))
+}
+
+/// Splits a path that goes through a zip archive into the archive's path and the name of the file
+/// in the archive. None if the path doesn't go through an archive.
+fn split_archive_path(path: &Path) -> Option<(&Path, String)> {
+    let archive = path
+        .ancestors()
+        .skip(1)
+        .find(|ancestor| is_archive(ancestor) && ancestor.is_file())?;
+
+    // Names in zip archives always use forward slashes.
+    let name: Vec<String> = path
+        .strip_prefix(archive)
+        .ok()?
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+
+    Some((archive, name.join("/")))
+}
+
+fn read_from_archive(archive_path: &Path, name: &str) -> anyhow::Result<Vec<u8>> {
+    let mut archive = zip::ZipArchive::new(fs::File::open(archive_path)?)?;
+    let mut file = archive.by_name(name).map_err(|error| {
+        anyhow::Error::msg(format!("{}: {name}: {error}", archive_path.display()))
+    })?;
+
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+
+    Ok(data)
+}
+
+/// Returns the SHA-1 hash of a program as a lowercase hex string. Used to tell programs apart no
+/// matter what their files are called, for things like save states.
+pub fn rom_hash(program_data: &[u8]) -> String {
+    sha1_smol::Sha1::from(program_data).digest().to_string()
+}
+
+/// Guesses the platform a program was written for from the instructions it uses. Programs with
+/// XO-CHIP instructions (like PLANE or LD I, LONG) or that only fit in XO-CHIP's memory are XO-CHIP
+/// programs and programs with SUPER-CHIP instructions (like HIGH or SCD) are SUPER-CHIP programs.
+/// Anything else is taken to be a Chip-8 program for the COSMAC VIP. Only code is looked at, see
+/// `disasm::disassemble()`.
+pub fn detect_platform(program: &[u8]) -> Platform {
+    // Programs are loaded at 0x200, so anything bigger doesn't fit in the other platforms' memory.
+    if program.len() > Platform::CosmacVip.memory_size() - PROGRAM_START {
+        return Platform::XoChip;
+    }
+
+    let mut platform = Platform::CosmacVip;
+
+    for line in disasm::disassemble(program) {
+        if !line.is_code {
+            continue;
+        }
+
+        let opcode = u16::from_be_bytes([line.bytes[0], line.bytes[1]]);
+        match (opcode, opcode & 0xf00f, opcode & 0xf0ff) {
+            // 00DN scrolls up, 5XY2/5XY3 save and load register ranges, F000 loads a long
+            // address, FN01 picks bitplanes, F002 loads an audio pattern and FX3A sets the pitch.
+            (0x00d0..=0x00df | 0xf000 | 0xf002, _, _)
+            | (_, 0x5002 | 0x5003, _)
+            | (_, _, 0xf001 | 0xf03a) => return Platform::XoChip,
+            // 00CN/00FB/00FC scroll, 00FD exits, 00FE/00FF switch resolution, DXY0 draws a 16x16
+            // sprite and FX30/FX75/FX85 use the big font and the RPL flags.
+            (0x00c0..=0x00cf | 0x00fb..=0x00ff, _, _)
+            | (_, 0xd000, _)
+            | (_, _, 0xf030 | 0xf075 | 0xf085) => platform = Platform::SchipModern,
+            _ => (),
+        }
+    }
+
+    platform
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_platforms_from_instructions() {
+        // CLS; JP 0x202
+        assert_eq!(
+            detect_platform(&[0x00, 0xe0, 0x12, 0x02]),
+            Platform::CosmacVip
+        );
+        // HIGH; JP 0x202
+        assert_eq!(
+            detect_platform(&[0x00, 0xff, 0x12, 0x02]),
+            Platform::SchipModern
+        );
+        // HIGH; PLANE 3; JP 0x204
+        assert_eq!(
+            detect_platform(&[0x00, 0xff, 0xf3, 0x01, 0x12, 0x04]),
+            Platform::XoChip
+        );
+        // SCU 2; JP 0x202
+        assert_eq!(detect_platform(&[0x00, 0xd2, 0x12, 0x02]), Platform::XoChip);
+        // LD I, LONG 0x0300; JP 0x204
+        assert_eq!(
+            detect_platform(&[0xf0, 0x00, 0x03, 0x00, 0x12, 0x04]),
+            Platform::XoChip
+        );
+    }
+
+    #[test]
+    fn ignores_data_and_looks_at_size() {
+        // JP 0x204, then data that looks like HIGH.
+        assert_eq!(
+            detect_platform(&[0x12, 0x04, 0x00, 0xff]),
+            Platform::CosmacVip
+        );
+        assert_eq!(detect_platform(&vec![0; 0x1000]), Platform::XoChip);
+    }
+
+    #[test]
+    fn reads_programs_inside_archives() {
+        use std::io::Write;
+        use zip::write::SimpleFileOptions;
+
+        let archive_path =
+            std::env::temp_dir().join(format!("chip-8-rom-test-{}.zip", std::process::id()));
+        let mut archive = zip::ZipWriter::new(fs::File::create(&archive_path).unwrap());
+        archive
+            .start_file("games/pong.sc8", SimpleFileOptions::default())
+            .unwrap();
+        archive.write_all(&[0x00, 0xff, 0x12, 0x02]).unwrap();
+        archive
+            .start_file("readme.txt", SimpleFileOptions::default())
+            .unwrap();
+        archive.write_all(b"Not a program").unwrap();
+        archive.finish().unwrap();
+
+        let pong = archive_path.join("games").join("pong.sc8");
+        let listed = list_archive(&archive_path);
+        let program = read_program(&pong);
+        let missing = read_program(&archive_path.join("missing.ch8"));
+        fs::remove_file(&archive_path).unwrap();
+
+        assert_eq!(listed.unwrap(), vec![pong.clone()]);
+        assert_eq!(program.unwrap(), vec![0x00, 0xff, 0x12, 0x02]);
+        assert_eq!(extension_platform(&pong), Some(Platform::Schip11));
+        assert!(missing.is_err());
+    }
+}
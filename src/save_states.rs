@@ -0,0 +1,58 @@
+use chip_8_interpreter::{Chip8Settings, SaveState, VirtualMachine};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Numbered save state slots for a single program. Each program gets its own folder of slots,
+/// named after the program's hash so renaming or moving the file doesn't lose its save states.
+/// The folders live in a states folder next to the programs folder from settings.toml, along with
+/// the program's RPL user flags.
+pub(crate) struct SaveSlots {
+    folder: PathBuf,
+}
+
+impl SaveSlots {
+    pub fn new(settings: &Chip8Settings, rom_hash: &str) -> Self {
+        let folder = Path::new(&settings.program_folder_path)
+            .with_file_name("states")
+            .join(rom_hash);
+
+        Self { folder }
+    }
+
+    /// Saves a snapshot of the Chip-8 VM to a slot, overwriting whatever was saved there before.
+    pub fn save(&self, vm: &VirtualMachine, slot: usize) -> anyhow::Result<()> {
+        fs::create_dir_all(&self.folder)?;
+        fs::write(self.slot_path(slot), vm.save_state().to_bytes())?;
+
+        Ok(())
+    }
+
+    /// Restores the snapshot saved in a slot to the Chip-8 VM.
+    pub fn load(&self, vm: &mut VirtualMachine, slot: usize) -> anyhow::Result<()> {
+        let state = SaveState::from_bytes(&fs::read(self.slot_path(slot))?)?;
+        vm.load_state(&state)?;
+
+        Ok(())
+    }
+
+    /// Saves the program's SUPER-CHIP RPL user flags, which it keeps between runs.
+    pub fn save_rpl_flags(&self, vm: &VirtualMachine) -> anyhow::Result<()> {
+        fs::create_dir_all(&self.folder)?;
+        vm.write_rpl_flags_file(&self.rpl_flags_path())
+    }
+
+    /// Restores the RPL user flags the program saved the last time it ran, if it ever did.
+    pub fn load_rpl_flags(&self, vm: &mut VirtualMachine) -> anyhow::Result<()> {
+        vm.read_rpl_flags_file(&self.rpl_flags_path())
+    }
+
+    fn rpl_flags_path(&self) -> PathBuf {
+        self.folder.join("rpl-flags")
+    }
+
+    fn slot_path(&self, slot: usize) -> PathBuf {
+        self.folder.join(format!("slot-{slot}.state"))
+    }
+}
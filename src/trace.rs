@@ -0,0 +1,118 @@
+//! Execution traces with one line per executed instruction, for comparing what a program does
+//! here with what it does on other interpreters. Each line has the cycle (instructions executed
+//! since the VM was reset), the PC, the opcode, its disassembly, VF after the instruction and then
+//! every other register and I that the instruction changed:
+//!
+//! ```text
+//!       12  0x0218  8014  ADD V0, V1                VF=01  V0=2c
+//!       13  0x021a  a300  LD I, 0x300               VF=01  I=0300
+//! ```
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    ops::Range,
+    path::Path,
+};
+
+/// Writes trace lines for the VM it's given to with `VirtualMachine::set_tracer()`.
+pub struct Tracer {
+    output: Box<dyn Write + Send>,
+    cycles: Range<u64>,
+    recording: bool,
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    /// Creates a tracer that records every instruction to `output`.
+    pub fn new(output: impl Write + Send + 'static) -> Self {
+        Self {
+            output: Box::new(output),
+            cycles: 0..u64::MAX,
+            recording: true,
+            error: None,
+        }
+    }
+
+    /// Creates a tracer that records every instruction to a new file, replacing any file that's
+    /// already there.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+
+    /// Only records the instructions executed in a range of cycles.
+    pub fn with_cycles(mut self, cycles: Range<u64>) -> Self {
+        self.cycles = cycles;
+        self
+    }
+
+    /// Returns true if recording isn't paused.
+    pub fn recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Pauses or resumes recording, like with a hotkey. Cycles outside the range from
+    /// `with_cycles()` are never recorded either way.
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+    }
+
+    /// Returns the error that stopped the trace if writing to the output failed. Nothing else is
+    /// recorded after that.
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    /// Writes everything recorded so far that's still buffered to the output.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+
+    /// Whether the instruction about to be executed at `cycle` should be recorded.
+    pub(crate) fn wants(&self, cycle: u64) -> bool {
+        self.recording && self.error.is_none() && self.cycles.contains(&cycle)
+    }
+
+    /// Records an executed instruction, given the registers and I from before and after it.
+    pub(crate) fn record(&mut self, entry: &TraceEntry) {
+        if let Err(error) = writeln!(self.output, "{}", entry.line()) {
+            self.error = Some(error);
+        }
+    }
+}
+
+/// Everything about an executed instruction that goes into its trace line.
+pub(crate) struct TraceEntry {
+    pub cycle: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub mnemonic: String,
+    pub v_before: [u8; 16],
+    pub v_after: [u8; 16],
+    pub i_before: u16,
+    pub i_after: u16,
+}
+
+impl TraceEntry {
+    fn line(&self) -> String {
+        let mut changes: Vec<String> = (0..0xf)
+            .filter(|x| self.v_before[*x] != self.v_after[*x])
+            .map(|x| format!("V{x:X}={:02x}", self.v_after[x]))
+            .collect();
+        if self.i_before != self.i_after {
+            changes.push(format!("I={:04x}", self.i_after));
+        }
+
+        format!(
+            "{:>8}  {:#06x}  {:04x}  {:<24}  VF={:02x}  {}",
+            self.cycle,
+            self.pc,
+            self.opcode,
+            self.mnemonic,
+            self.v_after[0xf],
+            changes.join(" ")
+        )
+        .trim_end()
+        .to_string()
+    }
+}
@@ -0,0 +1,33 @@
+//! Property tests that feed arbitrary programs into the VM and the disassembler, the offline
+//! counterpart to the cargo-fuzz targets in fuzz/. They check that nothing panics and that the VM
+//! stays in a valid state, see tests/fuzzing.
+
+mod fuzzing;
+
+use proptest::prelude::*;
+
+proptest! {
+    #[test]
+    fn arbitrary_programs_dont_break_the_vm(data in prop::collection::vec(any::<u8>(), 0..1024)) {
+        fuzzing::run_arbitrary_program(&data);
+    }
+
+    /// Programs made of nothing but instructions, which get further than random bytes usually do.
+    #[test]
+    fn arbitrary_instructions_dont_break_the_vm(
+        platform in any::<u8>(),
+        key in any::<u8>(),
+        opcodes in prop::collection::vec(any::<u16>(), 0..256),
+    ) {
+        let mut data = vec![platform, key];
+        data.extend(opcodes.iter().flat_map(|opcode| opcode.to_be_bytes()));
+        fuzzing::run_arbitrary_program(&data);
+    }
+
+    #[test]
+    fn arbitrary_programs_disassemble_and_assemble_back(
+        data in prop::collection::vec(any::<u8>(), 0..512),
+    ) {
+        fuzzing::decode_arbitrary_program(&data);
+    }
+}
@@ -0,0 +1,81 @@
+//! What the fuzz targets in fuzz/ and the property tests in tests/fuzz.rs feed arbitrary bytes
+//! into. Kept here so both run exactly the same checks.
+
+// Each fuzz target only uses one of these.
+#![allow(dead_code)]
+
+use chip_8_interpreter::{Chip8Settings, Platform, VirtualMachine, asm, disasm};
+
+/// Most step() calls to make per program, including the ones that just end a frame.
+const MAX_STEPS: usize = 2000;
+
+/// The resolutions the frame buffer is allowed to have.
+const RESOLUTIONS: [(usize, usize); 2] = [(64, 32), (128, 64)];
+
+/// Runs arbitrary bytes as a program. The first byte picks the platform, the second one the key
+/// held down (if its high nibble is set) and the rest is the program. Panics if the VM panics or
+/// ends up in a state it should never be in, no matter how broken the program is.
+pub fn run_arbitrary_program(data: &[u8]) {
+    let [platform, key, program @ ..] = data else {
+        return;
+    };
+
+    let settings = Chip8Settings {
+        platform: Platform::ALL[*platform as usize % Platform::ALL.len()],
+        rng_seed: Some(0),
+        ..Chip8Settings::default()
+    };
+    let mut vm = VirtualMachine::new(&settings);
+    if vm.load_rom(program).is_err() {
+        return;
+    }
+    vm.set_key((key & 0xf) as usize, key & 0xf0 != 0);
+
+    for _ in 0..MAX_STEPS {
+        // Skips and returns can take the PC past the end of the 4 KiB platforms' memory, but then
+        // the VM has to fault instead of executing anything.
+        let pc = vm.program_counter();
+        let pc_outside_memory = pc as usize + 1 >= vm.memory().len();
+        let cycles = vm.cycles();
+
+        match vm.step() {
+            Ok(Some(_)) => {}
+            Ok(None) => vm.tick_timers(),
+            Err(_) => break,
+        }
+        check_invariants(&vm);
+        assert!(
+            !pc_outside_memory || vm.cycles() == cycles,
+            "executed an instruction at PC {pc:#06x} outside memory"
+        );
+
+        if vm.exited() {
+            break;
+        }
+    }
+}
+
+/// Disassembles arbitrary bytes as a program and assembles the disassembler's source back, which
+/// has to give back the same bytes.
+pub fn decode_arbitrary_program(data: &[u8]) {
+    disasm::listing(data);
+
+    let source = disasm::source(data);
+    let assembled = asm::assemble(&source)
+        .unwrap_or_else(|error| panic!("the disassembly doesn't assemble: {error}\n{source}"));
+    assert_eq!(
+        assembled, data,
+        "the disassembly assembles differently:\n{source}"
+    );
+}
+
+fn check_invariants(vm: &VirtualMachine) {
+    assert!(vm.stack().len() <= 16, "stack depth {}", vm.stack().len());
+
+    let (width, height) = vm.resolution();
+    assert!(
+        RESOLUTIONS.contains(&(width, height)),
+        "resolution {width}x{height}"
+    );
+    assert_eq!(vm.framebuffer().len(), width * height, "frame buffer size");
+}
@@ -0,0 +1,99 @@
+//! Golden image regression tests. Each case runs a program headlessly for a number of frames with
+//! a given platform and compares the final frame buffer to a text image stored in tests/golden.
+//!
+//! Run with `UPDATE_GOLDEN=1` to write the current frame buffers as the new golden images.
+
+use chip_8_interpreter::{Chip8Settings, Platform, VirtualMachine, asm};
+use std::{env, fs, path::PathBuf};
+
+struct GoldenCase {
+    /// Name of the golden image file in tests/golden, without the extension.
+    name: &'static str,
+    platform: Platform,
+    frames: u32,
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.txt"))
+}
+
+/// Runs the program for the case's frames and returns the final frame buffer as text.
+fn run(rom: &[u8], case: &GoldenCase) -> String {
+    let settings = Chip8Settings {
+        platform: case.platform,
+        rng_seed: Some(0),
+        ..Chip8Settings::default()
+    };
+
+    let mut vm = VirtualMachine::new(&settings);
+    vm.load_rom(rom).unwrap();
+
+    let mut frames = 0;
+    while frames < case.frames && !vm.exited() {
+        match vm.step() {
+            Ok(Some(_)) => (),
+            Ok(None) => {
+                vm.tick_timers();
+                frames += 1;
+            }
+            Err(error) => panic!("{} faulted: {error}", case.name),
+        }
+    }
+
+    vm.framebuffer_text()
+}
+
+/// Compares the program's final frame buffer to the case's golden image, or overwrites the
+/// golden image if `UPDATE_GOLDEN` is set.
+fn check(rom: &[u8], case: &GoldenCase) {
+    let framebuffer_text = format!("{}\n", run(rom, case));
+    let golden_path = golden_path(case.name);
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(golden_path.parent().unwrap()).unwrap();
+        fs::write(&golden_path, framebuffer_text).unwrap();
+        return;
+    }
+
+    let golden = fs::read_to_string(&golden_path).unwrap_or_else(|_| {
+        panic!(
+            "{} has no golden image, run with UPDATE_GOLDEN=1 to create it and check that it looks \
+             right before committing it.",
+            case.name
+        )
+    });
+
+    assert!(
+        framebuffer_text == golden,
+        "{} doesn't match its golden image.\n\nExpected:\n{golden}\nGot:\n{framebuffer_text}",
+        case.name
+    );
+}
+
+/// Assembles a program from the sources kept in tests/roms.
+fn assemble(file_name: &str) -> Vec<u8> {
+    let source_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/roms")
+        .join(file_name);
+
+    asm::assemble_file(&source_path).unwrap_or_else(|error| panic!("{error}"))
+}
+
+#[test]
+fn font() {
+    for (name, platform) in [
+        ("font-cosmac-vip", Platform::CosmacVip),
+        ("font-xo-chip", Platform::XoChip),
+    ] {
+        check(
+            &assemble("font.asm"),
+            &GoldenCase {
+                name,
+                platform,
+                frames: 30,
+            },
+        );
+    }
+}
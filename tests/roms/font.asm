@@ -0,0 +1,21 @@
+; Draws every character of the small font in two rows of eight, then loops forever.
+
+ROW_WIDTH EQU 64
+CHARACTERS EQU 16
+
+        LD V0, 0            ; character
+        LD V1, 0            ; x
+        LD V2, 0            ; y
+
+draw:   LD F, V0
+        DRW V1, V2, 5
+        ADD V0, 1
+        ADD V1, 8
+        SE V1, ROW_WIDTH
+        JP next
+        LD V1, 0            ; start the next row
+        ADD V2, 8
+next:   SE V0, CHARACTERS
+        JP draw
+
+end:    JP end